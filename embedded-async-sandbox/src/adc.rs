@@ -0,0 +1,199 @@
+use core::future::Future;
+use embedded_hal::adc::Channel;
+
+/// Single conversion on request
+pub trait AsyncOneShot<ADC, Word, Pin: Channel<ADC>> {
+    /// Conversion error
+    type Error;
+    /// Read future for polling on completion
+    type ReadFuture<'t>: Future<Output=Result<Word, Self::Error>>;
+
+    /// Converts the voltage on `pin`
+    fn async_read<'a>(&'a mut self, pin: &'a mut Pin) -> Self::ReadFuture<'a>;
+}
+
+/// Free-running converter
+///
+/// `embedded-hal` has no trait for continuous conversions, so this is the interface
+/// a converter provides to the default implementation of [`AsyncSample`].
+/// Once started, the converter produces a sample every conversion period. A sample that
+/// is not read before the next one is ready is lost, which is reported as an error.
+///
+/// [`AsyncSample`]: trait.AsyncSample.html
+pub trait Continuous<Word> {
+    /// Conversion error, such as an overrun
+    type Error;
+
+    /// Starts converting
+    fn start(&mut self);
+
+    /// Returns the oldest sample not read yet
+    fn read(&mut self) -> nb::Result<Word, Self::Error>;
+
+    /// Stops converting, discarding samples not read yet
+    fn stop(&mut self);
+}
+
+/// Continuous sampling into a buffer
+pub trait AsyncSample<Word> {
+    /// Sampling error
+    type Error;
+    /// Sample future for polling on completion
+    type SampleFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Fills `buffer` with consecutive samples, one per conversion period
+    fn async_sample<'a>(&'a mut self, buffer: &'a mut [Word]) -> Self::SampleFuture<'a>;
+}
+
+pub mod oneshot {
+    use super::AsyncOneShot;
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::marker::PhantomData;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+    use embedded_hal::adc::{Channel, OneShot};
+
+    /// Marker trait to opt into default async one-shot implementation
+    ///
+    /// Implementers of `embedded-hal::adc::OneShot` can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`adc::AsyncOneShot`] for the type.
+    /// Futures wait for [`Event::RxNotEmpty`] until the conversion result is available.
+    ///
+    /// [`adc::AsyncOneShot`]: ../trait.AsyncOneShot.html
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default<ADC, Word, P: Channel<ADC>>: OneShot<ADC, Word, P> + RegisterWaker {}
+
+    impl<A, ADC, Word, P> AsyncOneShot<ADC, Word, P> for A
+    where
+        A: Default<ADC, Word, P> + 'static,
+        ADC: 'static,
+        Word: 'static,
+        P: Channel<ADC> + 'static,
+    {
+        type Error = A::Error;
+        type ReadFuture<'t> = DefaultReadFuture<'t, A, ADC, Word, P>;
+
+        fn async_read<'a>(&'a mut self, pin: &'a mut P) -> Self::ReadFuture<'a> {
+            DefaultReadFuture {
+                adc: self,
+                pin,
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    pub struct DefaultReadFuture<'a, A, ADC, Word, P> {
+        adc: &'a mut A,
+        pin: &'a mut P,
+        _marker: PhantomData<fn() -> (ADC, Word)>,
+    }
+
+    impl<'a, A, ADC, Word, P> Future for DefaultReadFuture<'a, A, ADC, Word, P>
+    where
+        A: Default<ADC, Word, P>,
+        P: Channel<ADC>,
+    {
+        type Output = Result<Word, A::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            match this.adc.read(this.pin) {
+                Ok(word) => Poll::Ready(Ok(word)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    this.adc.register_waker(Event::RxNotEmpty, cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+pub mod continuous {
+    use super::{AsyncSample, Continuous};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async sampling implementation
+    ///
+    /// Implementers of [`adc::Continuous`] can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`adc::AsyncSample`] for the type.
+    /// The converter is started when the future is first polled and stopped once the buffer
+    /// is full, the sampling fails or the future is dropped.
+    /// Futures wait for [`Event::RxNotEmpty`] until the next sample is available.
+    ///
+    /// [`adc::Continuous`]: ../trait.Continuous.html
+    /// [`adc::AsyncSample`]: ../trait.AsyncSample.html
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default<Word>: Continuous<Word> + RegisterWaker {}
+
+    impl<A: Default<Word> + 'static, Word: 'static> AsyncSample<Word> for A {
+        type Error = A::Error;
+        type SampleFuture<'t> = DefaultSampleFuture<'t, A, Word>;
+
+        fn async_sample<'a>(&'a mut self, buffer: &'a mut [Word]) -> Self::SampleFuture<'a> {
+            DefaultSampleFuture {
+                adc: self,
+                buffer,
+                offset: 0,
+                running: false,
+            }
+        }
+    }
+
+    pub struct DefaultSampleFuture<'a, A: Default<Word>, Word> {
+        adc: &'a mut A,
+        buffer: &'a mut [Word],
+        offset: usize,
+        running: bool,
+    }
+
+    impl<'a, A: Default<Word>, Word> Future for DefaultSampleFuture<'a, A, Word> {
+        type Output = Result<(), A::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            if !this.running && this.offset < this.buffer.len() {
+                this.adc.start();
+                this.running = true;
+            }
+
+            while this.offset < this.buffer.len() {
+                match this.adc.read() {
+                    Ok(word) => {
+                        this.buffer[this.offset] = word;
+                        this.offset += 1;
+                    },
+                    Err(nb::Error::Other(e)) => {
+                        this.adc.stop();
+                        this.running = false;
+                        return Poll::Ready(Err(e));
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        this.adc.register_waker(Event::RxNotEmpty, cx.waker());
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            if this.running {
+                this.adc.stop();
+                this.running = false;
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl<'a, A: Default<Word>, Word> Drop for DefaultSampleFuture<'a, A, Word> {
+        fn drop(&mut self) {
+            if self.running {
+                self.adc.stop();
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use core::future::Future;
+
+mod frame;
+
+pub use self::frame::{ExtendedId, Filter, Frame, Id, StandardId};
+
+/// CAN frame transmission
+pub trait AsyncTransmit {
+    /// Transmit error
+    type Error;
+    /// Transmit future for polling on completion
+    type TransmitFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Queues `frame` for transmission
+    /// When the future completes, the frame may still be waiting for the bus.
+    fn async_transmit(&mut self, frame: Frame) -> Self::TransmitFuture<'_>;
+}
+
+/// CAN frame reception
+pub trait AsyncReceive {
+    /// Receive error
+    type Error;
+    /// Receive future for polling on completion
+    type ReceiveFuture<'t>: Future<Output=Result<Frame, Self::Error>>;
+
+    /// Receives the oldest frame that passed the acceptance filters
+    fn async_receive(&mut self) -> Self::ReceiveFuture<'_>;
+}
+
+/// Acceptance filter configuration
+pub trait AcceptanceFilter {
+    /// Configuration error, such as more filters than the controller has
+    type Error;
+
+    /// Replaces the filters, frames passing none of them are not received
+    fn set_filters(&mut self, filters: &[Filter]) -> Result<(), Self::Error>;
+}
+
+/// Fault confinement state of a CAN controller
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorState {
+    /// Both error counters are at most 127, errors are signalled with active error flags
+    Active,
+    /// An error counter is above 127, errors are only signalled with passive error flags
+    Passive,
+    /// The transmit error counter went above 255, the controller no longer takes part in bus traffic
+    BusOff,
+}
+
+/// Non-blocking CAN controller
+///
+/// `embedded-hal` has no CAN traits in the version this crate builds against,
+/// so this is the interface a controller provides to the default implementations.
+pub trait Can {
+    /// Controller error, such as a receive overrun or being bus-off
+    type Error;
+
+    /// Puts `frame` into a free transmit mailbox
+    ///
+    /// Returns `WouldBlock` while all mailboxes are waiting for the bus.
+    fn transmit(&mut self, frame: &Frame) -> nb::Result<(), Self::Error>;
+
+    /// Returns the oldest received frame
+    fn receive(&mut self) -> nb::Result<Frame, Self::Error>;
+
+    /// Returns the current fault confinement state
+    fn error_state(&self) -> ErrorState;
+}
+
+pub mod transmit {
+    use super::{AsyncTransmit, Can, Frame};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async transmit implementation
+    ///
+    /// Implementers of [`can::Can`] can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`can::AsyncTransmit`] for the type.
+    /// Futures wait for [`Event::TxSpace`] while all transmit mailboxes are in use.
+    ///
+    /// [`can::Can`]: ../trait.Can.html
+    /// [`can::AsyncTransmit`]: ../trait.AsyncTransmit.html
+    /// [`Event::TxSpace`]: ../../waker/enum.Event.html#variant.TxSpace
+    pub trait Default: Can + RegisterWaker {}
+
+    impl<C: Default + 'static> AsyncTransmit for C {
+        type Error = C::Error;
+        type TransmitFuture<'t> = DefaultTransmitFuture<'t, C>;
+
+        fn async_transmit(&mut self, frame: Frame) -> Self::TransmitFuture<'_> {
+            DefaultTransmitFuture {
+                can: self,
+                frame,
+            }
+        }
+    }
+
+    pub struct DefaultTransmitFuture<'a, C> {
+        can: &'a mut C,
+        frame: Frame,
+    }
+
+    impl<'a, C: Default> Future for DefaultTransmitFuture<'a, C> {
+        type Output = Result<(), C::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            match this.can.transmit(&this.frame) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    this.can.register_waker(Event::TxSpace, cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+pub mod receive {
+    use super::{AsyncReceive, Can, Frame};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async receive implementation
+    ///
+    /// Implementers of [`can::Can`] can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`can::AsyncReceive`] for the type.
+    /// Futures wait for [`Event::RxNotEmpty`] until a frame is received.
+    ///
+    /// [`can::Can`]: ../trait.Can.html
+    /// [`can::AsyncReceive`]: ../trait.AsyncReceive.html
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default: Can + RegisterWaker {}
+
+    impl<C: Default + 'static> AsyncReceive for C {
+        type Error = C::Error;
+        type ReceiveFuture<'t> = DefaultReceiveFuture<'t, C>;
+
+        fn async_receive(&mut self) -> Self::ReceiveFuture<'_> {
+            DefaultReceiveFuture {
+                can: self,
+            }
+        }
+    }
+
+    pub struct DefaultReceiveFuture<'a, C> {
+        can: &'a mut C,
+    }
+
+    impl<'a, C: Default> Future for DefaultReceiveFuture<'a, C> {
+        type Output = Result<Frame, C::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            match self.can.receive() {
+                Ok(frame) => Poll::Ready(Ok(frame)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    self.can.register_waker(Event::RxNotEmpty, cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
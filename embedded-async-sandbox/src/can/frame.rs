@@ -0,0 +1,168 @@
+/// Standard 11-bit CAN identifier
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct StandardId(u16);
+
+impl StandardId {
+    /// Highest standard identifier, with the lowest priority
+    pub const MAX: StandardId = StandardId(0x7ff);
+
+    /// Returns `None` if `raw` does not fit into 11 bits
+    pub fn new(raw: u16) -> Option<Self> {
+        if raw <= 0x7ff {
+            Some(StandardId(raw))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_raw(&self) -> u16 {
+        self.0
+    }
+}
+
+/// Extended 29-bit CAN identifier
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExtendedId(u32);
+
+impl ExtendedId {
+    /// Highest extended identifier, with the lowest priority
+    pub const MAX: ExtendedId = ExtendedId(0x1fff_ffff);
+
+    /// Returns `None` if `raw` does not fit into 29 bits
+    pub fn new(raw: u32) -> Option<Self> {
+        if raw <= 0x1fff_ffff {
+            Some(ExtendedId(raw))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_raw(&self) -> u32 {
+        self.0
+    }
+
+    /// Returns the 11 most significant bits, sent in place of a standard identifier
+    pub fn standard_id(&self) -> StandardId {
+        StandardId((self.0 >> 18) as u16)
+    }
+}
+
+/// CAN identifier
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Id {
+    Standard(StandardId),
+    Extended(ExtendedId),
+}
+
+impl From<StandardId> for Id {
+    fn from(id: StandardId) -> Self {
+        Id::Standard(id)
+    }
+}
+
+impl From<ExtendedId> for Id {
+    fn from(id: ExtendedId) -> Self {
+        Id::Extended(id)
+    }
+}
+
+/// CAN 2.0 frame
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    id: Id,
+    remote: bool,
+    dlc: u8,
+    data: [u8; 8],
+}
+
+impl Frame {
+    /// Creates a data frame, returns `None` if `data` is longer than 8 bytes
+    pub fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        let mut frame = Frame {
+            id: id.into(),
+            remote: false,
+            dlc: data.len() as u8,
+            data: [0; 8],
+        };
+        frame.data[..data.len()].copy_from_slice(data);
+        Some(frame)
+    }
+
+    /// Creates a remote frame requesting `dlc` bytes, returns `None` if `dlc` is above 8
+    pub fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        Some(Frame {
+            id: id.into(),
+            remote: true,
+            dlc: dlc as u8,
+            data: [0; 8],
+        })
+    }
+
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    pub fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    pub fn is_remote_frame(&self) -> bool {
+        self.remote
+    }
+
+    /// Returns the data length code, which matches the length of the data for data frames
+    pub fn dlc(&self) -> usize {
+        self.dlc as usize
+    }
+
+    /// Returns the data of the frame, which is empty for remote frames
+    pub fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.dlc as usize]
+        }
+    }
+}
+
+/// Acceptance filter
+///
+/// A frame passes the filter if its identifier is of the same kind as the filter's
+/// and matches it in every bit set in the mask.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Filter {
+    id: Id,
+    mask: u32,
+}
+
+impl Filter {
+    pub fn new(id: impl Into<Id>, mask: u32) -> Self {
+        Self {
+            id: id.into(),
+            mask,
+        }
+    }
+
+    /// Creates a filter passing only the frames with identifier `id`
+    pub fn exact(id: impl Into<Id>) -> Self {
+        Self::new(id, !0)
+    }
+
+    pub fn matches(&self, id: Id) -> bool {
+        match (self.id, id) {
+            (Id::Standard(filter), Id::Standard(id)) => {
+                (filter.as_raw() ^ id.as_raw()) as u32 & self.mask == 0
+            },
+            (Id::Extended(filter), Id::Extended(id)) => {
+                (filter.as_raw() ^ id.as_raw()) & self.mask == 0
+            },
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use core::future::Future;
+
+/// Timer value whose differences stay correct when the counter wraps around
+pub trait WrappingSub: Copy {
+    /// Returns `self - earlier`, modulo the range of the type
+    fn wrapping_sub(self, earlier: Self) -> Self;
+}
+
+macro_rules! impl_wrapping_sub {
+    ($($t:ty),*) => {
+        $(
+            impl WrappingSub for $t {
+                fn wrapping_sub(self, earlier: Self) -> Self {
+                    <$t>::wrapping_sub(self, earlier)
+                }
+            }
+        )*
+    };
+}
+
+impl_wrapping_sub!(u8, u16, u32, u64, usize);
+
+/// Input capture
+pub trait AsyncCapture {
+    /// Capture error
+    type Error;
+    /// Capture channel
+    type Channel;
+    /// Timer value latched by an edge
+    type Capture;
+    /// Capture future for polling on completion
+    type CaptureFuture<'t>: Future<Output=Result<Self::Capture, Self::Error>>;
+    /// Period future for polling on completion
+    type PeriodFuture<'t>: Future<Output=Result<Self::Capture, Self::Error>>;
+
+    /// Waits for the next edge on `channel`, returning its timestamp
+    fn async_capture(&mut self, channel: Self::Channel) -> Self::CaptureFuture<'_>;
+
+    /// Waits for the next two edges on `channel`, returning the time between them
+    fn async_period(&mut self, channel: Self::Channel) -> Self::PeriodFuture<'_>;
+}
+
+pub mod input {
+    use super::{AsyncCapture, WrappingSub};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+    use embedded_hal::Capture;
+
+    /// Marker trait to opt into default async capture implementation
+    ///
+    /// Implementers of `embedded-hal::Capture` can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`capture::AsyncCapture`] for the type.
+    /// Futures wait for [`Event::RxNotEmpty`] until an edge has been captured.
+    ///
+    /// Periods are computed by subtracting consecutive captures with [`WrappingSub`],
+    /// so they stay correct when the counter overflows between the edges, as long as it
+    /// counts through the whole range of the capture type.
+    ///
+    /// [`WrappingSub`]: ../trait.WrappingSub.html
+    ///
+    /// [`capture::AsyncCapture`]: ../trait.AsyncCapture.html
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default: Capture + RegisterWaker {}
+
+    impl<C> AsyncCapture for C
+    where
+        C: Default + 'static,
+        C::Channel: Copy,
+        C::Capture: WrappingSub,
+    {
+        type Error = C::Error;
+        type Channel = C::Channel;
+        type Capture = C::Capture;
+        type CaptureFuture<'t> = DefaultCaptureFuture<'t, C>;
+        type PeriodFuture<'t> = DefaultPeriodFuture<'t, C>;
+
+        fn async_capture(&mut self, channel: Self::Channel) -> Self::CaptureFuture<'_> {
+            DefaultCaptureFuture {
+                timer: self,
+                channel,
+            }
+        }
+
+        fn async_period(&mut self, channel: Self::Channel) -> Self::PeriodFuture<'_> {
+            DefaultPeriodFuture {
+                timer: self,
+                channel,
+                first: None,
+            }
+        }
+    }
+
+    fn poll_capture<C>(timer: &mut C, channel: C::Channel, cx: &mut Context<'_>) -> Poll<Result<C::Capture, C::Error>>
+    where
+        C: Default,
+        C::Channel: Copy,
+    {
+        match timer.capture(channel) {
+            Ok(capture) => Poll::Ready(Ok(capture)),
+            Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+            Err(nb::Error::WouldBlock) => {
+                timer.register_waker(Event::RxNotEmpty, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    pub struct DefaultCaptureFuture<'a, C: Capture> {
+        timer: &'a mut C,
+        channel: C::Channel,
+    }
+
+    impl<'a, C: Capture> Unpin for DefaultCaptureFuture<'a, C> {}
+
+    impl<'a, C> Future for DefaultCaptureFuture<'a, C>
+    where
+        C: Default,
+        C::Channel: Copy,
+    {
+        type Output = Result<C::Capture, C::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            poll_capture(this.timer, this.channel, cx)
+        }
+    }
+
+    pub struct DefaultPeriodFuture<'a, C: Capture> {
+        timer: &'a mut C,
+        channel: C::Channel,
+        first: Option<C::Capture>,
+    }
+
+    impl<'a, C: Capture> Unpin for DefaultPeriodFuture<'a, C> {}
+
+    impl<'a, C> Future for DefaultPeriodFuture<'a, C>
+    where
+        C: Default,
+        C::Channel: Copy,
+        C::Capture: WrappingSub,
+    {
+        type Output = Result<C::Capture, C::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            loop {
+                let capture = match poll_capture(this.timer, this.channel, cx) {
+                    Poll::Ready(Ok(capture)) => capture,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                };
+                // The first edge only starts the period
+                match this.first {
+                    Some(first) => return Poll::Ready(Ok(capture.wrapping_sub(first))),
+                    None => this.first = Some(capture),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use core::future::Future;
+
+/// Digital input that can be waited on
+pub trait AsyncInputPin {
+    /// Error reading the pin level
+    type Error;
+    /// Level wait future for polling on completion
+    type WaitForLevelFuture<'t>: Future<Output=Result<(), Self::Error>>;
+    /// Edge wait future for polling on completion
+    type WaitForEdgeFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Waits until the pin is high, completing immediately if it already is
+    fn wait_for_high(&mut self) -> Self::WaitForLevelFuture<'_>;
+
+    /// Waits until the pin is low, completing immediately if it already is
+    fn wait_for_low(&mut self) -> Self::WaitForLevelFuture<'_>;
+
+    /// Waits for the pin to go from low to high
+    fn wait_for_rising_edge(&mut self) -> Self::WaitForEdgeFuture<'_>;
+
+    /// Waits for the pin to go from high to low
+    fn wait_for_falling_edge(&mut self) -> Self::WaitForEdgeFuture<'_>;
+
+    /// Waits for the pin to change its level
+    fn wait_for_any_edge(&mut self) -> Self::WaitForEdgeFuture<'_>;
+}
+
+pub mod input {
+    use super::AsyncInputPin;
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async input pin implementation
+    ///
+    /// Implementers of `embedded-hal::digital::v2::InputPin` can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`digital::AsyncInputPin`] for the type.
+    ///
+    /// The futures sample the pin every time they are polled and ask to be polled again
+    /// right away, so they keep the executor busy. Edges are detected by comparing
+    /// consecutive samples: a pulse shorter than the polling interval goes unnoticed.
+    /// Pins with an edge interrupt should rather implement [`digital::AsyncInputPin`] directly.
+    ///
+    /// [`digital::AsyncInputPin`]: ../trait.AsyncInputPin.html
+    pub trait Default: embedded_hal::digital::v2::InputPin {}
+
+    impl<P: Default + 'static> AsyncInputPin for P {
+        type Error = P::Error;
+        type WaitForLevelFuture<'t> = DefaultWaitForLevelFuture<'t, P>;
+        type WaitForEdgeFuture<'t> = DefaultWaitForEdgeFuture<'t, P>;
+
+        fn wait_for_high(&mut self) -> Self::WaitForLevelFuture<'_> {
+            DefaultWaitForLevelFuture {
+                pin: self,
+                high: true,
+            }
+        }
+
+        fn wait_for_low(&mut self) -> Self::WaitForLevelFuture<'_> {
+            DefaultWaitForLevelFuture {
+                pin: self,
+                high: false,
+            }
+        }
+
+        fn wait_for_rising_edge(&mut self) -> Self::WaitForEdgeFuture<'_> {
+            DefaultWaitForEdgeFuture {
+                pin: self,
+                edge: Edge::Rising,
+                previous: None,
+            }
+        }
+
+        fn wait_for_falling_edge(&mut self) -> Self::WaitForEdgeFuture<'_> {
+            DefaultWaitForEdgeFuture {
+                pin: self,
+                edge: Edge::Falling,
+                previous: None,
+            }
+        }
+
+        fn wait_for_any_edge(&mut self) -> Self::WaitForEdgeFuture<'_> {
+            DefaultWaitForEdgeFuture {
+                pin: self,
+                edge: Edge::Any,
+                previous: None,
+            }
+        }
+    }
+
+    pub struct DefaultWaitForLevelFuture<'a, P> {
+        pin: &'a mut P,
+        high: bool,
+    }
+
+    impl<'a, P: Default> Future for DefaultWaitForLevelFuture<'a, P> {
+        type Output = Result<(), P::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            match self.pin.is_high() {
+                Ok(high) if high == self.high => Poll::Ready(Ok(())),
+                Ok(_) => {
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                },
+                Err(e) => Poll::Ready(Err(e)),
+            }
+        }
+    }
+
+    #[derive(Copy, Clone, PartialEq)]
+    enum Edge {
+        Rising,
+        Falling,
+        Any,
+    }
+
+    pub struct DefaultWaitForEdgeFuture<'a, P> {
+        pin: &'a mut P,
+        edge: Edge,
+        previous: Option<bool>,
+    }
+
+    impl<'a, P: Default> Future for DefaultWaitForEdgeFuture<'a, P> {
+        type Output = Result<(), P::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let high = match self.pin.is_high() {
+                Ok(high) => high,
+                Err(e) => return Poll::Ready(Err(e)),
+            };
+
+            let detected = match (self.previous, self.edge) {
+                (Some(false), Edge::Rising) => high,
+                (Some(true), Edge::Falling) => !high,
+                (Some(previous), Edge::Any) => previous != high,
+                _ => false,
+            };
+            if detected {
+                return Poll::Ready(Ok(()));
+            }
+
+            self.previous = Some(high);
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use core::task::Waker;
+
+mod serial;
+mod spi;
+
+pub use self::serial::{DmaReader, DmaReadFuture, DmaWriter, DmaWriteFuture};
+pub use self::spi::{DmaSpi, DmaTransferFuture};
+
+/// DMA channel moving bytes between memory and a peripheral
+///
+/// The direction of the transfer is a property of the channel: a transmit channel reads
+/// the memory it is given, a receive channel writes to it.
+///
+/// A future cannot lend the memory of a borrowed buffer to a channel: the future may be
+/// leaked with `mem::forget`, releasing the buffer while the channel is still running.
+/// The wrappers in this module therefore own `'static` bounce buffers, copy data in and
+/// out of them, and stop the channel whenever a future is dropped before completion.
+pub trait Channel {
+    /// Transfer error, usually reported by the peripheral
+    type Error;
+
+    /// Starts moving `len` bytes to or from the memory at `address`
+    ///
+    /// # Safety
+    ///
+    /// The memory must stay valid, and must not be accessed other than through the channel,
+    /// until `stop` is called or `complete` reports the end of the transfer.
+    /// The channel must not be running already.
+    unsafe fn start(&mut self, address: *mut u8, len: usize);
+
+    /// Reports the end of the transfer started last
+    ///
+    /// Returns `WouldBlock` while the channel is still running and `Ok` once it has moved
+    /// all bytes. A channel that fails stops moving bytes before reporting the error.
+    fn complete(&mut self) -> nb::Result<(), Self::Error>;
+
+    /// Stops the channel, returning the number of bytes moved by the transfer started last
+    ///
+    /// Once this returns, the channel no longer accesses the memory it was given.
+    /// Stopping a channel that is not running has no effect besides returning the count.
+    fn stop(&mut self) -> usize;
+
+    /// Requests a wakeup once the running transfer ends, successfully or not
+    ///
+    /// If the transfer has ended already, `waker` must be woken right away.
+    fn register_waker(&self, waker: &Waker);
+}
@@ -0,0 +1,274 @@
+use super::Channel;
+use crate::serial::{AsyncRead, AsyncWrite, PartialError};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Serial writer moving data to the transmitter with a DMA channel
+///
+/// Data is copied to `buffer` and written in chunks of at most its size.
+/// Single bytes and flushes go through the inner writer.
+pub struct DmaWriter<S, C> {
+    serial: S,
+    channel: C,
+    buffer: &'static mut [u8],
+    running: bool,
+}
+
+impl<S, C> DmaWriter<S, C>
+where
+    S: AsyncWrite,
+    C: Channel<Error=S::Error>,
+{
+    pub fn new(serial: S, channel: C, buffer: &'static mut [u8]) -> Self {
+        assert!(!buffer.is_empty(), "DMA buffer must not be empty");
+        Self {
+            serial,
+            channel,
+            buffer,
+            running: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.serial
+    }
+
+    /// Stops the channel and returns the inner writer, the channel and the buffer
+    pub fn release(mut self) -> (S, C, &'static mut [u8]) {
+        self.channel.stop();
+        (self.serial, self.channel, self.buffer)
+    }
+
+    /// Starts writing the next chunk of `data`, returns its length
+    fn start(&mut self, data: &[u8]) -> usize {
+        if self.running {
+            // A future was leaked in the middle of a transfer
+            self.channel.stop();
+        }
+        let len = core::cmp::min(data.len(), self.buffer.len());
+        self.buffer[..len].copy_from_slice(&data[..len]);
+        unsafe {
+            self.channel.start(self.buffer.as_mut_ptr(), len);
+        }
+        self.running = true;
+        len
+    }
+}
+
+impl<S, C> AsyncWrite for DmaWriter<S, C>
+where
+    S: AsyncWrite + 'static,
+    C: Channel<Error=S::Error> + 'static,
+{
+    type Error = S::Error;
+    type WriteByteFuture<'t> = S::WriteByteFuture<'t>;
+    type WriteFuture<'t> = DmaWriteFuture<'t, S, C>;
+    type FlushFuture<'t> = S::FlushFuture<'t>;
+
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+        self.serial.async_write_byte(byte)
+    }
+
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        DmaWriteFuture {
+            writer: self,
+            data,
+            offset: 0,
+            chunk: 0,
+        }
+    }
+
+    fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+        self.serial.async_flush()
+    }
+}
+
+pub struct DmaWriteFuture<'a, S, C: Channel> {
+    writer: &'a mut DmaWriter<S, C>,
+    data: &'a [u8],
+    offset: usize,
+    chunk: usize,
+}
+
+impl<'a, S, C> Future for DmaWriteFuture<'a, S, C>
+where
+    S: AsyncWrite,
+    C: Channel<Error=S::Error>,
+{
+    type Output = Result<(), PartialError<S::Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            if this.writer.running {
+                match this.writer.channel.complete() {
+                    Ok(()) => {
+                        this.writer.running = false;
+                        this.offset += this.chunk;
+                    },
+                    Err(nb::Error::Other(error)) => {
+                        this.writer.running = false;
+                        let offset = this.offset + this.writer.channel.stop();
+                        return Poll::Ready(Err(PartialError { error, offset }));
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        this.writer.channel.register_waker(cx.waker());
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            if this.offset == this.data.len() {
+                return Poll::Ready(Ok(()));
+            }
+            this.chunk = this.writer.start(&this.data[this.offset..]);
+        }
+    }
+}
+
+impl<'a, S, C: Channel> Drop for DmaWriteFuture<'a, S, C> {
+    fn drop(&mut self) {
+        if self.writer.running {
+            self.writer.channel.stop();
+            self.writer.running = false;
+        }
+    }
+}
+
+/// Serial reader moving data from the receiver with a DMA channel
+///
+/// Data is received into `buffer` in chunks of at most its size and copied out once a chunk
+/// is complete. Bytes already moved by the channel are lost if the read future is dropped.
+/// Single bytes and partial reads go through the inner reader, as the channel cannot tell
+/// how many bytes are available.
+pub struct DmaReader<S, C> {
+    serial: S,
+    channel: C,
+    buffer: &'static mut [u8],
+    running: bool,
+}
+
+impl<S, C> DmaReader<S, C>
+where
+    S: AsyncRead,
+    C: Channel<Error=S::Error>,
+{
+    pub fn new(serial: S, channel: C, buffer: &'static mut [u8]) -> Self {
+        assert!(!buffer.is_empty(), "DMA buffer must not be empty");
+        Self {
+            serial,
+            channel,
+            buffer,
+            running: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.serial
+    }
+
+    /// Stops the channel and returns the inner reader, the channel and the buffer
+    pub fn release(mut self) -> (S, C, &'static mut [u8]) {
+        self.channel.stop();
+        (self.serial, self.channel, self.buffer)
+    }
+
+    /// Starts receiving the next chunk of at most `len` bytes, returns its length
+    fn start(&mut self, len: usize) -> usize {
+        if self.running {
+            // A future was leaked in the middle of a transfer
+            self.channel.stop();
+        }
+        let len = core::cmp::min(len, self.buffer.len());
+        unsafe {
+            self.channel.start(self.buffer.as_mut_ptr(), len);
+        }
+        self.running = true;
+        len
+    }
+}
+
+impl<S, C> AsyncRead for DmaReader<S, C>
+where
+    S: AsyncRead + 'static,
+    C: Channel<Error=S::Error> + 'static,
+{
+    type Error = S::Error;
+    type ReadByteFuture<'t> = S::ReadByteFuture<'t>;
+    type ReadFuture<'t> = DmaReadFuture<'t, S, C>;
+    type ReadSomeFuture<'t> = S::ReadSomeFuture<'t>;
+
+    fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
+        self.serial.async_read_byte()
+    }
+
+    fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        DmaReadFuture {
+            reader: self,
+            data,
+            offset: 0,
+            chunk: 0,
+        }
+    }
+
+    fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a> {
+        self.serial.async_read_some(data)
+    }
+}
+
+pub struct DmaReadFuture<'a, S, C: Channel> {
+    reader: &'a mut DmaReader<S, C>,
+    data: &'a mut [u8],
+    offset: usize,
+    chunk: usize,
+}
+
+impl<'a, S, C> Future for DmaReadFuture<'a, S, C>
+where
+    S: AsyncRead,
+    C: Channel<Error=S::Error>,
+{
+    type Output = Result<(), PartialError<S::Error>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            if this.reader.running {
+                match this.reader.channel.complete() {
+                    Ok(()) => {
+                        this.reader.running = false;
+                        let end = this.offset + this.chunk;
+                        this.data[this.offset..end].copy_from_slice(&this.reader.buffer[..this.chunk]);
+                        this.offset = end;
+                    },
+                    Err(nb::Error::Other(error)) => {
+                        this.reader.running = false;
+                        let moved = this.reader.channel.stop();
+                        let end = this.offset + moved;
+                        this.data[this.offset..end].copy_from_slice(&this.reader.buffer[..moved]);
+                        return Poll::Ready(Err(PartialError { error, offset: end }));
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        this.reader.channel.register_waker(cx.waker());
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            if this.offset == this.data.len() {
+                return Poll::Ready(Ok(()));
+            }
+            this.chunk = this.reader.start(this.data.len() - this.offset);
+        }
+    }
+}
+
+impl<'a, S, C: Channel> Drop for DmaReadFuture<'a, S, C> {
+    fn drop(&mut self) {
+        if self.reader.running {
+            self.reader.channel.stop();
+            self.reader.running = false;
+        }
+    }
+}
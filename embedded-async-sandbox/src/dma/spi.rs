@@ -0,0 +1,204 @@
+use super::Channel;
+use crate::spi::AsyncTransfer;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// SPI master moving data with a pair of DMA channels
+///
+/// Bytes to send are copied to `tx_buffer`, received bytes are collected in `rx_buffer`.
+/// Transfers are split into chunks of at most the size of the smaller buffer. The receive
+/// channel is started before the transmit channel, so that no response is missed.
+pub struct DmaSpi<TX, RX> {
+    tx: TX,
+    rx: RX,
+    tx_buffer: &'static mut [u8],
+    rx_buffer: &'static mut [u8],
+    fill_byte: u8,
+    running: bool,
+}
+
+impl<TX, RX> DmaSpi<TX, RX>
+where
+    TX: Channel,
+    RX: Channel<Error=TX::Error>,
+{
+    pub fn new(tx: TX, rx: RX, tx_buffer: &'static mut [u8], rx_buffer: &'static mut [u8]) -> Self {
+        assert!(!tx_buffer.is_empty() && !rx_buffer.is_empty(), "DMA buffers must not be empty");
+        Self {
+            tx,
+            rx,
+            tx_buffer,
+            rx_buffer,
+            fill_byte: 0x00,
+            running: false,
+        }
+    }
+
+    /// Sets the byte sent once the write buffer of a split transfer is exhausted
+    pub fn set_fill_byte(&mut self, fill_byte: u8) {
+        self.fill_byte = fill_byte;
+    }
+
+    /// Stops both channels and returns them with their buffers
+    pub fn release(mut self) -> (TX, RX, &'static mut [u8], &'static mut [u8]) {
+        self.stop();
+        (self.tx, self.rx, self.tx_buffer, self.rx_buffer)
+    }
+
+    fn stop(&mut self) {
+        self.tx.stop();
+        self.rx.stop();
+        self.running = false;
+    }
+
+    /// Starts the next chunk of at most `len` bytes, sending `write` followed by fill bytes
+    fn start(&mut self, write: &[u8], len: usize) -> usize {
+        if self.running {
+            // A future was leaked in the middle of a transfer
+            self.stop();
+        }
+        let len = core::cmp::min(len, core::cmp::min(self.tx_buffer.len(), self.rx_buffer.len()));
+        let count = core::cmp::min(write.len(), len);
+        self.tx_buffer[..count].copy_from_slice(&write[..count]);
+        for byte in &mut self.tx_buffer[count..len] {
+            *byte = self.fill_byte;
+        }
+        unsafe {
+            self.rx.start(self.rx_buffer.as_mut_ptr(), len);
+            self.tx.start(self.tx_buffer.as_mut_ptr(), len);
+        }
+        self.running = true;
+        len
+    }
+}
+
+impl<TX, RX> AsyncTransfer for DmaSpi<TX, RX>
+where
+    TX: Channel + 'static,
+    RX: Channel<Error=TX::Error> + 'static,
+{
+    type Error = TX::Error;
+    type TransferFuture<'t> = DmaTransferFuture<'t, TX, RX>;
+    type TransferSplitFuture<'t> = DmaTransferFuture<'t, TX, RX>;
+
+    fn async_transfer<'a>(&'a mut self, data: &'a mut [u8]) -> Self::TransferFuture<'a> {
+        DmaTransferFuture {
+            spi: self,
+            buffers: Buffers::InPlace(data),
+            offset: 0,
+            chunk: 0,
+        }
+    }
+
+    fn async_transfer_split<'a>(&'a mut self, read: &'a mut [u8], write: &'a [u8]) -> Self::TransferSplitFuture<'a> {
+        DmaTransferFuture {
+            spi: self,
+            buffers: Buffers::Split(read, write),
+            offset: 0,
+            chunk: 0,
+        }
+    }
+}
+
+enum Buffers<'a> {
+    InPlace(&'a mut [u8]),
+    Split(&'a mut [u8], &'a [u8]),
+}
+
+impl<'a> Buffers<'a> {
+    fn len(&self) -> usize {
+        match self {
+            Buffers::InPlace(data) => data.len(),
+            Buffers::Split(read, write) => core::cmp::max(read.len(), write.len()),
+        }
+    }
+
+    /// Returns the bytes to send from `offset` on
+    fn write(&self, offset: usize) -> &[u8] {
+        match self {
+            Buffers::InPlace(data) => &data[offset..],
+            Buffers::Split(_, write) => write.get(offset..).unwrap_or(&[]),
+        }
+    }
+
+    /// Stores the bytes received at `offset`, discarding those past the end of the read buffer
+    fn store(&mut self, offset: usize, received: &[u8]) {
+        let read = match self {
+            Buffers::InPlace(data) => data,
+            Buffers::Split(read, _) => read,
+        };
+        if offset < read.len() {
+            let count = core::cmp::min(received.len(), read.len() - offset);
+            read[offset..offset + count].copy_from_slice(&received[..count]);
+        }
+    }
+}
+
+pub struct DmaTransferFuture<'a, TX: Channel, RX: Channel> {
+    spi: &'a mut DmaSpi<TX, RX>,
+    buffers: Buffers<'a>,
+    offset: usize,
+    chunk: usize,
+}
+
+impl<'a, TX, RX> Future for DmaTransferFuture<'a, TX, RX>
+where
+    TX: Channel,
+    RX: Channel<Error=TX::Error>,
+{
+    type Output = Result<(), TX::Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        loop {
+            if this.spi.running {
+                // The last byte is received after it has been sent,
+                // so the transmit channel completes first
+                match this.spi.tx.complete() {
+                    Ok(()) => {},
+                    Err(nb::Error::Other(e)) => {
+                        this.spi.stop();
+                        return Poll::Ready(Err(e));
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        this.spi.tx.register_waker(cx.waker());
+                        return Poll::Pending;
+                    }
+                }
+                match this.spi.rx.complete() {
+                    Ok(()) => {
+                        this.spi.running = false;
+                        this.buffers.store(this.offset, &this.spi.rx_buffer[..this.chunk]);
+                        this.offset += this.chunk;
+                    },
+                    Err(nb::Error::Other(e)) => {
+                        this.spi.stop();
+                        return Poll::Ready(Err(e));
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        this.spi.rx.register_waker(cx.waker());
+                        return Poll::Pending;
+                    }
+                }
+            }
+
+            let len = this.buffers.len();
+            if this.offset == len {
+                return Poll::Ready(Ok(()));
+            }
+            let offset = this.offset;
+            this.chunk = this.spi.start(this.buffers.write(offset), len - offset);
+        }
+    }
+}
+
+impl<'a, TX: Channel, RX: Channel> Drop for DmaTransferFuture<'a, TX, RX> {
+    fn drop(&mut self) {
+        if self.spi.running {
+            self.spi.tx.stop();
+            self.spi.rx.stop();
+            self.spi.running = false;
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use core::future::Future;
+
+/// I2C write
+pub trait AsyncWrite {
+    /// Write error
+    type Error;
+    /// Write future for polling on completion
+    type WriteFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Sends bytes to the slave at `address`
+    fn async_write<'a>(&'a mut self, address: u8, bytes: &'a [u8]) -> Self::WriteFuture<'a>;
+}
+
+/// I2C read
+pub trait AsyncRead {
+    /// Read error
+    type Error;
+    /// Read future for polling on completion
+    type ReadFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Reads enough bytes from the slave at `address` to fill `buffer`
+    fn async_read<'a>(&'a mut self, address: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a>;
+}
+
+/// I2C write followed by a read
+pub trait AsyncWriteRead {
+    /// Write-read error
+    type Error;
+    /// Write-read future for polling on completion
+    type WriteReadFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Sends `bytes` to the slave at `address`, then fills `buffer` after a repeated start
+    fn async_write_read<'a>(&'a mut self, address: u8, bytes: &'a [u8], buffer: &'a mut [u8]) -> Self::WriteReadFuture<'a>;
+}
+
+/// Direction of the transfer following a start condition
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Write,
+    Read,
+}
+
+/// Non-blocking I2C master
+///
+/// `embedded-hal` only has blocking I2C traits, so this is the byte level interface
+/// an I2C peripheral provides to the default implementations. Every method starts
+/// its bus operation on the first call and returns `WouldBlock` until it has completed.
+/// Calling it again with the same arguments reports the outcome of that operation.
+pub trait Master {
+    /// Bus error, such as a missing acknowledge or a lost arbitration
+    type Error;
+
+    /// Generates a start or repeated start condition and sends the address of the slave
+    ///
+    /// Completes once the slave has acknowledged its address.
+    fn start(&mut self, address: u8, direction: Direction) -> nb::Result<(), Self::Error>;
+
+    /// Sends a byte, completing once the slave has acknowledged it
+    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error>;
+
+    /// Receives a byte, acknowledging it unless it is the `last` one
+    fn read(&mut self, last: bool) -> nb::Result<u8, Self::Error>;
+
+    /// Generates a stop condition, releasing the bus
+    fn stop(&mut self) -> nb::Result<(), Self::Error>;
+}
+
+pub mod master {
+    use super::{AsyncRead, AsyncWrite, AsyncWriteRead, Direction, Master};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async I2C implementations
+    ///
+    /// Implementers of [`i2c::Master`] can implement this marker trait for their type.
+    /// Doing so will automatically provide the default implementations of [`i2c::AsyncWrite`],
+    /// [`i2c::AsyncRead`] and [`i2c::AsyncWriteRead`] for the type.
+    /// Futures wait for [`Event::TxSpace`] while starting and sending, for [`Event::RxNotEmpty`]
+    /// while reading and for [`Event::TxIdle`] while stopping.
+    /// After a failed operation a stop condition is generated before the error is returned.
+    ///
+    /// [`i2c::Master`]: ../trait.Master.html
+    /// [`i2c::AsyncWrite`]: ../trait.AsyncWrite.html
+    /// [`i2c::AsyncRead`]: ../trait.AsyncRead.html
+    /// [`i2c::AsyncWriteRead`]: ../trait.AsyncWriteRead.html
+    /// [`Event::TxSpace`]: ../../waker/enum.Event.html#variant.TxSpace
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    /// [`Event::TxIdle`]: ../../waker/enum.Event.html#variant.TxIdle
+    pub trait Default: Master + RegisterWaker {}
+
+    impl<M: Default + 'static> AsyncWrite for M {
+        type Error = M::Error;
+        type WriteFuture<'t> = DefaultWriteFuture<'t, M>;
+
+        fn async_write<'a>(&'a mut self, address: u8, bytes: &'a [u8]) -> Self::WriteFuture<'a> {
+            DefaultWriteFuture {
+                i2c: self,
+                bytes,
+                transaction: Transaction::new(address, Direction::Write),
+            }
+        }
+    }
+
+    impl<M: Default + 'static> AsyncRead for M {
+        type Error = M::Error;
+        type ReadFuture<'t> = DefaultReadFuture<'t, M>;
+
+        fn async_read<'a>(&'a mut self, address: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a> {
+            DefaultReadFuture {
+                i2c: self,
+                buffer,
+                transaction: Transaction::new(address, Direction::Read),
+            }
+        }
+    }
+
+    impl<M: Default + 'static> AsyncWriteRead for M {
+        type Error = M::Error;
+        type WriteReadFuture<'t> = DefaultWriteReadFuture<'t, M>;
+
+        fn async_write_read<'a>(&'a mut self, address: u8, bytes: &'a [u8], buffer: &'a mut [u8]) -> Self::WriteReadFuture<'a> {
+            let direction = if bytes.is_empty() && !buffer.is_empty() {
+                Direction::Read
+            } else {
+                Direction::Write
+            };
+            DefaultWriteReadFuture {
+                i2c: self,
+                bytes,
+                buffer,
+                transaction: Transaction::new(address, direction),
+            }
+        }
+    }
+
+    enum State {
+        Start(Direction),
+        Sending(usize),
+        Reading(usize),
+        Stopping,
+    }
+
+    /// Bus state machine shared by the default futures
+    struct Transaction<E> {
+        address: u8,
+        state: State,
+        error: Option<E>,
+    }
+
+    // The pending error is never pinned
+    impl<E> Unpin for Transaction<E> {}
+
+    impl<E> Transaction<E> {
+        fn new(address: u8, direction: Direction) -> Self {
+            Self {
+                address,
+                state: State::Start(direction),
+                error: None,
+            }
+        }
+
+        fn poll<M>(&mut self, i2c: &mut M, bytes: &[u8], buffer: &mut [u8], cx: &mut Context<'_>) -> Poll<Result<(), E>>
+        where
+            M: Default<Error=E>,
+        {
+            loop {
+                let (result, event) = match self.state {
+                    State::Start(direction) => {
+                        let result = i2c.start(self.address, direction);
+                        if result.is_ok() {
+                            self.state = match direction {
+                                Direction::Write => State::Sending(0),
+                                Direction::Read => State::Reading(0),
+                            };
+                        }
+                        (result, Event::TxSpace)
+                    },
+                    State::Sending(offset) if offset == bytes.len() => {
+                        self.state = if buffer.is_empty() {
+                            State::Stopping
+                        } else {
+                            State::Start(Direction::Read)
+                        };
+                        continue;
+                    },
+                    State::Sending(offset) => {
+                        let result = i2c.send(bytes[offset]);
+                        if result.is_ok() {
+                            self.state = State::Sending(offset + 1);
+                        }
+                        (result, Event::TxSpace)
+                    },
+                    State::Reading(offset) if offset == buffer.len() => {
+                        self.state = State::Stopping;
+                        continue;
+                    },
+                    State::Reading(offset) => {
+                        let last = offset + 1 == buffer.len();
+                        let result = i2c.read(last).map(|byte| buffer[offset] = byte);
+                        if result.is_ok() {
+                            self.state = State::Reading(offset + 1);
+                        }
+                        (result, Event::RxNotEmpty)
+                    },
+                    State::Stopping => {
+                        return match i2c.stop() {
+                            Ok(()) => match self.error.take() {
+                                Some(e) => Poll::Ready(Err(e)),
+                                None => Poll::Ready(Ok(())),
+                            },
+                            Err(nb::Error::Other(e)) => Poll::Ready(Err(self.error.take().unwrap_or(e))),
+                            Err(nb::Error::WouldBlock) => {
+                                i2c.register_waker(Event::TxIdle, cx.waker());
+                                Poll::Pending
+                            },
+                        };
+                    },
+                };
+
+                match result {
+                    Ok(()) => continue,
+                    Err(nb::Error::Other(e)) => {
+                        self.error = Some(e);
+                        self.state = State::Stopping;
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        i2c.register_waker(event, cx.waker());
+                        return Poll::Pending;
+                    },
+                }
+            }
+        }
+    }
+
+    pub struct DefaultWriteFuture<'a, M: Master> {
+        i2c: &'a mut M,
+        bytes: &'a [u8],
+        transaction: Transaction<M::Error>,
+    }
+
+    impl<'a, M: Default> Future for DefaultWriteFuture<'a, M> {
+        type Output = Result<(), M::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            this.transaction.poll(this.i2c, this.bytes, &mut [], cx)
+        }
+    }
+
+    pub struct DefaultReadFuture<'a, M: Master> {
+        i2c: &'a mut M,
+        buffer: &'a mut [u8],
+        transaction: Transaction<M::Error>,
+    }
+
+    impl<'a, M: Default> Future for DefaultReadFuture<'a, M> {
+        type Output = Result<(), M::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            this.transaction.poll(this.i2c, &[], this.buffer, cx)
+        }
+    }
+
+    pub struct DefaultWriteReadFuture<'a, M: Master> {
+        i2c: &'a mut M,
+        bytes: &'a [u8],
+        buffer: &'a mut [u8],
+        transaction: Transaction<M::Error>,
+    }
+
+    impl<'a, M: Default> Future for DefaultWriteReadFuture<'a, M> {
+        type Output = Result<(), M::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            this.transaction.poll(this.i2c, this.bytes, this.buffer, cx)
+        }
+    }
+}
@@ -1,5 +1,19 @@
 #![no_std]
 #![feature(generic_associated_types)]
+#![feature(min_const_generics)]
 
+pub mod adc;
+pub mod can;
+pub mod capture;
+pub mod digital;
+pub mod dma;
+pub mod i2c;
+pub mod mutex;
+pub mod onewire;
+mod reborrow;
 pub mod serial;
 pub mod spi;
+pub mod storage;
+pub mod timer;
+pub mod usb;
+pub mod waker;
@@ -0,0 +1,225 @@
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
+
+/// Mutex whose lock can be awaited
+///
+/// Unlike a blocking mutex, the lock is held across `.await` points, so it can
+/// serialize whole operations of several tasks on a shared peripheral.
+/// All waiting tasks are woken when the lock is released. There is no limit on their
+/// number: every pending [`LockFuture`] links itself into a list of waiters, so the
+/// mutex needs no storage for them.
+///
+/// [`LockFuture`]: struct.LockFuture.html
+pub struct Mutex<T> {
+    locked: AtomicBool,
+    // Guards `waiters`, it is only held for a few instructions and never across an `.await`
+    listing: AtomicBool,
+    waiters: UnsafeCell<Waiters>,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            listing: AtomicBool::new(false),
+            waiters: UnsafeCell::new(Waiters {
+                head: ptr::null_mut(),
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Waits until the lock is free and acquires it
+    pub fn lock(&self) -> LockFuture<'_, T> {
+        LockFuture {
+            mutex: self,
+            waiter: UnsafeCell::new(Waiter {
+                waker: None,
+                prev: ptr::null_mut(),
+                next: ptr::null_mut(),
+                linked: false,
+            }),
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Acquires the lock if it is free
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            Some(MutexGuard { mutex: self })
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the value, no locking is needed
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.value.get() }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn with_waiters<R>(&self, f: impl FnOnce(&mut Waiters) -> R) -> R {
+        while self.listing.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {}
+        let result = f(unsafe { &mut *self.waiters.get() });
+        self.listing.store(false, Ordering::Release);
+        result
+    }
+
+    fn unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+        self.with_waiters(|waiters| {
+            let mut waiter = waiters.head;
+            while !waiter.is_null() {
+                unsafe {
+                    if let Some(waker) = &(*waiter).waker {
+                        waker.wake_by_ref();
+                    }
+                    waiter = (*waiter).next;
+                }
+            }
+        });
+    }
+}
+
+/// Doubly linked list of the pending `LockFuture`s of a `Mutex`
+struct Waiters {
+    head: *mut Waiter,
+}
+
+impl Waiters {
+    /// Safety: `waiter` must not be linked and must stay in place until it is removed
+    unsafe fn push(&mut self, waiter: *mut Waiter) {
+        (*waiter).prev = ptr::null_mut();
+        (*waiter).next = self.head;
+        if !self.head.is_null() {
+            (*self.head).prev = waiter;
+        }
+        self.head = waiter;
+        (*waiter).linked = true;
+    }
+
+    /// Safety: `waiter` must be linked into this list
+    unsafe fn remove(&mut self, waiter: *mut Waiter) {
+        let (prev, next) = ((*waiter).prev, (*waiter).next);
+        if prev.is_null() {
+            self.head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+        (*waiter).linked = false;
+        (*waiter).waker = None;
+    }
+}
+
+struct Waiter {
+    waker: Option<Waker>,
+    prev: *mut Waiter,
+    next: *mut Waiter,
+    // Only changed by the owning future, so it can check it without the list lock
+    linked: bool,
+}
+
+/// Exclusive access to the value of a locked `Mutex`
+///
+/// The lock is released when the guard is dropped.
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Future of [`Mutex::lock`]
+///
+/// While pending, the future is linked into the waiters of the mutex, so it cannot be moved.
+///
+/// [`Mutex::lock`]: struct.Mutex.html#method.lock
+pub struct LockFuture<'a, T> {
+    mutex: &'a Mutex<T>,
+    waiter: UnsafeCell<Waiter>,
+    _pinned: PhantomPinned,
+}
+
+// The waiter is only accessed by the future itself and, under the list lock, by the mutex
+unsafe impl<T: Send> Send for LockFuture<'_, T> {}
+
+impl<'a, T> LockFuture<'a, T> {
+    fn unlink(&self) {
+        let waiter = self.waiter.get();
+        if unsafe { (*waiter).linked } {
+            self.mutex.with_waiters(|waiters| unsafe { waiters.remove(waiter) });
+        }
+    }
+}
+
+impl<'a, T> Future for LockFuture<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mutex = self.mutex;
+        if let Some(guard) = mutex.try_lock() {
+            self.unlink();
+            return Poll::Ready(guard);
+        }
+
+        // The future is pinned, the waiter stays in place until it is unlinked on completion or drop
+        let waiter = self.waiter.get();
+        mutex.with_waiters(|waiters| unsafe {
+            match &mut (*waiter).waker {
+                Some(waker) if waker.will_wake(cx.waker()) => {},
+                waker => *waker = Some(cx.waker().clone()),
+            }
+            if !(*waiter).linked {
+                waiters.push(waiter);
+            }
+        });
+
+        // The lock may have been released before the waker was registered
+        match mutex.try_lock() {
+            Some(guard) => {
+                self.unlink();
+                Poll::Ready(guard)
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<T> Drop for LockFuture<'_, T> {
+    fn drop(&mut self) {
+        self.unlink();
+    }
+}
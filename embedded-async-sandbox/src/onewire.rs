@@ -0,0 +1,395 @@
+use core::future::Future;
+
+mod search;
+
+pub use self::search::{RomSearch, SearchError};
+
+/// ROM command starting a search for the ROM codes of all devices on the bus
+pub const SEARCH_ROM: u8 = 0xf0;
+/// ROM command reading the ROM code of the only device on the bus
+pub const READ_ROM: u8 = 0x33;
+/// ROM command addressing the device with the ROM code that follows
+pub const MATCH_ROM: u8 = 0x55;
+/// ROM command addressing all devices on the bus
+pub const SKIP_ROM: u8 = 0xcc;
+
+/// Computes the Dallas/Maxim CRC-8 used for ROM codes and scratchpads
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0;
+    for byte in data {
+        let mut byte = *byte;
+        for _ in 0..8 {
+            let mix = (crc ^ byte) & 1;
+            crc >>= 1;
+            if mix != 0 {
+                crc ^= 0x8c;
+            }
+            byte >>= 1;
+        }
+    }
+    crc
+}
+
+/// 64-bit ROM code of a 1-Wire device
+///
+/// The least significant byte holds the family code, followed by a 48-bit serial number
+/// and the CRC of the first 7 bytes. ROM codes are sent least significant bit first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rom(u64);
+
+impl Rom {
+    pub fn from_raw(raw: u64) -> Self {
+        Rom(raw)
+    }
+
+    pub fn as_raw(&self) -> u64 {
+        self.0
+    }
+
+    pub fn family_code(&self) -> u8 {
+        self.0 as u8
+    }
+
+    pub fn serial_number(&self) -> u64 {
+        (self.0 >> 8) & 0xffff_ffff_ffff
+    }
+
+    /// Returns the bytes of the ROM code in the order they are sent
+    pub fn to_bytes(&self) -> [u8; 8] {
+        self.0.to_le_bytes()
+    }
+
+    /// Returns true if the CRC byte matches the rest of the ROM code
+    pub fn is_valid(&self) -> bool {
+        let bytes = self.to_bytes();
+        crc8(&bytes[..7]) == bytes[7]
+    }
+}
+
+/// 1-Wire bus master
+pub trait AsyncOneWire {
+    /// Bus error
+    type Error;
+    /// Reset future for polling on completion
+    type ResetFuture<'t>: Future<Output=Result<bool, Self::Error>>;
+    /// Read bit future for polling on completion
+    type ReadBitFuture<'t>: Future<Output=Result<bool, Self::Error>>;
+    /// Write bit future for polling on completion
+    type WriteBitFuture<'t>: Future<Output=Result<(), Self::Error>>;
+    /// Read byte future for polling on completion
+    type ReadByteFuture<'t>: Future<Output=Result<u8, Self::Error>>;
+    /// Write byte future for polling on completion
+    type WriteByteFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Sends a reset pulse, returns true if any device answered with a presence pulse
+    fn async_reset(&mut self) -> Self::ResetFuture<'_>;
+
+    /// Generates a read time slot, returns false if a device pulled the bus low
+    fn async_read_bit(&mut self) -> Self::ReadBitFuture<'_>;
+
+    /// Generates a write time slot
+    fn async_write_bit(&mut self, bit: bool) -> Self::WriteBitFuture<'_>;
+
+    /// Reads a byte, least significant bit first
+    fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_>;
+
+    /// Writes a byte, least significant bit first
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_>;
+}
+
+pub mod uart {
+    use super::AsyncOneWire;
+    use crate::reborrow::Reborrow;
+    use crate::serial::{AsyncRead, AsyncWrite, SetBaudRate};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Baud rate at which sending 0xf0 makes a reset pulse
+    const RESET_BAUD_RATE: u32 = 9600;
+    /// Baud rate at which sending a byte makes a time slot
+    const SLOT_BAUD_RATE: u32 = 115_200;
+
+    /// Marker trait to opt into the 1-Wire over UART implementation
+    ///
+    /// Implementers of [`serial::AsyncRead`] and [`serial::AsyncWrite`] whose TX and RX lines
+    /// are tied to the bus through an open-drain driver can implement this marker trait
+    /// for their type. Doing so will automatically provide an implementation
+    /// of [`onewire::AsyncOneWire`] for the type.
+    ///
+    /// Every byte sent is read back from the bus. At 9600 baud, sending 0xf0 makes
+    /// a reset pulse and a presence pulse corrupts the byte read back. At 115200 baud,
+    /// every byte is a time slot: 0xff writes a 1 or reads a bit, which is 0 if a device
+    /// corrupted the byte, 0x00 writes a 0. Resets leave the interface at 115200 baud,
+    /// so the first operation must be a reset.
+    ///
+    /// [`serial::AsyncRead`]: ../../serial/trait.AsyncRead.html
+    /// [`serial::AsyncWrite`]: ../../serial/trait.AsyncWrite.html
+    /// [`onewire::AsyncOneWire`]: ../trait.AsyncOneWire.html
+    pub trait Default: AsyncRead + AsyncWrite<Error=<Self as AsyncRead>::Error> + SetBaudRate {}
+
+    impl<S: Default + 'static> AsyncOneWire for S {
+        type Error = <S as AsyncRead>::Error;
+        type ResetFuture<'t> = UartResetFuture<'t, S>;
+        type ReadBitFuture<'t> = UartReadBitFuture<'t, S>;
+        type WriteBitFuture<'t> = UartWriteBitFuture<'t, S>;
+        type ReadByteFuture<'t> = UartReadByteFuture<'t, S>;
+        type WriteByteFuture<'t> = UartWriteByteFuture<'t, S>;
+
+        fn async_reset(&mut self) -> Self::ResetFuture<'_> {
+            UartResetFuture {
+                exchange: Exchange::new(self),
+                started: false,
+            }
+        }
+
+        fn async_read_bit(&mut self) -> Self::ReadBitFuture<'_> {
+            UartReadBitFuture {
+                exchange: Exchange::new(self),
+                started: false,
+            }
+        }
+
+        fn async_write_bit(&mut self, bit: bool) -> Self::WriteBitFuture<'_> {
+            UartWriteBitFuture {
+                exchange: Exchange::new(self),
+                bit,
+                started: false,
+            }
+        }
+
+        fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
+            UartReadByteFuture {
+                exchange: Exchange::new(self),
+                byte: 0,
+                bit: 0,
+                started: false,
+            }
+        }
+
+        fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+            UartWriteByteFuture {
+                exchange: Exchange::new(self),
+                byte,
+                bit: 0,
+                started: false,
+            }
+        }
+    }
+
+    /// Returns the byte to send for a time slot writing `bit`
+    fn slot(bit: bool) -> u8 {
+        if bit {
+            0xff
+        } else {
+            0x00
+        }
+    }
+
+    enum Step<'a, S: Default + 'a> {
+        Idle,
+        Write(<S as AsyncWrite>::WriteByteFuture<'a>),
+        Read(<S as AsyncRead>::ReadByteFuture<'a>),
+    }
+
+    /// Sends a byte and reads it back from the bus
+    ///
+    /// The write and read futures borrow the serial interface one after another,
+    /// it is lent to each of them in turn.
+    struct Exchange<'a, S: Default + 'a> {
+        serial: Reborrow<'a, S>,
+        step: Step<'a, S>,
+    }
+
+    impl<'a, S: Default + 'a> Exchange<'a, S> {
+        fn new(serial: &'a mut S) -> Self {
+            Self {
+                serial: Reborrow::new(serial),
+                step: Step::Idle,
+            }
+        }
+
+        /// Returns the serial interface, must not be called while an exchange is in flight
+        fn serial(&mut self) -> &mut S {
+            self.serial.get()
+        }
+
+        fn start(&mut self, byte: u8) {
+            self.step = Step::Write(self.serial.lend().async_write_byte(byte));
+        }
+
+        /// Leaves the current step, whose future was the one borrowing the serial interface
+        fn finish_step(&mut self) {
+            self.step = Step::Idle;
+            unsafe { self.serial.give_back() };
+        }
+
+        /// Completes the exchange started by `start`, returns the byte read back
+        fn poll_exchange(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<u8, <S as AsyncRead>::Error>> {
+            // The futures in `step` are structurally pinned, the other fields are never pinned
+            let this = unsafe { self.get_unchecked_mut() };
+            loop {
+                match &mut this.step {
+                    Step::Idle => panic!("no exchange in flight"),
+                    Step::Write(future) => {
+                        let result = match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                            Poll::Ready(result) => result,
+                            Poll::Pending => return Poll::Pending,
+                        };
+                        this.finish_step();
+                        if let Err(e) = result {
+                            return Poll::Ready(Err(e));
+                        }
+                        this.step = Step::Read(this.serial.lend().async_read_byte());
+                    },
+                    Step::Read(future) => {
+                        let result = match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                            Poll::Ready(result) => result,
+                            Poll::Pending => return Poll::Pending,
+                        };
+                        this.finish_step();
+                        return Poll::Ready(result);
+                    },
+                }
+            }
+        }
+    }
+
+    pub struct UartResetFuture<'a, S: Default + 'a> {
+        exchange: Exchange<'a, S>,
+        started: bool,
+    }
+
+    impl<'a, S: Default + 'a> Future for UartResetFuture<'a, S> {
+        type Output = Result<bool, <S as AsyncRead>::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = unsafe { self.get_unchecked_mut() };
+            if !this.started {
+                this.exchange.serial().set_baud_rate(RESET_BAUD_RATE);
+                this.exchange.start(0xf0);
+                this.started = true;
+            }
+
+            let exchange = unsafe { Pin::new_unchecked(&mut this.exchange) };
+            let result = match exchange.poll_exchange(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            this.exchange.serial().set_baud_rate(SLOT_BAUD_RATE);
+            Poll::Ready(result.map(|echo| echo != 0xf0))
+        }
+    }
+
+    pub struct UartReadBitFuture<'a, S: Default + 'a> {
+        exchange: Exchange<'a, S>,
+        started: bool,
+    }
+
+    impl<'a, S: Default + 'a> Future for UartReadBitFuture<'a, S> {
+        type Output = Result<bool, <S as AsyncRead>::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = unsafe { self.get_unchecked_mut() };
+            if !this.started {
+                this.exchange.start(slot(true));
+                this.started = true;
+            }
+
+            let exchange = unsafe { Pin::new_unchecked(&mut this.exchange) };
+            match exchange.poll_exchange(cx) {
+                Poll::Ready(result) => Poll::Ready(result.map(|echo| echo == 0xff)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    pub struct UartWriteBitFuture<'a, S: Default + 'a> {
+        exchange: Exchange<'a, S>,
+        bit: bool,
+        started: bool,
+    }
+
+    impl<'a, S: Default + 'a> Future for UartWriteBitFuture<'a, S> {
+        type Output = Result<(), <S as AsyncRead>::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = unsafe { self.get_unchecked_mut() };
+            if !this.started {
+                this.exchange.start(slot(this.bit));
+                this.started = true;
+            }
+
+            let exchange = unsafe { Pin::new_unchecked(&mut this.exchange) };
+            match exchange.poll_exchange(cx) {
+                Poll::Ready(result) => Poll::Ready(result.map(|_| ())),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    pub struct UartReadByteFuture<'a, S: Default + 'a> {
+        exchange: Exchange<'a, S>,
+        byte: u8,
+        bit: u8,
+        started: bool,
+    }
+
+    impl<'a, S: Default + 'a> Future for UartReadByteFuture<'a, S> {
+        type Output = Result<u8, <S as AsyncRead>::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = unsafe { self.get_unchecked_mut() };
+            while this.bit < 8 {
+                if !this.started {
+                    this.exchange.start(slot(true));
+                    this.started = true;
+                }
+
+                let exchange = unsafe { Pin::new_unchecked(&mut this.exchange) };
+                let echo = match exchange.poll_exchange(cx) {
+                    Poll::Ready(Ok(echo)) => echo,
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                };
+                if echo == 0xff {
+                    this.byte |= 1 << this.bit;
+                }
+                this.bit += 1;
+                this.started = false;
+            }
+            Poll::Ready(Ok(this.byte))
+        }
+    }
+
+    pub struct UartWriteByteFuture<'a, S: Default + 'a> {
+        exchange: Exchange<'a, S>,
+        byte: u8,
+        bit: u8,
+        started: bool,
+    }
+
+    impl<'a, S: Default + 'a> Future for UartWriteByteFuture<'a, S> {
+        type Output = Result<(), <S as AsyncRead>::Error>;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = unsafe { self.get_unchecked_mut() };
+            while this.bit < 8 {
+                if !this.started {
+                    this.exchange.start(slot(this.byte & (1 << this.bit) != 0));
+                    this.started = true;
+                }
+
+                let exchange = unsafe { Pin::new_unchecked(&mut this.exchange) };
+                match exchange.poll_exchange(cx) {
+                    Poll::Ready(Ok(_)) => {},
+                    Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                    Poll::Pending => return Poll::Pending,
+                }
+                this.bit += 1;
+                this.started = false;
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::onewire::{AsyncOneWire, Rom, SEARCH_ROM};
+use crate::reborrow::Reborrow;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Error of a ROM search
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SearchError<E> {
+    /// No device answered a bit of the search, a device probably left the bus
+    NoResponse,
+    /// The ROM code found does not match its CRC
+    Crc(Rom),
+    /// The bus failed
+    Bus(E),
+}
+
+struct State {
+    rom: u64,
+    /// Last bit at which the previous search took the 0 branch
+    last_discrepancy: Option<usize>,
+    last_device: bool,
+}
+
+impl State {
+    fn restart(&mut self) {
+        self.rom = 0;
+        self.last_discrepancy = None;
+        self.last_device = false;
+    }
+}
+
+/// Search for the ROM codes of all devices on a bus
+///
+/// Every call to `async_next` runs one pass of the search algorithm, finding one more device.
+/// A CRC error skips the device, other errors restart the search from the beginning.
+pub struct RomSearch<'a, W> {
+    bus: &'a mut W,
+    state: State,
+}
+
+impl<'a, W: AsyncOneWire> RomSearch<'a, W> {
+    pub fn new(bus: &'a mut W) -> Self {
+        Self {
+            bus,
+            state: State {
+                rom: 0,
+                last_discrepancy: None,
+                last_device: false,
+            },
+        }
+    }
+
+    /// Finds the next device, returns `None` once all devices were found
+    pub fn async_next(&mut self) -> SearchFuture<'_, W> {
+        SearchFuture {
+            bus: Reborrow::new(self.bus),
+            state: &mut self.state,
+            step: Step::Start,
+            bit: 0,
+            id_bit: false,
+            last_zero: None,
+        }
+    }
+}
+
+enum Step<'a, W: AsyncOneWire + 'a> {
+    Start,
+    Reset(W::ResetFuture<'a>),
+    Command(W::WriteByteFuture<'a>),
+    IdBit(W::ReadBitFuture<'a>),
+    ComplementBit(W::ReadBitFuture<'a>),
+    Direction(W::WriteBitFuture<'a>),
+}
+
+/// Polls the bus future in `step`, leaving the step once it completes
+///
+/// Errors restart the search and end the calling `poll`.
+macro_rules! complete {
+    ($this:ident, $future:ident, $cx:ident) => {{
+        let result = match unsafe { Pin::new_unchecked($future) }.poll($cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        $this.step = Step::Start;
+        // The future that borrowed the bus is gone
+        unsafe { $this.bus.give_back() };
+        match result {
+            Ok(value) => value,
+            Err(e) => {
+                $this.state.restart();
+                return Poll::Ready(Err(SearchError::Bus(e)));
+            },
+        }
+    }};
+}
+
+pub struct SearchFuture<'a, W: AsyncOneWire + 'a> {
+    bus: Reborrow<'a, W>,
+    state: &'a mut State,
+    step: Step<'a, W>,
+    bit: usize,
+    id_bit: bool,
+    last_zero: Option<usize>,
+}
+
+impl<'a, W: AsyncOneWire + 'a> SearchFuture<'a, W> {
+    /// Lends the bus to the future of the next step
+    fn bus(&mut self) -> &'a mut W {
+        self.bus.lend()
+    }
+}
+
+impl<'a, W: AsyncOneWire + 'a> Future for SearchFuture<'a, W> {
+    type Output = Result<Option<Rom>, SearchError<W::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The futures in `step` are structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            match &mut this.step {
+                Step::Start => {
+                    if this.state.last_device {
+                        return Poll::Ready(Ok(None));
+                    }
+                    this.step = Step::Reset(this.bus().async_reset());
+                },
+                Step::Reset(future) => {
+                    if !complete!(this, future, cx) {
+                        this.state.restart();
+                        return Poll::Ready(Ok(None));
+                    }
+                    this.step = Step::Command(this.bus().async_write_byte(SEARCH_ROM));
+                },
+                Step::Command(future) => {
+                    complete!(this, future, cx);
+                    this.step = Step::IdBit(this.bus().async_read_bit());
+                },
+                Step::IdBit(future) => {
+                    this.id_bit = complete!(this, future, cx);
+                    this.step = Step::ComplementBit(this.bus().async_read_bit());
+                },
+                Step::ComplementBit(future) => {
+                    let complement_bit = complete!(this, future, cx);
+                    let direction = match (this.id_bit, complement_bit) {
+                        (true, true) => {
+                            this.state.restart();
+                            return Poll::Ready(Err(SearchError::NoResponse));
+                        },
+                        (false, false) => {
+                            // Devices differ in this bit, take the branch the previous pass did not finish
+                            let direction = match this.state.last_discrepancy {
+                                Some(last) if this.bit < last => this.state.rom & (1 << this.bit) != 0,
+                                Some(last) => this.bit == last,
+                                None => false,
+                            };
+                            if !direction {
+                                this.last_zero = Some(this.bit);
+                            }
+                            direction
+                        },
+                        (id_bit, _) => id_bit,
+                    };
+
+                    if direction {
+                        this.state.rom |= 1 << this.bit;
+                    } else {
+                        this.state.rom &= !(1 << this.bit);
+                    }
+                    this.step = Step::Direction(this.bus().async_write_bit(direction));
+                },
+                Step::Direction(future) => {
+                    complete!(this, future, cx);
+                    this.bit += 1;
+                    if this.bit < 64 {
+                        this.step = Step::IdBit(this.bus().async_read_bit());
+                        continue;
+                    }
+
+                    this.state.last_discrepancy = this.last_zero;
+                    this.state.last_device = this.last_zero.is_none();
+                    let rom = Rom::from_raw(this.state.rom);
+                    if !rom.is_valid() {
+                        return Poll::Ready(Err(SearchError::Crc(rom)));
+                    }
+                    return Poll::Ready(Ok(Some(rom)));
+                },
+            }
+        }
+    }
+}
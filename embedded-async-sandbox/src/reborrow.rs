@@ -0,0 +1,64 @@
+use core::marker::PhantomData;
+
+/// Mutable borrow that is lent out again and again for its whole lifetime
+///
+/// Futures running a sequence of operations on an object keep the future of the current
+/// operation next to the borrow of the object. That future holds a `&'a mut` derived from the
+/// borrow, so the borrow itself cannot be kept as a `&'a mut` without aliasing it.
+/// `Reborrow` keeps a pointer instead and tracks whether the value is lent out, so that
+/// no second reference to it can be created until the first one has been given back.
+pub(crate) struct Reborrow<'a, T: ?Sized> {
+    value: *mut T,
+    lent: bool,
+    _marker: PhantomData<&'a mut T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Reborrow<'_, T> {}
+unsafe impl<T: ?Sized + Sync> Sync for Reborrow<'_, T> {}
+
+impl<'a, T: ?Sized> Reborrow<'a, T> {
+    pub fn new(value: &'a mut T) -> Self {
+        Self {
+            value,
+            lent: false,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Borrows the value for as long as `self` is borrowed
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is lent out.
+    pub fn get(&mut self) -> &mut T {
+        assert!(!self.lent, "value is lent out");
+        unsafe { &mut *self.value }
+    }
+
+    /// Lends out the value for the whole lifetime `'a`
+    ///
+    /// The value is unavailable until it is given back with [`give_back`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is already lent out.
+    ///
+    /// [`give_back`]: #method.give_back
+    pub fn lend(&mut self) -> &'a mut T {
+        assert!(!self.lent, "value is lent out");
+        self.lent = true;
+        unsafe { &mut *self.value }
+    }
+
+    /// Makes the value available again after it has been lent out
+    ///
+    /// # Safety
+    ///
+    /// The reference returned by [`lend`], and everything borrowed from it,
+    /// must not be used anymore.
+    ///
+    /// [`lend`]: #method.lend
+    pub unsafe fn give_back(&mut self) {
+        self.lent = false;
+    }
+}
@@ -1,5 +1,31 @@
 use core::future::Future;
 
+mod buffered;
+#[cfg(feature = "futures")]
+mod stream;
+mod transform;
+
+pub use self::buffered::{BufReader, BufWriter, ReadUntilError};
+#[cfg(feature = "futures")]
+pub use self::stream::{ByteSink, ByteStream};
+pub use self::transform::{CrlfWriter, MapBytes, NewlineReader};
+
+/// Error of a multi-byte read or write that failed part way through
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PartialError<E> {
+    /// Error reported by the serial interface
+    pub error: E,
+    /// Number of bytes transferred before the error occurred
+    pub offset: usize,
+}
+
+impl<E> PartialError<E> {
+    /// Discards the progress information, returning the underlying error
+    pub fn into_inner(self) -> E {
+        self.error
+    }
+}
+
 /// Read half of a serial interface
 pub trait AsyncRead {
     /// Read error
@@ -7,13 +33,22 @@ pub trait AsyncRead {
     /// Read byte future for polling on completion
     type ReadByteFuture<'t>: Future<Output=Result<u8, Self::Error>>;
     /// Read future for polling on completion
-    type ReadFuture<'t>: Future<Output=Result<(), Self::Error>>;
+    type ReadFuture<'t>: Future<Output=Result<(), PartialError<Self::Error>>>;
+    /// Partial read future for polling on completion
+    type ReadSomeFuture<'t>: Future<Output=Result<usize, PartialError<Self::Error>>>;
 
     /// Reads a single byte from the serial interface
     fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_>;
 
     /// Reads an array of bytes from the serial interface
+    /// On error, reports how many bytes at the start of `data` were filled.
     fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a>;
+
+    /// Reads the bytes currently available from the serial interface
+    /// Waits until at least one byte is available, then returns the number of bytes
+    /// stored at the start of `data`. Completes immediately with 0 if `data` is empty.
+    /// On error, reports how many bytes at the start of `data` were filled.
+    fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a>;
 }
 
 /// Write half of a serial interface
@@ -23,7 +58,7 @@ pub trait AsyncWrite {
     /// Write byte future for polling on completion
     type WriteByteFuture<'t>: Future<Output=Result<(), Self::Error>>;
     /// Write future for polling on completion
-    type WriteFuture<'t>: Future<Output=Result<(), Self::Error>>;
+    type WriteFuture<'t>: Future<Output=Result<(), PartialError<Self::Error>>>;
     /// Flush future for polling on completion
     type FlushFuture<'t>: Future<Output=Result<(), Self::Error>>;
 
@@ -35,14 +70,22 @@ pub trait AsyncWrite {
     /// Writes an array of bytes to the serial interface
     /// When the future completes, data may not be fully transmitted.
     /// Call `flush` to ensure that no data is left buffered.
+    /// On error, reports how many bytes of `data` were accepted by the interface.
     fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a>;
 
     /// Ensures that none of the previously written words are still buffered
     fn async_flush(&mut self) -> Self::FlushFuture<'_>;
 }
 
+/// Baud rate configuration of a serial interface
+pub trait SetBaudRate {
+    /// Switches to `baud_rate`, must only be called while the transmitter is idle
+    fn set_baud_rate(&mut self, baud_rate: u32);
+}
+
 pub mod read {
-    use crate::serial::AsyncRead;
+    use crate::serial::{AsyncRead, PartialError};
+    use crate::waker::{Event, RegisterWaker};
     use core::future::Future;
     use core::task::{Context, Poll};
     use core::pin::Pin;
@@ -52,14 +95,17 @@ pub mod read {
     /// Implementers of `embedded-hal::serial::Read` can implement this marker trait
     /// for their type. Doing so will automatically provide the default
     /// implementation of [`serial::AsyncRead`] for the type.
+    /// Futures wait for [`Event::RxNotEmpty`] while the receiver is empty.
     ///
     /// [`serial::AsyncRead`]: ../trait.AsyncRead.html
-    pub trait Default: embedded_hal::serial::Read<u8> {}
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default: embedded_hal::serial::Read<u8> + RegisterWaker {}
 
     impl<S: Default + 'static> AsyncRead for S {
         type Error = S::Error;
         type ReadByteFuture<'t> = DefaultReadByteFuture<'t, S>;
         type ReadFuture<'t> = DefaultReadFuture<'t, S>;
+        type ReadSomeFuture<'t> = DefaultReadSomeFuture<'t, S>;
 
         fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
             DefaultReadByteFuture {
@@ -74,6 +120,14 @@ pub mod read {
                 offset: 0
             }
         }
+
+        fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a> {
+            DefaultReadSomeFuture {
+                serial: self,
+                data,
+                offset: 0
+            }
+        }
     }
 
     pub struct DefaultReadByteFuture<'a, S> {
@@ -88,7 +142,7 @@ pub mod read {
                 Ok(byte) => Poll::Ready(Ok(byte)),
                 Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
                 Err(nb::Error::WouldBlock) => {
-                    cx.waker().wake_by_ref();
+                    self.serial.register_waker(Event::RxNotEmpty, cx.waker());
                     Poll::Pending
                 }
             }
@@ -102,7 +156,7 @@ pub mod read {
     }
 
     impl<'a, S: Default> Future for DefaultReadFuture<'a, S> {
-        type Output = Result<(), S::Error>;
+        type Output = Result<(), PartialError<S::Error>>;
 
         fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
             while self.offset < self.data.len() {
@@ -113,11 +167,11 @@ pub mod read {
                         self.offset += 1;
                         continue;
                     },
-                    Err(nb::Error::Other(e)) => {
-                        return Poll::Ready(Err(e));
+                    Err(nb::Error::Other(error)) => {
+                        return Poll::Ready(Err(PartialError { error, offset: self.offset }));
                     },
                     Err(nb::Error::WouldBlock) => {
-                        cx.waker().wake_by_ref();
+                        self.serial.register_waker(Event::RxNotEmpty, cx.waker());
                         return Poll::Pending;
                     }
                 }
@@ -125,10 +179,45 @@ pub mod read {
             Poll::Ready(Ok(()))
         }
     }
+
+    pub struct DefaultReadSomeFuture<'a, S> {
+        serial: &'a mut S,
+        data: &'a mut [u8],
+        offset: usize,
+    }
+
+    impl<'a, S: Default> Future for DefaultReadSomeFuture<'a, S> {
+        type Output = Result<usize, PartialError<S::Error>>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            while self.offset < self.data.len() {
+                match self.serial.read() {
+                    Ok(byte) => {
+                        let offset = self.offset;
+                        self.data[offset] = byte;
+                        self.offset += 1;
+                        continue;
+                    },
+                    Err(nb::Error::Other(error)) => {
+                        return Poll::Ready(Err(PartialError { error, offset: self.offset }));
+                    },
+                    Err(nb::Error::WouldBlock) if self.offset > 0 => {
+                        break;
+                    },
+                    Err(nb::Error::WouldBlock) => {
+                        self.serial.register_waker(Event::RxNotEmpty, cx.waker());
+                        return Poll::Pending;
+                    }
+                }
+            }
+            Poll::Ready(Ok(self.offset))
+        }
+    }
 }
 
 pub mod write {
-    use crate::serial::AsyncWrite;
+    use crate::serial::{AsyncWrite, PartialError};
+    use crate::waker::{Event, RegisterWaker};
     use core::future::Future;
     use core::task::{Context, Poll};
     use core::pin::Pin;
@@ -138,9 +227,12 @@ pub mod write {
     /// Implementers of `embedded-hal::serial::Write` can implement this marker trait
     /// for their type. Doing so will automatically provide the default
     /// implementation of [`serial::AsyncWrite`] for the type.
+    /// Write futures wait for [`Event::TxSpace`], flush futures wait for [`Event::TxIdle`].
     ///
     /// [`serial::AsyncWrite`]: ../trait.AsyncWrite.html
-    pub trait Default: embedded_hal::serial::Write<u8> {}
+    /// [`Event::TxSpace`]: ../../waker/enum.Event.html#variant.TxSpace
+    /// [`Event::TxIdle`]: ../../waker/enum.Event.html#variant.TxIdle
+    pub trait Default: embedded_hal::serial::Write<u8> + RegisterWaker {}
 
     impl<S: Default + 'static> AsyncWrite for S {
         type Error = S::Error;
@@ -159,6 +251,7 @@ pub mod write {
             DefaultWriteFuture {
                 serial: self,
                 data,
+                offset: 0,
             }
         }
 
@@ -183,7 +276,7 @@ pub mod write {
                 Ok(()) => Poll::Ready(Ok(())),
                 Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
                 Err(nb::Error::WouldBlock) => {
-                    cx.waker().wake_by_ref();
+                    self.serial.register_waker(Event::TxSpace, cx.waker());
                     Poll::Pending
                 },
             }
@@ -193,23 +286,24 @@ pub mod write {
     pub struct DefaultWriteFuture<'a, S> {
         serial: &'a mut S,
         data: &'a [u8],
+        offset: usize,
     }
 
     impl<'a, S: Default> Future for DefaultWriteFuture<'a, S> {
-        type Output = Result<(), S::Error>;
+        type Output = Result<(), PartialError<S::Error>>;
 
         fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            while let Some(byte) = self.data.first() {
+            while let Some(byte) = self.data.get(self.offset) {
                 match self.serial.write(*byte) {
                     Ok(()) => {
-                        self.data = &self.data[1..];
+                        self.offset += 1;
                         continue;
                     },
-                    Err(nb::Error::Other(e)) => {
-                        return Poll::Ready(Err(e))
+                    Err(nb::Error::Other(error)) => {
+                        return Poll::Ready(Err(PartialError { error, offset: self.offset }))
                     },
                     Err(nb::Error::WouldBlock) => {
-                        cx.waker().wake_by_ref();
+                        self.serial.register_waker(Event::TxSpace, cx.waker());
                         return Poll::Pending;
                     }
                 }
@@ -230,10 +324,181 @@ pub mod write {
                 Ok(()) => Poll::Ready(Ok(())),
                 Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
                 Err(nb::Error::WouldBlock) => {
-                    cx.waker().wake_by_ref();
+                    self.serial.register_waker(Event::TxIdle, cx.waker());
                     Poll::Pending
                 }
             }
         }
     }
 }
+
+pub mod split {
+    use crate::serial::{read, write};
+    use crate::waker::{Event, RegisterWaker};
+    use core::cell::UnsafeCell;
+    use core::fmt;
+    use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use core::task::Waker;
+
+    /// Storage for a serial interface shared by its read and write halves
+    ///
+    /// Usually placed in a `static`, so that no allocator is required.
+    /// The storage can be reused once the halves of a split are reunited or dropped.
+    pub struct Storage<S> {
+        locked: AtomicBool,
+        halves: AtomicUsize,
+        serial: UnsafeCell<Option<S>>,
+    }
+
+    unsafe impl<S: Send> Sync for Storage<S> {}
+
+    impl<S> Storage<S> {
+        pub const fn new() -> Self {
+            Self {
+                locked: AtomicBool::new(false),
+                halves: AtomicUsize::new(0),
+                serial: UnsafeCell::new(None),
+            }
+        }
+
+        /// Runs `f` on the slot holding the serial interface
+        ///
+        /// The lock is never held for longer than a single non-blocking call,
+        /// so it is waited for by spinning.
+        fn with_slot<R>(&self, f: impl FnOnce(&mut Option<S>) -> R) -> R {
+            while self.locked.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {}
+            let result = f(unsafe { &mut *self.serial.get() });
+            self.locked.store(false, Ordering::Release);
+            result
+        }
+
+        /// Runs `f` on the serial interface, which is present as long as both halves are
+        fn with<R>(&self, f: impl FnOnce(&mut S) -> R) -> R {
+            self.with_slot(|slot| f(slot.as_mut().expect("serial interface was taken")))
+        }
+
+        /// Drops the serial interface once both halves are gone
+        fn release_half(&self) {
+            if self.halves.fetch_sub(1, Ordering::AcqRel) == 1 {
+                self.with_slot(|slot| *slot = None);
+            }
+        }
+    }
+
+    /// Moves `serial` into `storage` and returns independently owned halves
+    ///
+    /// The halves implement [`AsyncRead`] and [`AsyncWrite`] through the default
+    /// implementations and can be used from concurrent tasks.
+    /// The interface is only locked for the duration of a single non-blocking call,
+    /// never while a future is pending. A half that finds it locked spins until the
+    /// other half is done, so the halves must not be used from an interrupt handler
+    /// that can preempt a user of the other half.
+    ///
+    /// Dropping both halves drops the serial interface, after which `storage`
+    /// can be used for another split.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `storage` still holds the serial interface of an earlier split.
+    ///
+    /// [`AsyncRead`]: ../trait.AsyncRead.html
+    /// [`AsyncWrite`]: ../trait.AsyncWrite.html
+    pub fn split<S>(serial: S, storage: &'static Storage<S>) -> (ReadHalf<S>, WriteHalf<S>) {
+        let in_use = storage.with_slot(|slot| {
+            let in_use = slot.is_some();
+            if !in_use {
+                *slot = Some(serial);
+            }
+            in_use
+        });
+        assert!(!in_use, "storage is already in use");
+        storage.halves.store(2, Ordering::Release);
+
+        (ReadHalf { storage }, WriteHalf { storage })
+    }
+
+    /// Error returned when trying to reunite halves of different serial interfaces
+    pub struct ReuniteError<S: 'static>(pub ReadHalf<S>, pub WriteHalf<S>);
+
+    impl<S> fmt::Debug for ReuniteError<S> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("ReuniteError")
+        }
+    }
+
+    /// Read half of a split serial interface
+    pub struct ReadHalf<S: 'static> {
+        storage: &'static Storage<S>,
+    }
+
+    impl<S> ReadHalf<S> {
+        /// Puts the halves back together, returning the original serial interface
+        pub fn reunite(self, other: WriteHalf<S>) -> Result<S, ReuniteError<S>> {
+            if !core::ptr::eq(self.storage, other.storage) {
+                return Err(ReuniteError(self, other));
+            }
+            // Both halves are consumed, dropping them frees the storage
+            Ok(self.storage.with_slot(Option::take).unwrap())
+        }
+    }
+
+    impl<S> Drop for ReadHalf<S> {
+        fn drop(&mut self) {
+            self.storage.release_half();
+        }
+    }
+
+    impl<S: embedded_hal::serial::Read<u8>> embedded_hal::serial::Read<u8> for ReadHalf<S> {
+        type Error = S::Error;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            self.storage.with(|s| s.read())
+        }
+    }
+
+    impl<S: RegisterWaker> RegisterWaker for ReadHalf<S> {
+        fn register_waker(&self, event: Event, waker: &Waker) {
+            self.storage.with(|s| s.register_waker(event, waker));
+        }
+    }
+
+    impl<S: read::Default> read::Default for ReadHalf<S> {}
+
+    /// Write half of a split serial interface
+    pub struct WriteHalf<S: 'static> {
+        storage: &'static Storage<S>,
+    }
+
+    impl<S> WriteHalf<S> {
+        /// Puts the halves back together, returning the original serial interface
+        pub fn reunite(self, other: ReadHalf<S>) -> Result<S, ReuniteError<S>> {
+            other.reunite(self)
+        }
+    }
+
+    impl<S> Drop for WriteHalf<S> {
+        fn drop(&mut self) {
+            self.storage.release_half();
+        }
+    }
+
+    impl<S: embedded_hal::serial::Write<u8>> embedded_hal::serial::Write<u8> for WriteHalf<S> {
+        type Error = S::Error;
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            self.storage.with(|s| s.write(byte))
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            self.storage.with(|s| s.flush())
+        }
+    }
+
+    impl<S: RegisterWaker> RegisterWaker for WriteHalf<S> {
+        fn register_waker(&self, event: Event, waker: &Waker) {
+            self.storage.with(|s| s.register_waker(event, waker));
+        }
+    }
+
+    impl<S: write::Default> write::Default for WriteHalf<S> {}
+}
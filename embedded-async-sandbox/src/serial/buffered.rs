@@ -0,0 +1,487 @@
+use crate::reborrow::Reborrow;
+use crate::serial::{AsyncRead, AsyncWrite, PartialError};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+struct Ring<const N: usize> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> Ring<N> {
+    fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Copies as much of `data` as fits, returns the number of bytes copied
+    fn extend(&mut self, data: &[u8]) -> usize {
+        let count = core::cmp::min(data.len(), N - self.len);
+        for (i, byte) in data[..count].iter().enumerate() {
+            self.buf[(self.head + self.len + i) % N] = *byte;
+        }
+        self.len += count;
+        count
+    }
+
+    /// Returns the oldest buffered bytes that are stored contiguously
+    fn front(&self) -> &[u8] {
+        let end = core::cmp::min(self.head + self.len, N);
+        &self.buf[self.head..end]
+    }
+
+    fn consume(&mut self, count: usize) {
+        self.head = (self.head + count) % N;
+        self.len -= count;
+        if self.len == 0 {
+            self.head = 0;
+        }
+    }
+}
+
+/// Write adapter that collects bytes in a ring buffer of `N` bytes
+///
+/// Writes complete immediately while there is space in the buffer. Buffered bytes
+/// are written to the inner writer when the buffer fills up or on `async_flush`,
+/// which also flushes the inner writer.
+pub struct BufWriter<W, const N: usize> {
+    inner: W,
+    ring: Ring<N>,
+}
+
+impl<W: AsyncWrite, const N: usize> BufWriter<W, N> {
+    pub fn new(inner: W) -> Self {
+        assert!(N > 0, "buffer capacity must not be zero");
+        Self {
+            inner,
+            ring: Ring::new(),
+        }
+    }
+
+    /// Returns the number of bytes waiting in the buffer
+    pub fn buffered(&self) -> usize {
+        self.ring.len
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns the inner writer, discarding any buffered bytes
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + 'static, const N: usize> AsyncWrite for BufWriter<W, N> {
+    type Error = W::Error;
+    type WriteByteFuture<'t> = BufWriteByteFuture<'t, W, N>;
+    type WriteFuture<'t> = BufWriteFuture<'t, W, N>;
+    type FlushFuture<'t> = BufFlushFuture<'t, W, N>;
+
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+        BufWriteByteFuture {
+            drain: Drain::new(self),
+            byte,
+        }
+    }
+
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        BufWriteFuture {
+            drain: Drain::new(self),
+            data,
+            offset: 0,
+        }
+    }
+
+    fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+        BufFlushFuture {
+            drain: Drain::new(self),
+            flush: None,
+        }
+    }
+}
+
+/// Moves buffered bytes to the inner writer, one at a time
+///
+/// Every byte leaves the ring buffer as soon as the inner writer has accepted it,
+/// so a cancelled write or flush only leaves the bytes not written yet in the buffer.
+struct Drain<'a, W: AsyncWrite + 'a, const N: usize> {
+    inner: Reborrow<'a, W>,
+    ring: &'a mut Ring<N>,
+    write: Option<W::WriteByteFuture<'a>>,
+}
+
+impl<'a, W: AsyncWrite + 'a, const N: usize> Drain<'a, W, N> {
+    fn new(writer: &'a mut BufWriter<W, N>) -> Self {
+        let BufWriter { inner, ring } = writer;
+        Self {
+            inner: Reborrow::new(inner),
+            ring,
+            write: None,
+        }
+    }
+
+    /// Starts flushing the inner writer, which stays with the returned future
+    fn flush(&mut self) -> W::FlushFuture<'a> {
+        self.inner.lend().async_flush()
+    }
+
+    /// Writes buffered bytes to the inner writer until at most `len` bytes are left
+    fn poll_drain(self: Pin<&mut Self>, cx: &mut Context<'_>, len: usize) -> Poll<Result<(), W::Error>> {
+        // `write` is structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let mut write = unsafe { Pin::new_unchecked(&mut this.write) };
+            if let Some(future) = write.as_mut().as_pin_mut() {
+                let result = match future.poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                };
+                write.set(None);
+                // The write that borrowed the inner writer is gone
+                unsafe { this.inner.give_back() };
+
+                if let Err(e) = result {
+                    return Poll::Ready(Err(e));
+                }
+                this.ring.consume(1);
+            }
+
+            if this.ring.len <= len {
+                return Poll::Ready(Ok(()));
+            }
+            let byte = this.ring.front()[0];
+            this.write = Some(this.inner.lend().async_write_byte(byte));
+        }
+    }
+}
+
+pub struct BufWriteByteFuture<'a, W: AsyncWrite + 'a, const N: usize> {
+    drain: Drain<'a, W, N>,
+    byte: u8,
+}
+
+impl<'a, W: AsyncWrite + 'a, const N: usize> Future for BufWriteByteFuture<'a, W, N> {
+    type Output = Result<(), W::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut drain = unsafe { Pin::new_unchecked(&mut this.drain) };
+        match drain.as_mut().poll_drain(cx, N - 1) {
+            Poll::Ready(Ok(())) => {
+                let byte = this.byte;
+                this.drain.ring.extend(&[byte]);
+                Poll::Ready(Ok(()))
+            },
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct BufWriteFuture<'a, W: AsyncWrite + 'a, const N: usize> {
+    drain: Drain<'a, W, N>,
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a, W: AsyncWrite + 'a, const N: usize> Future for BufWriteFuture<'a, W, N> {
+    type Output = Result<(), PartialError<W::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let mut drain = unsafe { Pin::new_unchecked(&mut this.drain) };
+            match drain.as_mut().poll_drain(cx, N - 1) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(error)) => {
+                    return Poll::Ready(Err(PartialError { error, offset: this.offset }));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let data = this.data;
+            this.offset += this.drain.ring.extend(&data[this.offset..]);
+            if this.offset == data.len() {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+pub struct BufFlushFuture<'a, W: AsyncWrite + 'a, const N: usize> {
+    drain: Drain<'a, W, N>,
+    flush: Option<W::FlushFuture<'a>>,
+}
+
+impl<'a, W: AsyncWrite + 'a, const N: usize> Future for BufFlushFuture<'a, W, N> {
+    type Output = Result<(), W::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.flush.is_none() {
+            let drain = unsafe { Pin::new_unchecked(&mut this.drain) };
+            match drain.poll_drain(cx, 0) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+            this.flush = Some(this.drain.flush());
+        }
+
+        let flush = unsafe { Pin::new_unchecked(&mut this.flush) };
+        flush.as_pin_mut().unwrap().poll(cx)
+    }
+}
+
+/// Error of a delimiter-based read from a [`BufReader`]
+///
+/// [`BufReader`]: struct.BufReader.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReadUntilError<E> {
+    /// The buffer filled up before the delimiter was found
+    ///
+    /// The buffered bytes are discarded, the rest of the record
+    /// is returned by the next read.
+    Overflow,
+    /// The inner reader failed
+    Other(E),
+}
+
+struct Buffer<const N: usize> {
+    data: [u8; N],
+    pos: usize,
+    filled: usize,
+}
+
+impl<const N: usize> Buffer<N> {
+    fn new() -> Self {
+        Self {
+            data: [0; N],
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.pos = 0;
+        self.filled = 0;
+    }
+
+    /// Moves unconsumed bytes to the start of the buffer
+    fn compact(&mut self) {
+        self.data.copy_within(self.pos..self.filled, 0);
+        self.filled -= self.pos;
+        self.pos = 0;
+    }
+}
+
+/// Read adapter that collects bytes in a buffer of `N` bytes
+///
+/// Adds delimiter-based reads on top of [`AsyncRead`]. Records longer than
+/// the buffer are reported as [`ReadUntilError::Overflow`].
+///
+/// [`AsyncRead`]: trait.AsyncRead.html
+/// [`ReadUntilError::Overflow`]: enum.ReadUntilError.html#variant.Overflow
+pub struct BufReader<R, const N: usize> {
+    inner: R,
+    buf: Buffer<N>,
+}
+
+impl<R: AsyncRead, const N: usize> BufReader<R, N> {
+    pub fn new(inner: R) -> Self {
+        assert!(N > 0, "buffer capacity must not be zero");
+        Self {
+            inner,
+            buf: Buffer::new(),
+        }
+    }
+
+    /// Returns the buffered bytes, reading from the inner reader if the buffer is empty
+    pub fn async_fill_buf(&mut self) -> FillBufFuture<'_, R, N> {
+        FillBufFuture {
+            fill: Fill::new(self),
+        }
+    }
+
+    /// Marks `amount` buffered bytes as read
+    pub fn consume(&mut self, amount: usize) {
+        self.buf.pos = core::cmp::min(self.buf.pos + amount, self.buf.filled);
+    }
+
+    /// Reads until `delim` is found, returns the bytes up to and including the delimiter
+    pub fn async_read_until(&mut self, delim: u8) -> ReadUntilFuture<'_, R, N> {
+        ReadUntilFuture {
+            fill: Fill::new(self),
+            delim,
+        }
+    }
+
+    /// Reads a line terminated by `\n`, returns it including the terminator
+    pub fn async_read_line(&mut self) -> ReadUntilFuture<'_, R, N> {
+        self.async_read_until(b'\n')
+    }
+
+    /// Returns the bytes waiting in the buffer
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf.data[self.buf.pos..self.buf.filled]
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns the inner reader, discarding any buffered bytes
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Reads from the inner reader into the free space at the end of the buffer
+///
+/// The inner reader and the buffer are lent out separately,
+/// so that an in-flight read can borrow both of them while the fill lives in the same future.
+struct Fill<'a, R: AsyncRead + 'a, const N: usize> {
+    inner: Reborrow<'a, R>,
+    buf: Reborrow<'a, Buffer<N>>,
+    read: Option<R::ReadSomeFuture<'a>>,
+}
+
+impl<'a, R: AsyncRead + 'a, const N: usize> Fill<'a, R, N> {
+    fn new(reader: &'a mut BufReader<R, N>) -> Self {
+        let BufReader { inner, buf } = reader;
+        Self {
+            inner: Reborrow::new(inner),
+            buf: Reborrow::new(buf),
+            read: None,
+        }
+    }
+
+    /// Returns the buffer, must not be called while a read is in flight
+    fn buf(&mut self) -> &mut Buffer<N> {
+        self.buf.get()
+    }
+
+    /// Lends out the buffer for the result of the future, no more reads can be started
+    fn lend_buf(&mut self) -> &'a Buffer<N> {
+        self.buf.lend()
+    }
+
+    /// Starts reading into the free space of the buffer
+    fn start(&mut self) {
+        let buf = self.buf.lend();
+        self.read = Some(self.inner.lend().async_read_some(&mut buf.data[buf.filled..]));
+    }
+
+    /// Completes the read started by `start`, if any
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), R::Error>> {
+        // `read` is structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut read = unsafe { Pin::new_unchecked(&mut this.read) };
+        let result = match read.as_mut().as_pin_mut() {
+            Some(future) => match future.poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            },
+            None => return Poll::Ready(Ok(())),
+        };
+        read.set(None);
+        // The read that borrowed them is gone
+        unsafe {
+            this.inner.give_back();
+            this.buf.give_back();
+        }
+
+        match result {
+            Ok(count) => {
+                this.buf().filled += count;
+                Poll::Ready(Ok(()))
+            },
+            Err(PartialError { error, offset }) => {
+                this.buf().filled += offset;
+                Poll::Ready(Err(error))
+            },
+        }
+    }
+}
+
+pub struct FillBufFuture<'a, R: AsyncRead + 'a, const N: usize> {
+    fill: Fill<'a, R, N>,
+}
+
+impl<'a, R: AsyncRead + 'a, const N: usize> Future for FillBufFuture<'a, R, N> {
+    type Output = Result<&'a [u8], R::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let fill = unsafe { Pin::new_unchecked(&mut this.fill) };
+            match fill.poll_read(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let buf = this.fill.buf();
+            if buf.pos < buf.filled {
+                let buf = this.fill.lend_buf();
+                return Poll::Ready(Ok(&buf.data[buf.pos..buf.filled]));
+            }
+            buf.clear();
+            this.fill.start();
+        }
+    }
+}
+
+pub struct ReadUntilFuture<'a, R: AsyncRead + 'a, const N: usize> {
+    fill: Fill<'a, R, N>,
+    delim: u8,
+}
+
+impl<'a, R: AsyncRead + 'a, const N: usize> Future for ReadUntilFuture<'a, R, N> {
+    type Output = Result<&'a [u8], ReadUntilError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let fill = unsafe { Pin::new_unchecked(&mut this.fill) };
+            match fill.poll_read(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(ReadUntilError::Other(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let delim = this.delim;
+            let buf = this.fill.buf();
+            let start = buf.pos;
+            if let Some(i) = buf.data[start..buf.filled].iter().position(|b| *b == delim) {
+                buf.pos += i + 1;
+                let buf = this.fill.lend_buf();
+                return Poll::Ready(Ok(&buf.data[start..buf.pos]));
+            }
+
+            if buf.filled - buf.pos == N {
+                buf.clear();
+                return Poll::Ready(Err(ReadUntilError::Overflow));
+            }
+            if buf.filled == N {
+                buf.compact();
+            }
+            this.fill.start();
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::reborrow::Reborrow;
+use crate::serial::{AsyncRead, AsyncWrite};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_sink::Sink;
+
+/// Stream of the bytes received by a serial interface
+///
+/// Errors are yielded as items and do not end the stream, which never ends by itself.
+/// The stream is not `Unpin` unless the read future of `R` is, otherwise it has to be pinned
+/// to be used with `StreamExt::next`.
+pub struct ByteStream<'a, R: AsyncRead + 'a> {
+    reader: Reborrow<'a, R>,
+    read: Option<R::ReadByteFuture<'a>>,
+}
+
+impl<'a, R: AsyncRead + 'a> ByteStream<'a, R> {
+    pub fn new(reader: &'a mut R) -> Self {
+        Self {
+            reader: Reborrow::new(reader),
+            read: None,
+        }
+    }
+}
+
+impl<'a, R: AsyncRead + 'a> Stream for ByteStream<'a, R> {
+    type Item = Result<u8, R::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The future in `read` is structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.read.is_none() {
+            this.read = Some(this.reader.lend().async_read_byte());
+        }
+
+        let read = unsafe { Pin::new_unchecked(this.read.as_mut().unwrap()) };
+        match read.poll(cx) {
+            Poll::Ready(result) => {
+                this.read = None;
+                // The read that borrowed the reader is gone
+                unsafe { this.reader.give_back() };
+                Poll::Ready(Some(result))
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+enum Step<'a, W: AsyncWrite + 'a> {
+    Idle,
+    Write(W::WriteByteFuture<'a>),
+    Flush(W::FlushFuture<'a>),
+}
+
+/// Sink for the bytes transmitted by a serial interface
+///
+/// A single byte is in flight at a time: `poll_ready` waits until the interface has accepted
+/// the previous byte, which gives the backpressure of the transmitter to the sender.
+/// Flushing and closing flush the interface, closing does not make the sink unusable.
+/// The sink is not `Unpin` unless the write and flush futures of `W` are, otherwise it has
+/// to be pinned to be used with `SinkExt::send`.
+pub struct ByteSink<'a, W: AsyncWrite + 'a> {
+    writer: Reborrow<'a, W>,
+    step: Step<'a, W>,
+}
+
+impl<'a, W: AsyncWrite + 'a> ByteSink<'a, W> {
+    pub fn new(writer: &'a mut W) -> Self {
+        Self {
+            writer: Reborrow::new(writer),
+            step: Step::Idle,
+        }
+    }
+
+    /// Completes the write or flush in flight, if any
+    ///
+    /// The futures in `step` are structurally pinned, so `self` must be pinned.
+    fn poll_step(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), W::Error>> {
+        let result = match &mut self.step {
+            Step::Idle => return Poll::Ready(Ok(())),
+            Step::Write(future) => unsafe { Pin::new_unchecked(future) }.poll(cx),
+            Step::Flush(future) => unsafe { Pin::new_unchecked(future) }.poll(cx),
+        };
+        if result.is_ready() {
+            self.step = Step::Idle;
+            // The write or flush that borrowed the writer is gone
+            unsafe { self.writer.give_back() };
+        }
+        result
+    }
+}
+
+impl<'a, W: AsyncWrite + 'a> Sink<u8> for ByteSink<'a, W> {
+    type Error = W::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.poll_step(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, byte: u8) -> Result<(), Self::Error> {
+        let this = unsafe { self.get_unchecked_mut() };
+        assert!(matches!(this.step, Step::Idle), "start_send called before poll_ready");
+        this.step = Step::Write(this.writer.lend().async_write_byte(byte));
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let flushing = matches!(this.step, Step::Flush(_));
+            match this.poll_step(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+            if flushing {
+                return Poll::Ready(Ok(()));
+            }
+            this.step = Step::Flush(this.writer.lend().async_flush());
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
@@ -0,0 +1,621 @@
+use crate::reborrow::Reborrow;
+use crate::serial::{AsyncRead, AsyncWrite, PartialError};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Writes bytes one at a time through the inner writer
+///
+/// The inner writer is lent to every write in turn, so that a new write can be started
+/// after the previous one completed without borrowing the future itself.
+struct ByteWriter<'a, W: AsyncWrite + 'a> {
+    inner: Reborrow<'a, W>,
+    write: Option<W::WriteByteFuture<'a>>,
+}
+
+impl<'a, W: AsyncWrite + 'a> ByteWriter<'a, W> {
+    fn new(inner: &'a mut W) -> Self {
+        Self {
+            inner: Reborrow::new(inner),
+            write: None,
+        }
+    }
+
+    /// Writes `byte`, must be called with the same byte until it returns `Ready`
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, byte: u8) -> Poll<Result<(), W::Error>> {
+        // `write` is structurally pinned, the writer is never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.write.is_none() {
+            this.write = Some(this.inner.lend().async_write_byte(byte));
+        }
+
+        let mut write = unsafe { Pin::new_unchecked(&mut this.write) };
+        match write.as_mut().as_pin_mut().unwrap().poll(cx) {
+            Poll::Ready(result) => {
+                write.set(None);
+                // The write that borrowed the writer is gone
+                unsafe { this.inner.give_back() };
+                Poll::Ready(result)
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Adapter applying a function to every byte passing through it
+///
+/// Bytes are transformed before being written and after being read.
+/// Writes go through the inner writer one byte at a time.
+pub struct MapBytes<S, F> {
+    inner: S,
+    f: F,
+}
+
+impl<S, F: FnMut(u8) -> u8> MapBytes<S, F> {
+    pub fn new(inner: S, f: F) -> Self {
+        Self {
+            inner,
+            f,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + 'static, F: FnMut(u8) -> u8 + 'static> AsyncWrite for MapBytes<W, F> {
+    type Error = W::Error;
+    type WriteByteFuture<'t> = W::WriteByteFuture<'t>;
+    type WriteFuture<'t> = MapWriteFuture<'t, W, F>;
+    type FlushFuture<'t> = W::FlushFuture<'t>;
+
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+        let byte = (self.f)(byte);
+        self.inner.async_write_byte(byte)
+    }
+
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        MapWriteFuture {
+            writer: ByteWriter::new(&mut self.inner),
+            f: &mut self.f,
+            data,
+            offset: 0,
+            mapped: None,
+        }
+    }
+
+    fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+        self.inner.async_flush()
+    }
+}
+
+pub struct MapWriteFuture<'a, W: AsyncWrite + 'a, F> {
+    writer: ByteWriter<'a, W>,
+    f: &'a mut F,
+    data: &'a [u8],
+    offset: usize,
+    mapped: Option<u8>,
+}
+
+impl<'a, W: AsyncWrite + 'a, F: FnMut(u8) -> u8> Future for MapWriteFuture<'a, W, F> {
+    type Output = Result<(), PartialError<W::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        while let Some(byte) = this.data.get(this.offset) {
+            // Map every byte exactly once, `f` may be stateful
+            let f = &mut this.f;
+            let mapped = *this.mapped.get_or_insert_with(|| f(*byte));
+
+            let writer = unsafe { Pin::new_unchecked(&mut this.writer) };
+            match writer.poll_write(cx, mapped) {
+                Poll::Ready(Ok(())) => {
+                    this.mapped = None;
+                    this.offset += 1;
+                },
+                Poll::Ready(Err(error)) => {
+                    return Poll::Ready(Err(PartialError { error, offset: this.offset }));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + 'static, F: FnMut(u8) -> u8 + 'static> AsyncRead for MapBytes<R, F> {
+    type Error = R::Error;
+    type ReadByteFuture<'t> = MapReadByteFuture<'t, R, F>;
+    type ReadFuture<'t> = MapReadFuture<'t, R, F>;
+    type ReadSomeFuture<'t> = MapReadSomeFuture<'t, R, F>;
+
+    fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
+        MapReadByteFuture {
+            read: self.inner.async_read_byte(),
+            f: &mut self.f,
+        }
+    }
+
+    fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        // `data` is lent to the inner read, the bytes are mapped once it completes
+        let len = data.len();
+        let mut data = Reborrow::new(data);
+        MapReadFuture {
+            read: Some(self.inner.async_read(data.lend())),
+            f: &mut self.f,
+            data,
+            len,
+        }
+    }
+
+    fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a> {
+        let mut data = Reborrow::new(data);
+        MapReadSomeFuture {
+            read: Some(self.inner.async_read_some(data.lend())),
+            f: &mut self.f,
+            data,
+        }
+    }
+}
+
+pub struct MapReadByteFuture<'a, R: AsyncRead + 'a, F> {
+    read: R::ReadByteFuture<'a>,
+    f: &'a mut F,
+}
+
+impl<'a, R: AsyncRead + 'a, F: FnMut(u8) -> u8> Future for MapReadByteFuture<'a, R, F> {
+    type Output = Result<u8, R::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `read` is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let read = unsafe { Pin::new_unchecked(&mut this.read) };
+        match read.poll(cx) {
+            Poll::Ready(result) => Poll::Ready(result.map(|byte| (this.f)(byte))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Applies `f` to the first `count` bytes of `data` once the inner read is gone
+fn map_in_place<F: FnMut(u8) -> u8>(f: &mut F, data: &mut Reborrow<'_, [u8]>, count: usize) {
+    // The read that borrowed the buffer has been dropped
+    unsafe { data.give_back() };
+    for byte in &mut data.get()[..count] {
+        *byte = f(*byte);
+    }
+}
+
+pub struct MapReadFuture<'a, R: AsyncRead + 'a, F> {
+    read: Option<R::ReadFuture<'a>>,
+    f: &'a mut F,
+    data: Reborrow<'a, [u8]>,
+    len: usize,
+}
+
+impl<'a, R: AsyncRead + 'a, F: FnMut(u8) -> u8> Future for MapReadFuture<'a, R, F> {
+    type Output = Result<(), PartialError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `read` is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut read = unsafe { Pin::new_unchecked(&mut this.read) };
+        let result = match read.as_mut().as_pin_mut().expect("polled after completion").poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        read.set(None);
+
+        let count = match &result {
+            Ok(()) => this.len,
+            Err(e) => e.offset,
+        };
+        map_in_place(this.f, &mut this.data, count);
+        Poll::Ready(result)
+    }
+}
+
+pub struct MapReadSomeFuture<'a, R: AsyncRead + 'a, F> {
+    read: Option<R::ReadSomeFuture<'a>>,
+    f: &'a mut F,
+    data: Reborrow<'a, [u8]>,
+}
+
+impl<'a, R: AsyncRead + 'a, F: FnMut(u8) -> u8> Future for MapReadSomeFuture<'a, R, F> {
+    type Output = Result<usize, PartialError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `read` is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut read = unsafe { Pin::new_unchecked(&mut this.read) };
+        let result = match read.as_mut().as_pin_mut().expect("polled after completion").poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        read.set(None);
+
+        let count = match &result {
+            Ok(count) => *count,
+            Err(e) => e.offset,
+        };
+        map_in_place(this.f, &mut this.data, count);
+        Poll::Ready(result)
+    }
+}
+
+/// Write adapter translating `\n` into `\r\n`
+pub struct CrlfWriter<W> {
+    inner: W,
+}
+
+impl<W: AsyncWrite> CrlfWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + 'static> AsyncWrite for CrlfWriter<W> {
+    type Error = W::Error;
+    type WriteByteFuture<'t> = CrlfWriteByteFuture<'t, W>;
+    type WriteFuture<'t> = CrlfWriteFuture<'t, W>;
+    type FlushFuture<'t> = W::FlushFuture<'t>;
+
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+        CrlfWriteByteFuture {
+            writer: ByteWriter::new(&mut self.inner),
+            byte,
+            cr_sent: false,
+        }
+    }
+
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        CrlfWriteFuture {
+            writer: ByteWriter::new(&mut self.inner),
+            data,
+            offset: 0,
+            cr_sent: false,
+        }
+    }
+
+    fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+        self.inner.async_flush()
+    }
+}
+
+/// Reads are passed through unchanged
+impl<R: AsyncRead + 'static> AsyncRead for CrlfWriter<R> {
+    type Error = R::Error;
+    type ReadByteFuture<'t> = R::ReadByteFuture<'t>;
+    type ReadFuture<'t> = R::ReadFuture<'t>;
+    type ReadSomeFuture<'t> = R::ReadSomeFuture<'t>;
+
+    fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
+        self.inner.async_read_byte()
+    }
+
+    fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        self.inner.async_read(data)
+    }
+
+    fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a> {
+        self.inner.async_read_some(data)
+    }
+}
+
+/// Writes `byte`, preceded by `\r` if it is `\n`
+fn poll_write_crlf<'a, W: AsyncWrite + 'a>(
+    writer: Pin<&mut ByteWriter<'a, W>>,
+    cx: &mut Context<'_>,
+    byte: u8,
+    cr_sent: &mut bool,
+) -> Poll<Result<(), W::Error>> {
+    let mut writer = writer;
+    if byte == b'\n' && !*cr_sent {
+        match writer.as_mut().poll_write(cx, b'\r') {
+            Poll::Ready(Ok(())) => *cr_sent = true,
+            other => return other,
+        }
+    }
+
+    match writer.poll_write(cx, byte) {
+        Poll::Ready(result) => {
+            *cr_sent = false;
+            Poll::Ready(result)
+        },
+        Poll::Pending => Poll::Pending,
+    }
+}
+
+pub struct CrlfWriteByteFuture<'a, W: AsyncWrite + 'a> {
+    writer: ByteWriter<'a, W>,
+    byte: u8,
+    cr_sent: bool,
+}
+
+impl<'a, W: AsyncWrite + 'a> Future for CrlfWriteByteFuture<'a, W> {
+    type Output = Result<(), W::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let writer = unsafe { Pin::new_unchecked(&mut this.writer) };
+        poll_write_crlf(writer, cx, this.byte, &mut this.cr_sent)
+    }
+}
+
+pub struct CrlfWriteFuture<'a, W: AsyncWrite + 'a> {
+    writer: ByteWriter<'a, W>,
+    data: &'a [u8],
+    offset: usize,
+    cr_sent: bool,
+}
+
+impl<'a, W: AsyncWrite + 'a> Future for CrlfWriteFuture<'a, W> {
+    type Output = Result<(), PartialError<W::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        while let Some(byte) = this.data.get(this.offset) {
+            let writer = unsafe { Pin::new_unchecked(&mut this.writer) };
+            match poll_write_crlf(writer, cx, *byte, &mut this.cr_sent) {
+                Poll::Ready(Ok(())) => this.offset += 1,
+                Poll::Ready(Err(error)) => {
+                    return Poll::Ready(Err(PartialError { error, offset: this.offset }));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Read adapter normalizing `\r\n` and lone `\r` into `\n`
+pub struct NewlineReader<R> {
+    inner: R,
+    last_cr: bool,
+}
+
+impl<R: AsyncRead> NewlineReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            last_cr: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + 'static> AsyncRead for NewlineReader<R> {
+    type Error = R::Error;
+    type ReadByteFuture<'t> = NewlineReadByteFuture<'t, R>;
+    type ReadFuture<'t> = NewlineReadFuture<'t, R>;
+    type ReadSomeFuture<'t> = NewlineReadSomeFuture<'t, R>;
+
+    fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
+        NewlineReadByteFuture {
+            inner: Reborrow::new(&mut self.inner),
+            read: None,
+            last_cr: &mut self.last_cr,
+        }
+    }
+
+    fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        NewlineReadFuture {
+            normalize: Normalize::new(self, data),
+            filled: 0,
+        }
+    }
+
+    fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a> {
+        NewlineReadSomeFuture {
+            normalize: Normalize::new(self, data),
+        }
+    }
+}
+
+/// Writes are passed through unchanged
+impl<W: AsyncWrite + 'static> AsyncWrite for NewlineReader<W> {
+    type Error = W::Error;
+    type WriteByteFuture<'t> = W::WriteByteFuture<'t>;
+    type WriteFuture<'t> = W::WriteFuture<'t>;
+    type FlushFuture<'t> = W::FlushFuture<'t>;
+
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+        self.inner.async_write_byte(byte)
+    }
+
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        self.inner.async_write(data)
+    }
+
+    fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+        self.inner.async_flush()
+    }
+}
+
+/// Translates `byte` in place, returns `false` if it must be dropped
+fn normalize_byte(byte: &mut u8, last_cr: &mut bool) -> bool {
+    let keep = !(*byte == b'\n' && *last_cr);
+    *last_cr = *byte == b'\r';
+    if *last_cr {
+        *byte = b'\n';
+    }
+    keep
+}
+
+pub struct NewlineReadByteFuture<'a, R: AsyncRead + 'a> {
+    inner: Reborrow<'a, R>,
+    read: Option<R::ReadByteFuture<'a>>,
+    last_cr: &'a mut bool,
+}
+
+impl<'a, R: AsyncRead + 'a> Future for NewlineReadByteFuture<'a, R> {
+    type Output = Result<u8, R::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `read` is structurally pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if this.read.is_none() {
+                this.read = Some(this.inner.lend().async_read_byte());
+            }
+
+            let mut read = unsafe { Pin::new_unchecked(&mut this.read) };
+            let result = match read.as_mut().as_pin_mut().unwrap().poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            read.set(None);
+            // The read that borrowed the reader is gone
+            unsafe { this.inner.give_back() };
+
+            let mut byte = match result {
+                Ok(byte) => byte,
+                Err(e) => return Poll::Ready(Err(e)),
+            };
+            if normalize_byte(&mut byte, this.last_cr) {
+                return Poll::Ready(Ok(byte));
+            }
+        }
+    }
+}
+
+/// Reads into a buffer and normalizes the received bytes in place
+struct Normalize<'a, R: AsyncRead + 'a> {
+    inner: Reborrow<'a, R>,
+    data: Reborrow<'a, [u8]>,
+    len: usize,
+    read: Option<R::ReadSomeFuture<'a>>,
+    last_cr: &'a mut bool,
+}
+
+impl<'a, R: AsyncRead + 'a> Normalize<'a, R> {
+    fn new(reader: &'a mut NewlineReader<R>, data: &'a mut [u8]) -> Self {
+        Self {
+            inner: Reborrow::new(&mut reader.inner),
+            len: data.len(),
+            data: Reborrow::new(data),
+            read: None,
+            last_cr: &mut reader.last_cr,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Reads at least one byte into `data[start..]`, returns the number of normalized bytes
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, start: usize) -> Poll<Result<usize, PartialError<R::Error>>> {
+        // `read` is structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            if this.read.is_none() {
+                let data = &mut this.data.lend()[start..];
+                this.read = Some(this.inner.lend().async_read_some(data));
+            }
+
+            let mut read = unsafe { Pin::new_unchecked(&mut this.read) };
+            let result = match read.as_mut().as_pin_mut().unwrap().poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            };
+            read.set(None);
+            // The read that borrowed them is gone
+            unsafe {
+                this.inner.give_back();
+                this.data.give_back();
+            }
+
+            let (count, error) = match result {
+                Ok(count) => (count, None),
+                Err(PartialError { error, offset }) => (offset, Some(error)),
+            };
+
+            // Compact the received bytes, dropping the `\n` of every `\r\n`
+            let data = &mut this.data.get()[start..start + count];
+            let mut kept = 0;
+            for i in 0..count {
+                let mut byte = data[i];
+                if normalize_byte(&mut byte, this.last_cr) {
+                    data[kept] = byte;
+                    kept += 1;
+                }
+            }
+
+            match error {
+                Some(error) => return Poll::Ready(Err(PartialError { error, offset: start + kept })),
+                None if kept > 0 || count == 0 => return Poll::Ready(Ok(kept)),
+                None => {},
+            }
+        }
+    }
+}
+
+pub struct NewlineReadFuture<'a, R: AsyncRead + 'a> {
+    normalize: Normalize<'a, R>,
+    filled: usize,
+}
+
+impl<'a, R: AsyncRead + 'a> Future for NewlineReadFuture<'a, R> {
+    type Output = Result<(), PartialError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        while this.filled < this.normalize.len() {
+            let normalize = unsafe { Pin::new_unchecked(&mut this.normalize) };
+            match normalize.poll_read(cx, this.filled) {
+                Poll::Ready(Ok(count)) => this.filled += count,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct NewlineReadSomeFuture<'a, R: AsyncRead + 'a> {
+    normalize: Normalize<'a, R>,
+}
+
+impl<'a, R: AsyncRead + 'a> Future for NewlineReadSomeFuture<'a, R> {
+    type Output = Result<usize, PartialError<R::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let normalize = unsafe { Pin::new_unchecked(&mut this.normalize) };
+        normalize.poll_read(cx, 0)
+    }
+}
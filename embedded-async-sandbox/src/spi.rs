@@ -1,5 +1,11 @@
 use core::future::Future;
 
+mod device;
+mod transaction;
+
+pub use self::device::{AsyncSpiDevice, DeviceError, DeviceTransactionFuture, SelectFuture, SelectedDevice};
+pub use self::transaction::{transaction, TransactionFuture};
+
 /// SPI transfer
 pub trait AsyncTransfer {
     /// Write error
@@ -7,12 +13,83 @@ pub trait AsyncTransfer {
     /// Write byte future for polling on completion
     type TransferFuture<'t>: Future<Output=Result<(), Self::Error>>;
 
+    /// Transfer future with separate buffers for polling on completion
+    type TransferSplitFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
     /// Sends bytes to the slave. Returns the bytes received from the slave
     fn async_transfer<'a>(&'a mut self, data: &'a mut [u8]) -> Self::TransferFuture<'a>;
+
+    /// Sends `write` to the slave while receiving into `read`
+    /// The transfer is as long as the longer buffer: once `write` is exhausted a fill byte
+    /// is sent, once `read` is full the received bytes are discarded.
+    fn async_transfer_split<'a>(&'a mut self, read: &'a mut [u8], write: &'a [u8]) -> Self::TransferSplitFuture<'a>;
+}
+
+/// Single step of an SPI transaction
+pub enum Operation<'a> {
+    /// Sends bytes to the slave, ignoring all bytes received
+    Write(&'a [u8]),
+    /// Reads bytes from the slave, sending a fill byte for each of them
+    Read(&'a mut [u8]),
+    /// Sends the second buffer while receiving into the first one, see [`AsyncTransfer::async_transfer_split`]
+    ///
+    /// [`AsyncTransfer::async_transfer_split`]: trait.AsyncTransfer.html#tymethod.async_transfer_split
+    Transfer(&'a mut [u8], &'a [u8]),
+    /// Sends bytes to the slave, replacing them with the bytes received
+    TransferInPlace(&'a mut [u8]),
+    /// Waits for the given number of timer ticks with the slave still selected
+    Delay(u32),
+}
+
+/// SPI transaction
+///
+/// Runs several operations back to back, without other users of the bus getting in between.
+pub trait AsyncTransaction {
+    /// Transaction error
+    type Error;
+    /// Transaction future for polling on completion
+    type TransactionFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Executes `operations` in order, stopping at the first one that fails
+    fn async_transaction<'a>(&'a mut self, operations: &'a mut [Operation<'a>]) -> Self::TransactionFuture<'a>;
+}
+
+/// SPI write
+pub trait AsyncWrite {
+    /// Write error
+    type Error;
+    /// Write future for polling on completion
+    type WriteFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Sends bytes to the slave, ignoring all bytes received
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a>;
+}
+
+/// SPI read
+pub trait AsyncRead {
+    /// Read error
+    type Error;
+    /// Read future for polling on completion
+    type ReadFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Reads bytes from the slave, sending a fill byte for each of them
+    fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a>;
+}
+
+/// Byte sent to the slave while nothing is left to write
+///
+/// Shared by the default read and transfer implementations, so that a peripheral
+/// using both of them always sends the same byte.
+pub trait FillByte {
+    /// Byte sent for every byte read beyond the data to write
+    fn fill_byte(&self) -> u8 {
+        0x00
+    }
 }
 
 pub mod transfer {
-    use super::AsyncTransfer;
+    use super::{AsyncTransfer, FillByte};
+    use crate::waker::{Event, RegisterWaker};
     use core::future::Future;
     use core::task::{Context, Poll};
     use core::pin::Pin;
@@ -22,16 +99,201 @@ pub mod transfer {
     /// Implementers of `embedded-hal::spi::FullDuplex<u8>` can implement this marker trait
     /// for their type. Doing so will automatically provide the default
     /// implementation of [`spi::AsyncTransfer`] for the type.
+    /// Futures wait for [`Event::TxSpace`] while sending and for [`Event::RxNotEmpty`] while receiving.
+    /// Once the write buffer of a split transfer is exhausted, the byte of [`FillByte`] is sent.
     ///
     /// [`spi::AsyncTransfer`]: ../trait.AsyncTransfer.html
-    pub trait Default: embedded_hal::spi::FullDuplex<u8> {}
+    /// [`FillByte`]: ../trait.FillByte.html
+    /// [`Event::TxSpace`]: ../../waker/enum.Event.html#variant.TxSpace
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default: embedded_hal::spi::FullDuplex<u8> + RegisterWaker + FillByte {
+        /// Maximum number of bytes sent ahead of the received ones
+        ///
+        /// Values above one keep the transmit FIFO busy while earlier responses are still being
+        /// read. Every byte in flight ends up in the receive FIFO, so the depth must not exceed
+        /// its size or received bytes get lost. The default of one only ever sends the next
+        /// byte once the previous response has been read, which is safe for any peripheral.
+        fn transfer_depth(&self) -> usize {
+            1
+        }
+    }
 
     impl<S: Default + 'static> AsyncTransfer for S {
         type Error = S::Error;
         type TransferFuture<'t> = DefaultTransferFuture<'t, S>;
+        type TransferSplitFuture<'t> = DefaultTransferSplitFuture<'t, S>;
 
         fn async_transfer<'a>(&'a mut self, data: &'a mut [u8]) -> Self::TransferFuture<'a> {
             DefaultTransferFuture {
+                spi: self,
+                data,
+                sent: 0,
+                received: 0,
+            }
+        }
+
+        fn async_transfer_split<'a>(&'a mut self, read: &'a mut [u8], write: &'a [u8]) -> Self::TransferSplitFuture<'a> {
+            DefaultTransferSplitFuture {
+                spi: self,
+                read,
+                write,
+                sent: 0,
+                received: 0,
+            }
+        }
+    }
+
+    pub struct DefaultTransferFuture<'a, S> {
+        spi: &'a mut S,
+        data: &'a mut [u8],
+        sent: usize,
+        received: usize,
+    }
+
+    impl<'a, S: Default> Future for DefaultTransferFuture<'a, S> {
+        type Output = Result<(), S::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            let len = this.data.len();
+            let depth = core::cmp::max(this.spi.transfer_depth(), 1);
+            loop {
+                let mut progress = false;
+
+                while this.received < this.sent {
+                    match this.spi.read() {
+                        Ok(byte) => {
+                            this.data[this.received] = byte;
+                            this.received += 1;
+                            progress = true;
+                        },
+                        Err(nb::Error::Other(e)) => {
+                            return Poll::Ready(Err(e));
+                        },
+                        Err(nb::Error::WouldBlock) => break,
+                    }
+                }
+                if this.received == len {
+                    return Poll::Ready(Ok(()));
+                }
+
+                while this.sent < len && this.sent - this.received < depth {
+                    match this.spi.send(this.data[this.sent]) {
+                        Ok(()) => {
+                            this.sent += 1;
+                            progress = true;
+                        },
+                        Err(nb::Error::Other(e)) => {
+                            return Poll::Ready(Err(e));
+                        },
+                        Err(nb::Error::WouldBlock) => break,
+                    }
+                }
+
+                if !progress {
+                    if this.sent < len && this.sent - this.received < depth {
+                        this.spi.register_waker(Event::TxSpace, cx.waker());
+                    }
+                    if this.received < this.sent {
+                        this.spi.register_waker(Event::RxNotEmpty, cx.waker());
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+
+    pub struct DefaultTransferSplitFuture<'a, S> {
+        spi: &'a mut S,
+        read: &'a mut [u8],
+        write: &'a [u8],
+        sent: usize,
+        received: usize,
+    }
+
+    impl<'a, S: Default> Future for DefaultTransferSplitFuture<'a, S> {
+        type Output = Result<(), S::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            let len = core::cmp::max(this.read.len(), this.write.len());
+            let depth = core::cmp::max(this.spi.transfer_depth(), 1);
+            loop {
+                let mut progress = false;
+
+                while this.received < this.sent {
+                    match this.spi.read() {
+                        Ok(byte) => {
+                            if let Some(slot) = this.read.get_mut(this.received) {
+                                *slot = byte;
+                            }
+                            this.received += 1;
+                            progress = true;
+                        },
+                        Err(nb::Error::Other(e)) => {
+                            return Poll::Ready(Err(e));
+                        },
+                        Err(nb::Error::WouldBlock) => break,
+                    }
+                }
+                if this.received == len {
+                    return Poll::Ready(Ok(()));
+                }
+
+                while this.sent < len && this.sent - this.received < depth {
+                    let byte = match this.write.get(this.sent) {
+                        Some(byte) => *byte,
+                        None => this.spi.fill_byte(),
+                    };
+                    match this.spi.send(byte) {
+                        Ok(()) => {
+                            this.sent += 1;
+                            progress = true;
+                        },
+                        Err(nb::Error::Other(e)) => {
+                            return Poll::Ready(Err(e));
+                        },
+                        Err(nb::Error::WouldBlock) => break,
+                    }
+                }
+
+                if !progress {
+                    if this.sent < len && this.sent - this.received < depth {
+                        this.spi.register_waker(Event::TxSpace, cx.waker());
+                    }
+                    if this.received < this.sent {
+                        this.spi.register_waker(Event::RxNotEmpty, cx.waker());
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+pub mod write {
+    use super::AsyncWrite;
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async write implementation
+    ///
+    /// Implementers of `embedded-hal::spi::FullDuplex<u8>` can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`spi::AsyncWrite`] for the type.
+    /// Every received byte is read and discarded, so the receive FIFO never overflows.
+    ///
+    /// [`spi::AsyncWrite`]: ../trait.AsyncWrite.html
+    pub trait Default: embedded_hal::spi::FullDuplex<u8> + RegisterWaker {}
+
+    impl<S: Default + 'static> AsyncWrite for S {
+        type Error = S::Error;
+        type WriteFuture<'t> = DefaultWriteFuture<'t, S>;
+
+        fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+            DefaultWriteFuture {
                 spi: self,
                 data,
                 offset: 0,
@@ -45,14 +307,14 @@ pub mod transfer {
         Receiving,
     }
 
-    pub struct DefaultTransferFuture<'a, S> {
+    pub struct DefaultWriteFuture<'a, S> {
         spi: &'a mut S,
-        data: &'a mut [u8],
+        data: &'a [u8],
         offset: usize,
         state: State,
     }
 
-    impl<'a, S: Default> Future for DefaultTransferFuture<'a, S> {
+    impl<'a, S: Default> Future for DefaultWriteFuture<'a, S> {
         type Output = Result<(), S::Error>;
 
         fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -69,7 +331,96 @@ pub mod transfer {
                                 return Poll::Ready(Err(e));
                             },
                             Err(nb::Error::WouldBlock) => {
-                                cx.waker().wake_by_ref();
+                                self.spi.register_waker(Event::TxSpace, cx.waker());
+                                return Poll::Pending;
+                            }
+                        }
+                    },
+                    State::Receiving => {
+                        match self.spi.read() {
+                            Ok(_) => {
+                                self.offset += 1;
+                                self.state = State::Sending;
+                                continue;
+                            },
+                            Err(nb::Error::Other(e)) => {
+                                return Poll::Ready(Err(e));
+                            },
+                            Err(nb::Error::WouldBlock) => {
+                                self.spi.register_waker(Event::RxNotEmpty, cx.waker());
+                                return Poll::Pending;
+                            }
+                        }
+                    },
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+pub mod read {
+    use super::{AsyncRead, FillByte};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async read implementation
+    ///
+    /// Implementers of `embedded-hal::spi::FullDuplex<u8>` can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`spi::AsyncRead`] for the type.
+    /// The byte of [`FillByte`] is sent for every byte read.
+    ///
+    /// [`spi::AsyncRead`]: ../trait.AsyncRead.html
+    /// [`FillByte`]: ../trait.FillByte.html
+    pub trait Default: embedded_hal::spi::FullDuplex<u8> + RegisterWaker + FillByte {}
+
+    impl<S: Default + 'static> AsyncRead for S {
+        type Error = S::Error;
+        type ReadFuture<'t> = DefaultReadFuture<'t, S>;
+
+        fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+            DefaultReadFuture {
+                spi: self,
+                data,
+                offset: 0,
+                state: State::Sending
+            }
+        }
+    }
+
+    enum State {
+        Sending,
+        Receiving,
+    }
+
+    pub struct DefaultReadFuture<'a, S> {
+        spi: &'a mut S,
+        data: &'a mut [u8],
+        offset: usize,
+        state: State,
+    }
+
+    impl<'a, S: Default> Future for DefaultReadFuture<'a, S> {
+        type Output = Result<(), S::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            while self.offset < self.data.len() {
+                match self.state {
+                    State::Sending => {
+                        let byte = self.spi.fill_byte();
+                        match self.spi.send(byte) {
+                            Ok(()) => {
+                                self.state = State::Receiving;
+                                continue;
+                            },
+                            Err(nb::Error::Other(e)) => {
+                                return Poll::Ready(Err(e));
+                            },
+                            Err(nb::Error::WouldBlock) => {
+                                self.spi.register_waker(Event::TxSpace, cx.waker());
                                 return Poll::Pending;
                             }
                         }
@@ -87,7 +438,7 @@ pub mod transfer {
                                 return Poll::Ready(Err(e));
                             },
                             Err(nb::Error::WouldBlock) => {
-                                cx.waker().wake_by_ref();
+                                self.spi.register_waker(Event::RxNotEmpty, cx.waker());
                                 return Poll::Pending;
                             }
                         }
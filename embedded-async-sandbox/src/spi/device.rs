@@ -0,0 +1,211 @@
+use super::{transaction, AsyncTransaction, AsyncTransfer, Operation, TransactionFuture};
+use crate::mutex::{LockFuture, Mutex, MutexGuard};
+use crate::timer::AsyncDelay;
+use core::future::Future;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use embedded_hal::digital::v2::OutputPin;
+
+/// Error of an operation on an [`AsyncSpiDevice`]
+///
+/// [`AsyncSpiDevice`]: struct.AsyncSpiDevice.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DeviceError<E, P> {
+    /// The operation on the bus failed
+    Bus(E),
+    /// The chip select pin could not be driven
+    ChipSelect(P),
+}
+
+/// Device on an SPI bus shared with other devices
+///
+/// The bus lives in a [`Mutex`], every device on the bus holds a reference to it
+/// and owns the chip select pin of its chip. The pin is active low.
+/// The timer is used for [`Operation::Delay`] steps of transactions.
+///
+/// [`Mutex`]: ../../mutex/struct.Mutex.html
+/// [`Operation::Delay`]: enum.Operation.html#variant.Delay
+pub struct AsyncSpiDevice<'a, B, CS, D> {
+    bus: &'a Mutex<B>,
+    cs: CS,
+    delay: D,
+}
+
+impl<'a, B, CS: OutputPin, D> AsyncSpiDevice<'a, B, CS, D> {
+    /// Creates a device selected by `cs`, using `delay` for delays within transactions
+    ///
+    /// The pin is not touched until the first transaction, it should already be driven high.
+    pub fn new(bus: &'a Mutex<B>, cs: CS, delay: D) -> Self {
+        Self {
+            bus,
+            cs,
+            delay,
+        }
+    }
+
+    /// Releases the chip select pin and the timer
+    pub fn release(self) -> (CS, D) {
+        (self.cs, self.delay)
+    }
+
+    /// Waits until no other device uses the bus and selects the chip
+    ///
+    /// The returned guard gives access to the bus for any number of operations with the
+    /// chip selected. The pin goes high again and the bus is released when the guard is dropped.
+    pub fn async_select(&mut self) -> SelectFuture<'_, B, CS> {
+        SelectFuture {
+            lock: self.bus.lock(),
+            cs: Some(&mut self.cs),
+        }
+    }
+}
+
+pub struct SelectFuture<'a, B, CS> {
+    lock: LockFuture<'a, B>,
+    cs: Option<&'a mut CS>,
+}
+
+impl<'a, B, CS: OutputPin> Future for SelectFuture<'a, B, CS> {
+    type Output = Result<SelectedDevice<'a, B, CS>, CS::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `lock` is structurally pinned: it is never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let bus = match unsafe { Pin::new_unchecked(&mut this.lock) }.poll(cx) {
+            Poll::Ready(bus) => bus,
+            Poll::Pending => return Poll::Pending,
+        };
+        let cs = this.cs.take().expect("polled after completion");
+        // On failure the bus is released right away
+        if let Err(e) = cs.set_low() {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(SelectedDevice {
+            bus,
+            cs: Some(cs),
+        }))
+    }
+}
+
+/// Locked bus with the chip of a device selected
+///
+/// Dereferences to the bus. When the guard is dropped, the chip select pin is driven
+/// high before the bus is released.
+pub struct SelectedDevice<'a, B, CS: OutputPin> {
+    bus: MutexGuard<'a, B>,
+    cs: Option<&'a mut CS>,
+}
+
+impl<B, CS: OutputPin> SelectedDevice<'_, B, CS> {
+    /// Deselects the chip and releases the bus, reporting a failure to drive the pin
+    pub fn deselect(mut self) -> Result<(), CS::Error> {
+        match self.cs.take() {
+            Some(cs) => cs.set_high(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<B, CS: OutputPin> Deref for SelectedDevice<'_, B, CS> {
+    type Target = B;
+
+    fn deref(&self) -> &B {
+        &self.bus
+    }
+}
+
+impl<B, CS: OutputPin> DerefMut for SelectedDevice<'_, B, CS> {
+    fn deref_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+}
+
+impl<B, CS: OutputPin> Drop for SelectedDevice<'_, B, CS> {
+    fn drop(&mut self) {
+        // Runs before the fields are dropped, so the bus is still locked
+        if let Some(cs) = self.cs.take() {
+            let _ = cs.set_high();
+        }
+    }
+}
+
+impl<B, CS, D> AsyncTransaction for AsyncSpiDevice<'_, B, CS, D>
+where
+    B: AsyncTransfer + 'static,
+    CS: OutputPin + 'static,
+    D: AsyncDelay + 'static,
+{
+    type Error = DeviceError<B::Error, CS::Error>;
+    type TransactionFuture<'t> = DeviceTransactionFuture<'t, B, CS, D>;
+
+    fn async_transaction<'a>(&'a mut self, operations: &'a mut [Operation<'a>]) -> Self::TransactionFuture<'a> {
+        DeviceTransactionFuture {
+            select: SelectFuture {
+                lock: self.bus.lock(),
+                cs: Some(&mut self.cs),
+            },
+            delay: Some(&mut self.delay),
+            operations: Some(operations),
+            future: None,
+            device: None,
+        }
+    }
+}
+
+pub struct DeviceTransactionFuture<'a, B: AsyncTransfer + 'a, CS: OutputPin, D: AsyncDelay + 'a> {
+    select: SelectFuture<'a, B, CS>,
+    delay: Option<&'a mut D>,
+    operations: Option<&'a mut [Operation<'a>]>,
+    // Declared before the device, so that it is dropped before the chip is deselected
+    future: Option<TransactionFuture<'a, B, D>>,
+    device: Option<SelectedDevice<'a, B, CS>>,
+}
+
+impl<'a, B, CS, D> Future for DeviceTransactionFuture<'a, B, CS, D>
+where
+    B: AsyncTransfer + 'a,
+    CS: OutputPin,
+    D: AsyncDelay + 'a,
+{
+    type Output = Result<(), DeviceError<B::Error, CS::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `select` and `future` are structurally pinned: they are never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+
+        if this.device.is_none() {
+            let device = match unsafe { Pin::new_unchecked(&mut this.select) }.poll(cx) {
+                Poll::Ready(Ok(device)) => device,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(DeviceError::ChipSelect(e))),
+                Poll::Pending => return Poll::Pending,
+            };
+            // The selected device outlives the future, which is dropped first
+            let bus: &'a mut B = unsafe { &mut *(&mut **this.device.get_or_insert(device) as *mut B) };
+            let delay = this.delay.take().expect("polled after completion");
+            let operations = this.operations.take().expect("polled after completion");
+            this.future = Some(transaction(bus, delay, operations));
+        }
+
+        let future = match this.future.as_mut() {
+            Some(future) => unsafe { Pin::new_unchecked(future) },
+            None => panic!("polled after completion"),
+        };
+        let result = match future.poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        this.future = None;
+        let deselected = match this.device.take() {
+            Some(device) => device.deselect(),
+            None => Ok(()),
+        };
+
+        match (result, deselected) {
+            (Err(e), _) => Poll::Ready(Err(DeviceError::Bus(e))),
+            (Ok(()), Err(e)) => Poll::Ready(Err(DeviceError::ChipSelect(e))),
+            (Ok(()), Ok(())) => Poll::Ready(Ok(())),
+        }
+    }
+}
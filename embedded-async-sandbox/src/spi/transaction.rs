@@ -0,0 +1,94 @@
+use super::{AsyncTransfer, Operation};
+use crate::reborrow::Reborrow;
+use crate::timer::AsyncDelay;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Runs `operations` on `spi` back to back
+///
+/// This is the building block of [`AsyncTransaction`] implementations: it only sequences
+/// the operations, keeping other users off the bus is up to the caller.
+/// Writes and reads are performed as split transfers, delays use `delay`.
+///
+/// [`AsyncTransaction`]: trait.AsyncTransaction.html
+pub fn transaction<'a, S, D>(spi: &'a mut S, delay: &'a mut D, operations: &'a mut [Operation<'a>]) -> TransactionFuture<'a, S, D>
+where
+    S: AsyncTransfer + 'a,
+    D: AsyncDelay + 'a,
+{
+    TransactionFuture {
+        spi: Reborrow::new(spi),
+        delay: Reborrow::new(delay),
+        operations,
+        step: Step::Idle,
+    }
+}
+
+enum Step<'a, S: AsyncTransfer + 'a, D: AsyncDelay + 'a> {
+    Idle,
+    Transfer(S::TransferFuture<'a>),
+    TransferSplit(S::TransferSplitFuture<'a>),
+    Delay(D::DelayFuture<'a>),
+}
+
+pub struct TransactionFuture<'a, S: AsyncTransfer + 'a, D: AsyncDelay + 'a> {
+    // Lent to the future of every operation in turn
+    spi: Reborrow<'a, S>,
+    delay: Reborrow<'a, D>,
+    operations: &'a mut [Operation<'a>],
+    step: Step<'a, S, D>,
+}
+
+impl<'a, S: AsyncTransfer + 'a, D: AsyncDelay + 'a> Future for TransactionFuture<'a, S, D> {
+    type Output = Result<(), S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // `step` is structurally pinned: it is never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+
+        loop {
+            let result = match &mut this.step {
+                Step::Idle => Ok(()),
+                Step::Transfer(future) => match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                },
+                Step::TransferSplit(future) => match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                },
+                Step::Delay(future) => match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                    Poll::Ready(()) => Ok(()),
+                    Poll::Pending => return Poll::Pending,
+                },
+            };
+            // Drop the finished future in place, then take back the bus and the timer it borrowed
+            unsafe {
+                Pin::new_unchecked(&mut this.step).set(Step::Idle);
+                this.spi.give_back();
+                this.delay.give_back();
+            }
+            if let Err(e) = result {
+                return Poll::Ready(Err(e));
+            }
+
+            let operations = core::mem::replace(&mut this.operations, &mut []);
+            let (operation, rest) = match operations.split_first_mut() {
+                Some(split) => split,
+                None => return Poll::Ready(Ok(())),
+            };
+            this.operations = rest;
+
+            let spi = &mut this.spi;
+            let step = match operation {
+                Operation::Write(write) => Step::TransferSplit(spi.lend().async_transfer_split(&mut [], write)),
+                Operation::Read(read) => Step::TransferSplit(spi.lend().async_transfer_split(read, &[])),
+                Operation::Transfer(read, write) => Step::TransferSplit(spi.lend().async_transfer_split(read, write)),
+                Operation::TransferInPlace(data) => Step::Transfer(spi.lend().async_transfer(data)),
+                Operation::Delay(ticks) => Step::Delay(this.delay.lend().async_delay(*ticks)),
+            };
+            unsafe { Pin::new_unchecked(&mut this.step) }.set(step);
+        }
+    }
+}
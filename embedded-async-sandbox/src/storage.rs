@@ -0,0 +1,45 @@
+use core::future::Future;
+use core::ops::Range;
+
+mod nor;
+
+pub use self::nor::{JedecId, NorFlashError, SpiNorFlash};
+
+/// Readable storage
+pub trait AsyncReadStorage {
+    /// Read error
+    type Error;
+    /// Read future for polling on completion
+    type ReadFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Returns the size of the storage in bytes
+    fn capacity(&self) -> usize;
+
+    /// Fills `data` with the bytes starting at `offset`
+    fn async_read<'a>(&'a mut self, offset: u32, data: &'a mut [u8]) -> Self::ReadFuture<'a>;
+}
+
+/// Storage that has to be erased before it is written, like NOR flash
+///
+/// Erasing sets every byte to 0xff. Writing can only clear bits, so writing a byte
+/// that was not erased leaves the bits that are clear in either value cleared.
+pub trait AsyncWriteStorage: AsyncReadStorage {
+    /// Write future for polling on completion
+    type WriteFuture<'t>: Future<Output=Result<(), Self::Error>>;
+    /// Erase future for polling on completion
+    type EraseFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Returns the granularity of writes
+    /// Offsets and lengths of writes must be multiples of it.
+    fn write_size(&self) -> usize;
+
+    /// Returns the granularity of erases
+    /// Both ends of an erased range must be multiples of it.
+    fn erase_size(&self) -> usize;
+
+    /// Writes `data` starting at `offset`, the bytes should have been erased before
+    fn async_write<'a>(&'a mut self, offset: u32, data: &'a [u8]) -> Self::WriteFuture<'a>;
+
+    /// Erases the bytes in `range`
+    fn async_erase(&mut self, range: Range<u32>) -> Self::EraseFuture<'_>;
+}
@@ -0,0 +1,466 @@
+use crate::reborrow::Reborrow;
+use crate::spi::AsyncTransfer;
+use crate::storage::{AsyncReadStorage, AsyncWriteStorage};
+use core::future::Future;
+use core::ops::Range;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use embedded_hal::digital::v2::OutputPin;
+
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS: u8 = 0x05;
+const READ_DATA: u8 = 0x03;
+const PAGE_PROGRAM: u8 = 0x02;
+const SECTOR_ERASE: u8 = 0x20;
+const READ_JEDEC_ID: u8 = 0x9f;
+
+/// Status register bit set while a program or erase operation is running
+const STATUS_BUSY: u8 = 0x01;
+
+/// Largest number of bytes a single page program command writes
+const PAGE_SIZE: usize = 256;
+/// Smallest number of bytes an erase command erases
+const SECTOR_SIZE: usize = 4096;
+/// Number of bytes reachable with 24-bit addresses
+const MAX_CAPACITY: usize = 1 << 24;
+
+/// Error of an operation on an [`SpiNorFlash`]
+///
+/// [`SpiNorFlash`]: struct.SpiNorFlash.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NorFlashError<E, P> {
+    /// The operation on the bus failed
+    Bus(E),
+    /// The chip select pin could not be driven
+    ChipSelect(P),
+    /// The range does not start or end at a multiple of the granularity of the operation
+    NotAligned,
+    /// The range extends past the end of the chip
+    OutOfBounds,
+}
+
+/// Manufacturer and device identification of a chip
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct JedecId([u8; 3]);
+
+impl JedecId {
+    pub fn manufacturer(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn memory_type(&self) -> u8 {
+        self.0[1]
+    }
+
+    /// Returns the size of the chip in bytes, encoded as a power of two in the last byte
+    ///
+    /// Returns `None` if the size does not fit into `usize`, as for the `ff ff ff`
+    /// read back when no chip answers. Chips of 32 MiB and more report sizes beyond the
+    /// 16 MiB reachable with 24-bit addresses, [`SpiNorFlash`] only uses the first 16 MiB of them.
+    ///
+    /// [`SpiNorFlash`]: struct.SpiNorFlash.html
+    pub fn capacity(&self) -> Option<usize> {
+        1usize.checked_shl(self.0[2] as u32)
+    }
+}
+
+/// Buffers for command headers and short responses
+struct Scratch {
+    header: [u8; 4],
+    response: [u8; 3],
+}
+
+/// SPI NOR flash chip with 4 KiB sectors and 256 byte pages, addressed with 24 bits
+///
+/// The driver owns the bus and the chip select pin, which is active low. Writes are split
+/// at page boundaries, every page program and sector erase polls the status register
+/// until the chip is done, so that completed futures leave the chip idle.
+pub struct SpiNorFlash<SPI, CS> {
+    spi: SPI,
+    cs: CS,
+    capacity: usize,
+    scratch: Scratch,
+}
+
+impl<SPI: AsyncTransfer, CS: OutputPin> SpiNorFlash<SPI, CS> {
+    /// Creates a driver for a chip of `capacity` bytes selected by `cs`
+    ///
+    /// The pin is not touched until the first command, it should already be driven high.
+    /// If the capacity is not known up front, it can be taken from the JEDEC ID.
+    /// Capacities above 16 MiB are limited to the 16 MiB reachable with 24-bit addresses.
+    pub fn new(spi: SPI, cs: CS, capacity: usize) -> Self {
+        Self {
+            spi,
+            cs,
+            capacity: capacity.min(MAX_CAPACITY),
+            scratch: Scratch {
+                header: [0; 4],
+                response: [0; 3],
+            },
+        }
+    }
+
+    /// Changes the capacity, usually to the one reported by the chip
+    ///
+    /// Like in [`new`], capacities above 16 MiB are limited to 16 MiB.
+    ///
+    /// [`new`]: #method.new
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.min(MAX_CAPACITY);
+    }
+
+    /// Reads the manufacturer and device identification
+    pub fn async_read_jedec_id(&mut self) -> JedecIdFuture<'_, SPI, CS> {
+        JedecIdFuture {
+            commands: Commands::new(self),
+            started: false,
+        }
+    }
+
+    /// Releases the bus and the chip select pin
+    pub fn release(self) -> (SPI, CS) {
+        (self.spi, self.cs)
+    }
+
+    /// Returns an error if `len` bytes at `offset` do not fit into the chip
+    fn check_bounds(&self, offset: u32, len: usize) -> Result<(), NorFlashError<SPI::Error, CS::Error>> {
+        if offset as usize > self.capacity || len > self.capacity - offset as usize {
+            Err(NorFlashError::OutOfBounds)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<SPI: AsyncTransfer + 'static, CS: OutputPin + 'static> AsyncReadStorage for SpiNorFlash<SPI, CS> {
+    type Error = NorFlashError<SPI::Error, CS::Error>;
+    type ReadFuture<'t> = NorReadFuture<'t, SPI, CS>;
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn async_read<'a>(&'a mut self, offset: u32, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        NorReadFuture {
+            error: self.check_bounds(offset, data.len()).err(),
+            commands: Commands::new(self),
+            offset,
+            data: Some(data),
+        }
+    }
+}
+
+impl<SPI: AsyncTransfer + 'static, CS: OutputPin + 'static> AsyncWriteStorage for SpiNorFlash<SPI, CS> {
+    type WriteFuture<'t> = NorModifyFuture<'t, SPI, CS>;
+    type EraseFuture<'t> = NorModifyFuture<'t, SPI, CS>;
+
+    fn write_size(&self) -> usize {
+        1
+    }
+
+    fn erase_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn async_write<'a>(&'a mut self, offset: u32, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        NorModifyFuture {
+            error: self.check_bounds(offset, data.len()).err(),
+            commands: Commands::new(self),
+            operation: Operation::Program(data),
+            address: offset,
+            phase: Phase::Next,
+        }
+    }
+
+    fn async_erase(&mut self, range: Range<u32>) -> Self::EraseFuture<'_> {
+        let error = if range.start % SECTOR_SIZE as u32 != 0 || range.end % SECTOR_SIZE as u32 != 0 {
+            Some(NorFlashError::NotAligned)
+        } else if range.start > range.end {
+            Some(NorFlashError::OutOfBounds)
+        } else {
+            self.check_bounds(range.start, (range.end - range.start) as usize).err()
+        };
+        NorModifyFuture {
+            error,
+            commands: Commands::new(self),
+            operation: Operation::Erase(range.end),
+            address: range.start,
+            phase: Phase::Next,
+        }
+    }
+}
+
+/// Data phase of a command, following the header
+enum Data<'a> {
+    None,
+    Read(&'a mut [u8]),
+    Write(&'a [u8]),
+}
+
+enum Step<'a, SPI: AsyncTransfer + 'a> {
+    Idle,
+    Header(SPI::TransferSplitFuture<'a>),
+    Data(SPI::TransferSplitFuture<'a>),
+}
+
+/// Runs commands on the chip, one at a time
+///
+/// The bus and the scratch buffers are lent out separately,
+/// so that an in-flight transfer can borrow the bus and a buffer at the same time.
+struct Commands<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> {
+    spi: Reborrow<'a, SPI>,
+    cs: &'a mut CS,
+    header: Reborrow<'a, [u8; 4]>,
+    response: Reborrow<'a, [u8; 3]>,
+    header_len: usize,
+    data: Data<'a>,
+    step: Step<'a, SPI>,
+}
+
+impl<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> Commands<'a, SPI, CS> {
+    fn new(flash: &'a mut SpiNorFlash<SPI, CS>) -> Self {
+        let SpiNorFlash { spi, cs, scratch, .. } = flash;
+        Self {
+            spi: Reborrow::new(spi),
+            cs,
+            header: Reborrow::new(&mut scratch.header),
+            response: Reborrow::new(&mut scratch.response),
+            header_len: 0,
+            data: Data::None,
+            step: Step::Idle,
+        }
+    }
+
+    /// Lends out the buffer for short responses, to be read into by the next command
+    fn response(&mut self) -> &'a mut [u8; 3] {
+        self.response.lend()
+    }
+
+    /// Returns the response received by the last command
+    fn last_response(&mut self) -> [u8; 3] {
+        *self.response.get()
+    }
+
+    /// Selects the chip and starts sending `header`, followed by `data`
+    fn start(&mut self, header: &[u8], data: Data<'a>) -> Result<(), NorFlashError<SPI::Error, CS::Error>> {
+        let buf = self.header.lend();
+        buf[..header.len()].copy_from_slice(header);
+        self.header_len = header.len();
+        self.data = data;
+
+        if let Err(e) = self.cs.set_low() {
+            self.finish();
+            return Err(NorFlashError::ChipSelect(e));
+        }
+        self.step = Step::Header(self.spi.lend().async_transfer_split(&mut [], &buf[..self.header_len]));
+        Ok(())
+    }
+
+    /// Takes back the bus and the buffers once no future of the command is left
+    fn finish(&mut self) {
+        self.step = Step::Idle;
+        self.data = Data::None;
+        unsafe {
+            self.spi.give_back();
+            self.header.give_back();
+            self.response.give_back();
+        }
+    }
+
+    /// Completes the command started by `start`, if any, and deselects the chip
+    fn poll_command(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), NorFlashError<SPI::Error, CS::Error>>> {
+        // The futures in `step` are structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let result = match &mut this.step {
+                Step::Idle => return Poll::Ready(Ok(())),
+                Step::Header(future) | Step::Data(future) => {
+                    match unsafe { Pin::new_unchecked(future) }.poll(cx) {
+                        Poll::Ready(result) => result,
+                        Poll::Pending => return Poll::Pending,
+                    }
+                },
+            };
+            let header = matches!(this.step, Step::Header(_));
+            this.step = Step::Idle;
+            // The transfer that borrowed the bus is gone
+            unsafe { this.spi.give_back() };
+            if let Err(e) = result {
+                let _ = this.cs.set_high();
+                this.finish();
+                return Poll::Ready(Err(NorFlashError::Bus(e)));
+            }
+
+            match core::mem::replace(&mut this.data, Data::None) {
+                Data::Read(data) if header => this.step = Step::Data(this.spi.lend().async_transfer_split(data, &[])),
+                Data::Write(data) if header => this.step = Step::Data(this.spi.lend().async_transfer_split(&mut [], data)),
+                _ => {
+                    let deselected = this.cs.set_high();
+                    this.finish();
+                    return Poll::Ready(deselected.map_err(NorFlashError::ChipSelect));
+                },
+            }
+        }
+    }
+}
+
+impl<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> Drop for Commands<'a, SPI, CS> {
+    fn drop(&mut self) {
+        // Cancelled in the middle of a command, deselect the chip so that it ignores the command
+        if !matches!(self.step, Step::Idle) {
+            self.step = Step::Idle;
+            let _ = self.cs.set_high();
+        }
+    }
+}
+
+/// Splits a 24-bit address into the bytes following a command
+fn address(command: u8, address: u32) -> [u8; 4] {
+    [command, (address >> 16) as u8, (address >> 8) as u8, address as u8]
+}
+
+pub struct JedecIdFuture<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> {
+    commands: Commands<'a, SPI, CS>,
+    started: bool,
+}
+
+impl<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> Future for JedecIdFuture<'a, SPI, CS> {
+    type Output = Result<JedecId, NorFlashError<SPI::Error, CS::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if !this.started {
+            this.started = true;
+            let response = this.commands.response();
+            if let Err(e) = this.commands.start(&[READ_JEDEC_ID], Data::Read(response)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+
+        let commands = unsafe { Pin::new_unchecked(&mut this.commands) };
+        match commands.poll_command(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(JedecId(this.commands.last_response()))),
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct NorReadFuture<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> {
+    commands: Commands<'a, SPI, CS>,
+    error: Option<NorFlashError<SPI::Error, CS::Error>>,
+    offset: u32,
+    data: Option<&'a mut [u8]>,
+}
+
+impl<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> Future for NorReadFuture<'a, SPI, CS> {
+    type Output = Result<(), NorFlashError<SPI::Error, CS::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(e) = this.error.take() {
+            return Poll::Ready(Err(e));
+        }
+        // Nothing is sent to the chip for empty reads
+        if let Some(data) = this.data.take().filter(|data| !data.is_empty()) {
+            if let Err(e) = this.commands.start(&address(READ_DATA, this.offset), Data::Read(data)) {
+                return Poll::Ready(Err(e));
+            }
+        }
+
+        let commands = unsafe { Pin::new_unchecked(&mut this.commands) };
+        commands.poll_command(cx)
+    }
+}
+
+enum Operation<'a> {
+    /// Programs the remaining data
+    Program(&'a [u8]),
+    /// Erases sectors up to the given address
+    Erase(u32),
+}
+
+#[derive(Copy, Clone)]
+enum Phase {
+    /// Starts on the next page or sector, if any
+    Next,
+    WriteEnable,
+    /// Programs or erases the given number of bytes
+    Modify(usize),
+    /// Waits until the chip has finished
+    Busy,
+}
+
+pub struct NorModifyFuture<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> {
+    commands: Commands<'a, SPI, CS>,
+    error: Option<NorFlashError<SPI::Error, CS::Error>>,
+    operation: Operation<'a>,
+    address: u32,
+    phase: Phase,
+}
+
+impl<'a, SPI: AsyncTransfer + 'a, CS: OutputPin> Future for NorModifyFuture<'a, SPI, CS> {
+    type Output = Result<(), NorFlashError<SPI::Error, CS::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if let Some(e) = this.error.take() {
+            return Poll::Ready(Err(e));
+        }
+
+        loop {
+            let commands = unsafe { Pin::new_unchecked(&mut this.commands) };
+            match commands.poll_command(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let address = this.address;
+            let started = match this.phase {
+                Phase::Next => {
+                    let done = match this.operation {
+                        Operation::Program(data) => data.is_empty(),
+                        Operation::Erase(end) => address == end,
+                    };
+                    if done {
+                        return Poll::Ready(Ok(()));
+                    }
+                    this.phase = Phase::WriteEnable;
+                    this.commands.start(&[WRITE_ENABLE], Data::None)
+                },
+                Phase::WriteEnable => match this.operation {
+                    Operation::Program(data) => {
+                        let len = core::cmp::min(data.len(), PAGE_SIZE - address as usize % PAGE_SIZE);
+                        this.phase = Phase::Modify(len);
+                        this.commands.start(&self::address(PAGE_PROGRAM, address), Data::Write(&data[..len]))
+                    },
+                    Operation::Erase(_) => {
+                        this.phase = Phase::Modify(SECTOR_SIZE);
+                        this.commands.start(&self::address(SECTOR_ERASE, address), Data::None)
+                    },
+                },
+                Phase::Modify(len) => {
+                    this.address += len as u32;
+                    if let Operation::Program(data) = &mut this.operation {
+                        *data = &data[len..];
+                    }
+                    this.phase = Phase::Busy;
+                    let response = this.commands.response();
+                    this.commands.start(&[READ_STATUS], Data::Read(&mut response[..1]))
+                },
+                Phase::Busy => {
+                    if this.commands.last_response()[0] & STATUS_BUSY == 0 {
+                        this.phase = Phase::Next;
+                        continue;
+                    }
+                    let response = this.commands.response();
+                    this.commands.start(&[READ_STATUS], Data::Read(&mut response[..1]))
+                },
+            };
+            if let Err(e) = started {
+                return Poll::Ready(Err(e));
+            }
+        }
+    }
+}
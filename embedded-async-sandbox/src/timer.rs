@@ -0,0 +1,167 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// Timer used to bound the duration of other operations
+pub trait AsyncDelay {
+    /// Delay future for polling on completion
+    type DelayFuture<'t>: Future<Output=()>;
+
+    /// Waits for `ticks` periods of the timer clock
+    fn async_delay(&mut self, ticks: u32) -> Self::DelayFuture<'_>;
+}
+
+/// Millisecond delay
+pub trait AsyncDelayMs {
+    /// Delay future for polling on completion
+    type DelayMsFuture<'t>: Future<Output=()>;
+
+    /// Waits for at least `ms` milliseconds
+    fn async_delay_ms(&mut self, ms: u32) -> Self::DelayMsFuture<'_>;
+}
+
+/// Microsecond delay
+pub trait AsyncDelayUs {
+    /// Delay future for polling on completion
+    type DelayUsFuture<'t>: Future<Output=()>;
+
+    /// Waits for at least `us` microseconds
+    fn async_delay_us(&mut self, us: u32) -> Self::DelayUsFuture<'_>;
+}
+
+pub mod delay_ms {
+    use super::AsyncDelayMs;
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async millisecond delay implementation
+    ///
+    /// Implementers of `embedded-hal::blocking::delay::DelayMs<u32>` can implement this marker
+    /// trait for their type. Doing so will automatically provide the default
+    /// implementation of [`timer::AsyncDelayMs`] for the type.
+    /// The future runs the blocking delay when first polled, stalling the executor meanwhile.
+    ///
+    /// [`timer::AsyncDelayMs`]: ../trait.AsyncDelayMs.html
+    pub trait Default: embedded_hal::blocking::delay::DelayMs<u32> {}
+
+    impl<D: Default + 'static> AsyncDelayMs for D {
+        type DelayMsFuture<'t> = DefaultDelayMsFuture<'t, D>;
+
+        fn async_delay_ms(&mut self, ms: u32) -> Self::DelayMsFuture<'_> {
+            DefaultDelayMsFuture {
+                delay: self,
+                ms,
+            }
+        }
+    }
+
+    pub struct DefaultDelayMsFuture<'a, D> {
+        delay: &'a mut D,
+        ms: u32,
+    }
+
+    impl<'a, D: Default> Future for DefaultDelayMsFuture<'a, D> {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let ms = self.ms;
+            self.delay.delay_ms(ms);
+            Poll::Ready(())
+        }
+    }
+}
+
+pub mod delay_us {
+    use super::AsyncDelayUs;
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async microsecond delay implementation
+    ///
+    /// Implementers of `embedded-hal::blocking::delay::DelayUs<u32>` can implement this marker
+    /// trait for their type. Doing so will automatically provide the default
+    /// implementation of [`timer::AsyncDelayUs`] for the type.
+    /// The future runs the blocking delay when first polled, stalling the executor meanwhile.
+    ///
+    /// [`timer::AsyncDelayUs`]: ../trait.AsyncDelayUs.html
+    pub trait Default: embedded_hal::blocking::delay::DelayUs<u32> {}
+
+    impl<D: Default + 'static> AsyncDelayUs for D {
+        type DelayUsFuture<'t> = DefaultDelayUsFuture<'t, D>;
+
+        fn async_delay_us(&mut self, us: u32) -> Self::DelayUsFuture<'_> {
+            DefaultDelayUsFuture {
+                delay: self,
+                us,
+            }
+        }
+    }
+
+    pub struct DefaultDelayUsFuture<'a, D> {
+        delay: &'a mut D,
+        us: u32,
+    }
+
+    impl<'a, D: Default> Future for DefaultDelayUsFuture<'a, D> {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let us = self.us;
+            self.delay.delay_us(us);
+            Poll::Ready(())
+        }
+    }
+}
+
+/// Error of an operation bounded by [`with_timeout`]
+///
+/// [`with_timeout`]: fn.with_timeout.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimeoutError<E> {
+    /// The delay elapsed before the operation completed
+    Timeout,
+    /// The operation itself failed
+    Other(E),
+}
+
+/// Runs `future` until it completes or `delay` elapses, whichever comes first
+///
+/// Works with any fallible future, such as the read, write and transfer futures
+/// of the serial and SPI traits. The operation is dropped when the delay elapses.
+pub fn with_timeout<F, D>(future: F, delay: D) -> Timeout<F, D> {
+    Timeout {
+        future,
+        delay,
+    }
+}
+
+pub struct Timeout<F, D> {
+    future: F,
+    delay: D,
+}
+
+impl<F, D, T, E> Future for Timeout<F, D>
+where
+    F: Future<Output=Result<T, E>>,
+    D: Future<Output=()>,
+{
+    type Output = Result<T, TimeoutError<E>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Both fields are structurally pinned: they are never moved out of `self`
+        let this = unsafe { self.get_unchecked_mut() };
+
+        let future = unsafe { Pin::new_unchecked(&mut this.future) };
+        if let Poll::Ready(result) = future.poll(cx) {
+            return Poll::Ready(result.map_err(TimeoutError::Other));
+        }
+
+        let delay = unsafe { Pin::new_unchecked(&mut this.delay) };
+        match delay.poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(TimeoutError::Timeout)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use core::future::Future;
+
+mod serial;
+
+pub use self::serial::SerialPort;
+
+/// USB endpoint
+pub trait Endpoint {
+    /// Returns the size of the largest packet the endpoint transfers
+    fn max_packet_size(&self) -> usize;
+}
+
+/// IN endpoint, sending packets to the host
+pub trait AsyncEndpointIn: Endpoint {
+    /// Write error
+    type Error;
+    /// Write packet future for polling on completion
+    type WritePacketFuture<'t>: Future<Output=Result<(), Self::Error>>;
+    /// Flush future for polling on completion
+    type FlushFuture<'t>: Future<Output=Result<(), Self::Error>>;
+
+    /// Queues a packet of at most `max_packet_size` bytes for the host to read
+    /// A packet shorter than `max_packet_size`, including a zero-length packet, ends the transfer.
+    /// When the future completes, the host may not have read the packet yet.
+    fn async_write_packet<'a>(&'a mut self, data: &'a [u8]) -> Self::WritePacketFuture<'a>;
+
+    /// Waits until the host has read all queued packets
+    fn async_flush(&mut self) -> Self::FlushFuture<'_>;
+}
+
+/// OUT endpoint, receiving packets from the host
+pub trait AsyncEndpointOut: Endpoint {
+    /// Read error
+    type Error;
+    /// Read packet future for polling on completion
+    type ReadPacketFuture<'t>: Future<Output=Result<usize, Self::Error>>;
+
+    /// Reads the next packet into `buf`, returns the length of the packet
+    /// `buf` must hold at least `max_packet_size` bytes. Zero-length packets are returned
+    /// like any other packet.
+    fn async_read_packet<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::ReadPacketFuture<'a>;
+}
+
+/// Non-blocking IN endpoint
+///
+/// `embedded-hal` has no USB traits, so this is the interface an endpoint
+/// provides to the default implementations.
+pub trait EndpointIn: Endpoint {
+    /// Endpoint error, such as a packet longer than the maximum packet size
+    type Error;
+
+    /// Puts a packet into the endpoint buffer
+    ///
+    /// Returns `WouldBlock` while the host has not read the previous packet.
+    fn write_packet(&mut self, data: &[u8]) -> nb::Result<(), Self::Error>;
+
+    /// Returns `WouldBlock` while the endpoint buffer holds a packet
+    fn flush(&mut self) -> nb::Result<(), Self::Error>;
+}
+
+/// Non-blocking OUT endpoint
+pub trait EndpointOut: Endpoint {
+    /// Endpoint error, such as a packet not fitting into the buffer
+    type Error;
+
+    /// Takes the packet out of the endpoint buffer, returns its length
+    ///
+    /// Returns `WouldBlock` while the host has not sent a packet.
+    fn read_packet(&mut self, buf: &mut [u8]) -> nb::Result<usize, Self::Error>;
+}
+
+pub mod endpoint_in {
+    use super::{AsyncEndpointIn, EndpointIn};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async IN endpoint implementation
+    ///
+    /// Implementers of [`usb::EndpointIn`] can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`usb::AsyncEndpointIn`] for the type.
+    /// Write futures wait for [`Event::TxSpace`], flush futures wait for [`Event::TxIdle`].
+    ///
+    /// [`usb::EndpointIn`]: ../trait.EndpointIn.html
+    /// [`usb::AsyncEndpointIn`]: ../trait.AsyncEndpointIn.html
+    /// [`Event::TxSpace`]: ../../waker/enum.Event.html#variant.TxSpace
+    /// [`Event::TxIdle`]: ../../waker/enum.Event.html#variant.TxIdle
+    pub trait Default: EndpointIn + RegisterWaker {}
+
+    impl<E: Default + 'static> AsyncEndpointIn for E {
+        type Error = E::Error;
+        type WritePacketFuture<'t> = DefaultWritePacketFuture<'t, E>;
+        type FlushFuture<'t> = DefaultFlushFuture<'t, E>;
+
+        fn async_write_packet<'a>(&'a mut self, data: &'a [u8]) -> Self::WritePacketFuture<'a> {
+            DefaultWritePacketFuture {
+                endpoint: self,
+                data,
+            }
+        }
+
+        fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+            DefaultFlushFuture {
+                endpoint: self,
+            }
+        }
+    }
+
+    pub struct DefaultWritePacketFuture<'a, E> {
+        endpoint: &'a mut E,
+        data: &'a [u8],
+    }
+
+    impl<'a, E: Default> Future for DefaultWritePacketFuture<'a, E> {
+        type Output = Result<(), E::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            match this.endpoint.write_packet(this.data) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    this.endpoint.register_waker(Event::TxSpace, cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    pub struct DefaultFlushFuture<'a, E> {
+        endpoint: &'a mut E,
+    }
+
+    impl<'a, E: Default> Future for DefaultFlushFuture<'a, E> {
+        type Output = Result<(), E::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            match self.endpoint.flush() {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    self.endpoint.register_waker(Event::TxIdle, cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
+
+pub mod endpoint_out {
+    use super::{AsyncEndpointOut, EndpointOut};
+    use crate::waker::{Event, RegisterWaker};
+    use core::future::Future;
+    use core::task::{Context, Poll};
+    use core::pin::Pin;
+
+    /// Marker trait to opt into default async OUT endpoint implementation
+    ///
+    /// Implementers of [`usb::EndpointOut`] can implement this marker trait
+    /// for their type. Doing so will automatically provide the default
+    /// implementation of [`usb::AsyncEndpointOut`] for the type.
+    /// Futures wait for [`Event::RxNotEmpty`] until the host sends a packet.
+    ///
+    /// [`usb::EndpointOut`]: ../trait.EndpointOut.html
+    /// [`usb::AsyncEndpointOut`]: ../trait.AsyncEndpointOut.html
+    /// [`Event::RxNotEmpty`]: ../../waker/enum.Event.html#variant.RxNotEmpty
+    pub trait Default: EndpointOut + RegisterWaker {}
+
+    impl<E: Default + 'static> AsyncEndpointOut for E {
+        type Error = E::Error;
+        type ReadPacketFuture<'t> = DefaultReadPacketFuture<'t, E>;
+
+        fn async_read_packet<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::ReadPacketFuture<'a> {
+            DefaultReadPacketFuture {
+                endpoint: self,
+                buf,
+            }
+        }
+    }
+
+    pub struct DefaultReadPacketFuture<'a, E> {
+        endpoint: &'a mut E,
+        buf: &'a mut [u8],
+    }
+
+    impl<'a, E: Default> Future for DefaultReadPacketFuture<'a, E> {
+        type Output = Result<usize, E::Error>;
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+            let this = &mut *self;
+            match this.endpoint.read_packet(this.buf) {
+                Ok(len) => Poll::Ready(Ok(len)),
+                Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+                Err(nb::Error::WouldBlock) => {
+                    this.endpoint.register_waker(Event::RxNotEmpty, cx.waker());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,463 @@
+use crate::reborrow::Reborrow;
+use crate::serial::{AsyncRead, AsyncWrite, PartialError};
+use crate::usb::{AsyncEndpointIn, AsyncEndpointOut};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+struct TxPacket<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    /// The last packet sent was a full one, so the host still waits for the end of the transfer
+    open: bool,
+}
+
+struct RxPacket<const N: usize> {
+    buf: [u8; N],
+    pos: usize,
+    len: usize,
+}
+
+/// Serial interface on top of a pair of bulk endpoints, like the data interface of a CDC ACM device
+///
+/// Written bytes are collected into packets. A full packet is sent right away, the rest
+/// of the bytes are sent on `async_flush`, which ends the transfer. If the last packet was
+/// a full one, flushing sends a zero-length packet, so that the host does not keep waiting
+/// for more data. Received packets are handed out byte by byte, zero-length packets are skipped.
+///
+/// `N` is the capacity of the packet buffers and must not be smaller than
+/// the maximum packet size of either endpoint.
+pub struct SerialPort<I, O, const N: usize> {
+    ep_in: I,
+    ep_out: O,
+    tx: TxPacket<N>,
+    rx: RxPacket<N>,
+}
+
+impl<I: AsyncEndpointIn, O: AsyncEndpointOut, const N: usize> SerialPort<I, O, N> {
+    pub fn new(ep_in: I, ep_out: O) -> Self {
+        assert!(ep_in.max_packet_size() > 0, "maximum packet size must not be zero");
+        assert!(ep_in.max_packet_size() <= N, "packet does not fit into the buffer");
+        assert!(ep_out.max_packet_size() <= N, "packet does not fit into the buffer");
+        Self {
+            ep_in,
+            ep_out,
+            tx: TxPacket {
+                buf: [0; N],
+                len: 0,
+                open: false,
+            },
+            rx: RxPacket {
+                buf: [0; N],
+                pos: 0,
+                len: 0,
+            },
+        }
+    }
+
+    /// Returns the number of written bytes waiting for the next packet
+    pub fn buffered(&self) -> usize {
+        self.tx.len
+    }
+
+    /// Returns the IN and OUT endpoints
+    pub fn endpoints(&self) -> (&I, &O) {
+        (&self.ep_in, &self.ep_out)
+    }
+
+    /// Returns the endpoints, discarding any buffered bytes
+    pub fn release(self) -> (I, O) {
+        (self.ep_in, self.ep_out)
+    }
+}
+
+impl<I: 'static, O: AsyncEndpointOut + 'static, const N: usize> AsyncRead for SerialPort<I, O, N> {
+    type Error = O::Error;
+    type ReadByteFuture<'t> = PortReadByteFuture<'t, O, N>;
+    type ReadFuture<'t> = PortReadFuture<'t, O, N>;
+    type ReadSomeFuture<'t> = PortReadSomeFuture<'t, O, N>;
+
+    fn async_read_byte(&mut self) -> Self::ReadByteFuture<'_> {
+        PortReadByteFuture {
+            receive: Receive::new(&mut self.ep_out, &mut self.rx),
+        }
+    }
+
+    fn async_read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        PortReadFuture {
+            receive: Receive::new(&mut self.ep_out, &mut self.rx),
+            data,
+            offset: 0,
+        }
+    }
+
+    fn async_read_some<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadSomeFuture<'a> {
+        PortReadSomeFuture {
+            receive: Receive::new(&mut self.ep_out, &mut self.rx),
+            data,
+        }
+    }
+}
+
+impl<I: AsyncEndpointIn + 'static, O: 'static, const N: usize> AsyncWrite for SerialPort<I, O, N> {
+    type Error = I::Error;
+    type WriteByteFuture<'t> = PortWriteByteFuture<'t, I, N>;
+    type WriteFuture<'t> = PortWriteFuture<'t, I, N>;
+    type FlushFuture<'t> = PortFlushFuture<'t, I, N>;
+
+    fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
+        PortWriteByteFuture {
+            send: Sender::new(&mut self.ep_in, &mut self.tx),
+            byte: Some(byte),
+        }
+    }
+
+    fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        PortWriteFuture {
+            send: Sender::new(&mut self.ep_in, &mut self.tx),
+            data,
+            offset: 0,
+            unsent: 0,
+        }
+    }
+
+    fn async_flush(&mut self) -> Self::FlushFuture<'_> {
+        PortFlushFuture {
+            send: Sender::new(&mut self.ep_in, &mut self.tx),
+            flush: None,
+        }
+    }
+}
+
+/// Sends the packet buffer through the IN endpoint
+///
+/// The endpoint and the packet buffer are lent out separately,
+/// so that an in-flight packet write can borrow both of them while
+/// the sender lives in the same future.
+struct Sender<'a, I: AsyncEndpointIn + 'a, const N: usize> {
+    ep: Reborrow<'a, I>,
+    packet: Reborrow<'a, TxPacket<N>>,
+    write: Option<I::WritePacketFuture<'a>>,
+}
+
+impl<'a, I: AsyncEndpointIn + 'a, const N: usize> Sender<'a, I, N> {
+    fn new(ep: &'a mut I, packet: &'a mut TxPacket<N>) -> Self {
+        Self {
+            ep: Reborrow::new(ep),
+            packet: Reborrow::new(packet),
+            write: None,
+        }
+    }
+
+    /// Returns the packet buffer, must not be called while a write is in flight
+    fn packet(&mut self) -> &mut TxPacket<N> {
+        self.packet.get()
+    }
+
+    /// Returns the number of bytes that still fit into the packet being collected
+    fn space(&mut self) -> usize {
+        let max_packet_size = self.ep.get().max_packet_size();
+        max_packet_size - self.packet.get().len
+    }
+
+    /// Returns whether a write started by `start` is in flight
+    fn is_sending(&self) -> bool {
+        self.write.is_some()
+    }
+
+    /// Starts sending the packet buffer, which may be empty
+    fn start(&mut self) {
+        let packet = self.packet.lend();
+        self.write = Some(self.ep.lend().async_write_packet(&packet.buf[..packet.len]));
+    }
+
+    /// Starts flushing the endpoint, which stays with the returned future
+    fn flush(&mut self) -> I::FlushFuture<'a> {
+        self.ep.lend().async_flush()
+    }
+
+    /// Completes the write started by `start`, if any
+    ///
+    /// The packet buffer is emptied even if the write fails.
+    fn poll_send(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), I::Error>> {
+        // `write` is structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut write = unsafe { Pin::new_unchecked(&mut this.write) };
+        let result = match write.as_mut().as_pin_mut() {
+            Some(future) => match future.poll(cx) {
+                Poll::Ready(result) => result,
+                Poll::Pending => return Poll::Pending,
+            },
+            None => return Poll::Ready(Ok(())),
+        };
+        write.set(None);
+        // The write that borrowed them is gone
+        unsafe {
+            this.ep.give_back();
+            this.packet.give_back();
+        }
+
+        let full = this.space() == 0;
+        let packet = this.packet();
+        packet.open = full && result.is_ok();
+        packet.len = 0;
+        Poll::Ready(result)
+    }
+}
+
+pub struct PortWriteByteFuture<'a, I: AsyncEndpointIn + 'a, const N: usize> {
+    send: Sender<'a, I, N>,
+    byte: Option<u8>,
+}
+
+impl<'a, I: AsyncEndpointIn + 'a, const N: usize> Future for PortWriteByteFuture<'a, I, N> {
+    type Output = Result<(), I::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let send = unsafe { Pin::new_unchecked(&mut this.send) };
+            match send.poll_send(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let byte = match this.byte.take() {
+                Some(byte) => byte,
+                None => return Poll::Ready(Ok(())),
+            };
+            // A full packet is left over if an earlier future was dropped while sending it
+            if this.send.space() > 0 {
+                let packet = this.send.packet();
+                packet.buf[packet.len] = byte;
+                packet.len += 1;
+            } else {
+                this.byte = Some(byte);
+            }
+            if this.send.space() == 0 {
+                this.send.start();
+            }
+        }
+    }
+}
+
+pub struct PortWriteFuture<'a, I: AsyncEndpointIn + 'a, const N: usize> {
+    send: Sender<'a, I, N>,
+    data: &'a [u8],
+    offset: usize,
+    /// Bytes of `data` in the packet buffer, which are lost if sending it fails
+    unsent: usize,
+}
+
+impl<'a, I: AsyncEndpointIn + 'a, const N: usize> Future for PortWriteFuture<'a, I, N> {
+    type Output = Result<(), PartialError<I::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let sending = this.send.is_sending();
+            let send = unsafe { Pin::new_unchecked(&mut this.send) };
+            match send.poll_send(cx) {
+                Poll::Ready(Ok(())) => {
+                    if sending {
+                        this.unsent = 0;
+                    }
+                },
+                Poll::Ready(Err(error)) => {
+                    let offset = this.offset - this.unsent;
+                    return Poll::Ready(Err(PartialError { error, offset }));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+
+            if this.offset == this.data.len() {
+                return Poll::Ready(Ok(()));
+            }
+            let count = core::cmp::min(this.send.space(), this.data.len() - this.offset);
+            let data = &this.data[this.offset..this.offset + count];
+            let packet = this.send.packet();
+            packet.buf[packet.len..packet.len + count].copy_from_slice(data);
+            packet.len += count;
+            this.offset += count;
+            this.unsent += count;
+            if this.send.space() == 0 {
+                this.send.start();
+            }
+        }
+    }
+}
+
+pub struct PortFlushFuture<'a, I: AsyncEndpointIn + 'a, const N: usize> {
+    send: Sender<'a, I, N>,
+    flush: Option<I::FlushFuture<'a>>,
+}
+
+impl<'a, I: AsyncEndpointIn + 'a, const N: usize> Future for PortFlushFuture<'a, I, N> {
+    type Output = Result<(), I::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        while this.flush.is_none() {
+            let send = unsafe { Pin::new_unchecked(&mut this.send) };
+            match send.poll_send(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+
+            let packet = this.send.packet();
+            if packet.len > 0 || packet.open {
+                // Sending a short or zero-length packet ends the transfer
+                this.send.start();
+            } else {
+                this.flush = Some(this.send.flush());
+            }
+        }
+
+        let flush = unsafe { Pin::new_unchecked(&mut this.flush) };
+        flush.as_pin_mut().unwrap().poll(cx)
+    }
+}
+
+/// Reads packets from the OUT endpoint into the packet buffer
+///
+/// Lends out the endpoint and the packet buffer separately, like `Sender`.
+struct Receive<'a, O: AsyncEndpointOut + 'a, const N: usize> {
+    ep: Reborrow<'a, O>,
+    packet: Reborrow<'a, RxPacket<N>>,
+    read: Option<O::ReadPacketFuture<'a>>,
+}
+
+impl<'a, O: AsyncEndpointOut + 'a, const N: usize> Receive<'a, O, N> {
+    fn new(ep: &'a mut O, packet: &'a mut RxPacket<N>) -> Self {
+        Self {
+            ep: Reborrow::new(ep),
+            packet: Reborrow::new(packet),
+            read: None,
+        }
+    }
+
+    /// Returns the packet buffer, must not be called while a read is in flight
+    fn packet(&mut self) -> &mut RxPacket<N> {
+        self.packet.get()
+    }
+
+    /// Takes up to `data.len()` received bytes out of the packet buffer
+    fn take(&mut self, data: &mut [u8]) -> usize {
+        let packet = self.packet();
+        let count = core::cmp::min(data.len(), packet.len - packet.pos);
+        data[..count].copy_from_slice(&packet.buf[packet.pos..packet.pos + count]);
+        packet.pos += count;
+        count
+    }
+
+    /// Reads packets until the packet buffer holds at least one byte
+    fn poll_fill(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), O::Error>> {
+        // `read` is structurally pinned, the other fields are never pinned
+        let this = unsafe { self.get_unchecked_mut() };
+        loop {
+            let mut read = unsafe { Pin::new_unchecked(&mut this.read) };
+            if let Some(future) = read.as_mut().as_pin_mut() {
+                let result = match future.poll(cx) {
+                    Poll::Ready(result) => result,
+                    Poll::Pending => return Poll::Pending,
+                };
+                read.set(None);
+                // The read that borrowed them is gone
+                unsafe {
+                    this.ep.give_back();
+                    this.packet.give_back();
+                }
+
+                // A failed read may have clobbered the buffer, so nothing in it is valid anymore
+                let packet = this.packet();
+                packet.pos = 0;
+                match result {
+                    Ok(len) => packet.len = len,
+                    Err(e) => {
+                        packet.len = 0;
+                        return Poll::Ready(Err(e));
+                    },
+                }
+            }
+
+            let packet = this.packet();
+            if packet.pos < packet.len {
+                return Poll::Ready(Ok(()));
+            }
+            let packet = this.packet.lend();
+            this.read = Some(this.ep.lend().async_read_packet(&mut packet.buf));
+        }
+    }
+}
+
+pub struct PortReadByteFuture<'a, O: AsyncEndpointOut + 'a, const N: usize> {
+    receive: Receive<'a, O, N>,
+}
+
+impl<'a, O: AsyncEndpointOut + 'a, const N: usize> Future for PortReadByteFuture<'a, O, N> {
+    type Output = Result<u8, O::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let receive = unsafe { Pin::new_unchecked(&mut this.receive) };
+        match receive.poll_fill(cx) {
+            Poll::Ready(Ok(())) => {
+                let mut byte = [0];
+                this.receive.take(&mut byte);
+                Poll::Ready(Ok(byte[0]))
+            },
+            Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+pub struct PortReadFuture<'a, O: AsyncEndpointOut + 'a, const N: usize> {
+    receive: Receive<'a, O, N>,
+    data: &'a mut [u8],
+    offset: usize,
+}
+
+impl<'a, O: AsyncEndpointOut + 'a, const N: usize> Future for PortReadFuture<'a, O, N> {
+    type Output = Result<(), PartialError<O::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        while this.offset < this.data.len() {
+            let receive = unsafe { Pin::new_unchecked(&mut this.receive) };
+            match receive.poll_fill(cx) {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(error)) => {
+                    return Poll::Ready(Err(PartialError { error, offset: this.offset }));
+                },
+                Poll::Pending => return Poll::Pending,
+            }
+            this.offset += this.receive.take(&mut this.data[this.offset..]);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct PortReadSomeFuture<'a, O: AsyncEndpointOut + 'a, const N: usize> {
+    receive: Receive<'a, O, N>,
+    data: &'a mut [u8],
+}
+
+impl<'a, O: AsyncEndpointOut + 'a, const N: usize> Future for PortReadSomeFuture<'a, O, N> {
+    type Output = Result<usize, PartialError<O::Error>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        if this.data.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let receive = unsafe { Pin::new_unchecked(&mut this.receive) };
+        match receive.poll_fill(cx) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(this.receive.take(this.data))),
+            Poll::Ready(Err(error)) => Poll::Ready(Err(PartialError { error, offset: 0 })),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Waker;
+
+/// Peripheral event a future can wait on
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Receive FIFO has at least one byte available
+    RxNotEmpty,
+    /// Transmit FIFO has space for at least one byte
+    TxSpace,
+    /// Transmitter has finished sending all buffered data
+    TxIdle,
+}
+
+/// Interrupt-driven wakeup source of a peripheral
+///
+/// Default futures call `register_waker` instead of spinning on `WouldBlock`.
+/// Implementers must arrange for `waker` to be woken once `event` occurs,
+/// usually from the interrupt handler. If the event condition already holds
+/// when the waker is registered, the waker must be woken right away.
+pub trait RegisterWaker {
+    /// Requests a wakeup when `event` occurs
+    fn register_waker(&self, event: Event, waker: &Waker);
+}
+
+const WAITING: usize = 0;
+const REGISTERING: usize = 0b01;
+const WAKING: usize = 0b10;
+
+/// Slot for a single waker shared between a future and an interrupt handler
+///
+/// Registering replaces the previously stored waker, waking takes it out of the slot.
+/// Both operations are lock-free and can race with each other safely.
+pub struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+unsafe impl Send for AtomicWaker {}
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    /// Stores `waker` to be woken by the next call to `wake`
+    pub fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(WAITING, REGISTERING, Ordering::Acquire, Ordering::Acquire) {
+            Ok(_) => {
+                unsafe {
+                    *self.waker.get() = Some(waker.clone());
+                }
+
+                if self.state.compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                    // `wake` was called while we were registering, it couldn't take the waker
+                    let waker = unsafe { (*self.waker.get()).take() };
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            },
+            Err(WAKING) => {
+                // Being woken right now, make sure the new waker is not missed
+                waker.wake_by_ref();
+            },
+            Err(_) => {
+                // Concurrent call to `register`, one of the wakers wins
+            }
+        }
+    }
+
+    /// Wakes the registered waker, if any
+    pub fn wake(&self) {
+        if let Some(waker) = self.take() {
+            waker.wake();
+        }
+    }
+
+    /// Takes the registered waker out of the slot
+    pub fn take(&self) -> Option<Waker> {
+        match self.state.fetch_or(WAKING, Ordering::AcqRel) {
+            WAITING => {
+                let waker = unsafe { (*self.waker.get()).take() };
+                self.state.fetch_and(!WAKING, Ordering::Release);
+                waker
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Default for AtomicWaker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
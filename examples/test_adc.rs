@@ -0,0 +1,81 @@
+#![allow(dead_code)]
+
+use async_trait_poc::adc::*;
+use async_trait_poc::clock::Clock;
+use embedded_async_sandbox::adc::{AsyncOneShot, AsyncSample, Continuous};
+use embedded_async_sandbox::timer::AsyncDelay;
+
+struct AsyncDriver<ADC> {
+    adc: ADC
+}
+
+impl<ADC: AsyncSample<u16>> AsyncDriver<ADC> {
+    pub fn new(adc: ADC) -> Self {
+        Self {
+            adc
+        }
+    }
+
+    /// Averages a full period of a 16-tick signal sampled every tick
+    async fn average(&mut self) -> Result<u16, ADC::Error> {
+        let mut buf = [0; 16];
+        self.adc.async_sample(&mut buf).await?;
+        let sum: u32 = buf.iter().map(|s| *s as u32).sum();
+        Ok((sum / buf.len() as u32) as u16)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut adc = Adc::new();
+    adc.set_input(0, Waveform::Ramp { start: 100, step: 10 });
+    adc.set_input(1, Waveform::Square { low: 0, high: 4000, period: 10 });
+    adc.set_continuous(0, 3);
+    let mut driver = AdcDriver::new(adc);
+    clock.attach(driver.irq());
+
+    // A conversion takes 2 ticks and samples its input at the end
+    assert_eq!(clock.run_until(driver.async_read(&mut Ain0)).await, Ok(100 + 10 * 2));
+    assert_eq!(clock.run_until(driver.async_read(&mut Ain1)).await, Ok(4000));
+    assert_eq!(clock.run_until(driver.async_read(&mut Ain1)).await, Ok(0));
+    assert_eq!(clock.now(), 6);
+
+    // Continuous mode samples every 3 ticks
+    let mut buf = [0; 4];
+    clock.run_until(driver.async_sample(&mut buf)).await.unwrap();
+    assert_eq!(buf, [100 + 10 * 9, 100 + 10 * 12, 100 + 10 * 15, 100 + 10 * 18]);
+    assert_eq!(clock.now(), 18);
+
+    // Samples that are not picked up in time are reported
+    driver.start();
+    clock.run_until(clock.timer().async_delay(7)).await;
+    assert_eq!(driver.read(), Err(nb::Error::Other(AdcError::Overrun)));
+    assert_eq!(driver.read(), Ok(100 + 10 * 24));
+    driver.stop();
+
+    let mut adc = Adc::new();
+    adc.set_input(1, Waveform::Sine { offset: 2048, amplitude: 1000, period: 16 });
+    adc.set_continuous(1, 1);
+    let mut driver = AsyncDriver::new(AdcDriver::new(adc));
+    clock.attach(driver.adc.irq());
+    let average = clock.run_until(driver.average()).await.unwrap();
+    assert!((2047..=2049).contains(&average));
+
+    // A result left behind by a cancelled read does not block reads of other channels
+    let mut adc = Adc::new();
+    adc.set_input(1, Waveform::Constant(1234));
+    let mut driver = AdcDriver::new(adc);
+    clock.attach(driver.irq());
+    {
+        let mut pin = Ain0;
+        let read = driver.async_read(&mut pin);
+        futures::pin_mut!(read);
+        assert!(futures::poll!(read).is_pending());
+    }
+    clock.run_until(clock.timer().async_delay(3)).await;
+    assert_eq!(clock.run_until(driver.async_read(&mut Ain1)).await, Ok(1234));
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+#![allow(dead_code)]
+
+use async_trait_poc::can::*;
+use async_trait_poc::clock::Clock;
+use embedded_async_sandbox::can::*;
+use embedded_async_sandbox::timer::AsyncDelay;
+
+fn standard(id: u16) -> StandardId {
+    StandardId::new(id).unwrap()
+}
+
+fn extended(id: u32) -> ExtendedId {
+    ExtendedId::new(id).unwrap()
+}
+
+fn ids(log: &[(usize, Frame)]) -> Vec<Id> {
+    log.iter().map(|(_, frame)| frame.id()).collect()
+}
+
+struct AsyncDriver<CAN> {
+    can: CAN
+}
+
+impl<CAN: AsyncTransmit + AsyncReceive<Error=<CAN as AsyncTransmit>::Error>> AsyncDriver<CAN> {
+    pub fn new(can: CAN) -> Self {
+        Self {
+            can
+        }
+    }
+
+    /// Sends a remote frame and waits for the data frame answering it
+    async fn request(&mut self, id: StandardId) -> Result<Frame, <CAN as AsyncTransmit>::Error> {
+        self.can.async_transmit(Frame::new_remote(id, 8).unwrap()).await?;
+        loop {
+            let frame = self.can.async_receive().await?;
+            if frame.id() == Id::Standard(id) && frame.is_data_frame() {
+                return Ok(frame);
+            }
+        }
+    }
+}
+
+trait DataFrame {
+    fn is_data_frame(&self) -> bool;
+}
+
+impl DataFrame for Frame {
+    fn is_data_frame(&self) -> bool {
+        !self.is_remote_frame()
+    }
+}
+
+/// Lets a node with `mailboxes` mailboxes send a low and then a high priority frame,
+/// while another node keeps sending medium priority frames
+async fn priority_order(mailboxes: usize) -> Vec<Id> {
+    let clock = Clock::new();
+    let bus = CanBus::new();
+    let mut sender = bus.connect(mailboxes);
+    let mut stream = bus.connect(3);
+    clock.attach(bus.irq());
+
+    let low = async {
+        sender.async_transmit(Frame::new(standard(0x700), &[1]).unwrap()).await.unwrap();
+        sender.async_transmit(Frame::new(standard(0x010), &[2]).unwrap()).await.unwrap();
+    };
+    let medium = async {
+        for i in 0..5 {
+            stream.async_transmit(Frame::new(standard(0x400), &[i]).unwrap()).await.unwrap();
+        }
+    };
+    clock.run_until(async { tokio::join!(low, medium) }).await;
+    clock.run_until(clock.timer().async_delay(100)).await;
+    ids(&bus.log())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let bus = CanBus::new();
+    let mut a = bus.connect(1);
+    let mut b = bus.connect(3);
+    clock.attach(bus.irq());
+
+    // Arbitration takes a tick, a standard frame with 3 data bytes another 4 + 3
+    let frame = Frame::new(standard(0x123), &[1, 2, 3]).unwrap();
+    let (sent, received) = clock.run_until(async { tokio::join!(a.async_transmit(frame), b.async_receive()) }).await;
+    assert_eq!(sent, Ok(()));
+    assert_eq!(received, Ok(frame));
+    assert_eq!(clock.now(), 8);
+
+    // Only frames passing one of the filters are received
+    assert_eq!(b.set_filters(&[Filter::new(standard(0x100), 0x700); 5]), Err(TooManyFilters));
+    b.set_filters(&[Filter::new(standard(0x100), 0x700), Filter::exact(extended(0x18da_f110))]).unwrap();
+    for frame in &[
+        Frame::new(standard(0x1ab), &[]).unwrap(),
+        Frame::new(standard(0x2ab), &[]).unwrap(),
+        Frame::new(extended(0x18da_f111), &[]).unwrap(),
+        Frame::new(extended(0x18da_f110), &[0xaa]).unwrap(),
+    ] {
+        clock.run_until(a.async_transmit(*frame)).await.unwrap();
+    }
+    assert_eq!(clock.run_until(b.async_receive()).await.map(|f| f.id()), Ok(Id::Standard(standard(0x1ab))));
+    assert_eq!(clock.run_until(b.async_receive()).await.map(|f| f.id()), Ok(Id::Extended(extended(0x18da_f110))));
+    assert_eq!(bus.log().len(), 5);
+
+    // A full receive FIFO loses frames
+    for i in 0..4 {
+        clock.run_until(a.async_transmit(Frame::new(standard(0x100), &[i]).unwrap())).await.unwrap();
+    }
+    clock.run_until(clock.timer().async_delay(10)).await;
+    assert_eq!(b.receive(), Err(nb::Error::Other(CanError::Overrun)));
+    for i in 0..3 {
+        assert_eq!(b.receive().map(|f| f.data()[0]), Ok(i));
+    }
+    assert_eq!(b.receive(), Err(nb::Error::WouldBlock));
+
+    // Remote frames request data from another node
+    b.set_filters(&[Filter::exact(standard(0x321))]).unwrap();
+    let mut driver = AsyncDriver::new(b);
+    let responder = async {
+        let request = a.async_receive().await.unwrap();
+        assert!(request.is_remote_frame());
+        assert_eq!(request.dlc(), 8);
+        a.async_transmit(Frame::new(standard(0x321), b"response").unwrap()).await.unwrap();
+    };
+    let (response, ()) = clock.run_until(async { tokio::join!(driver.request(standard(0x321)), responder) }).await;
+    assert_eq!(response.unwrap().data(), b"response");
+
+    // Lower identifiers win, a standard frame beats an extended one with the same base identifier
+    let clock = Clock::new();
+    let bus = CanBus::new();
+    let mut nodes = [bus.connect(1), bus.connect(1), bus.connect(1)];
+    clock.attach(bus.irq());
+    nodes[0].transmit(&Frame::new(standard(0x300), &[]).unwrap()).unwrap();
+    nodes[1].transmit(&Frame::new(extended(0x200 << 18), &[]).unwrap()).unwrap();
+    nodes[2].transmit(&Frame::new(standard(0x200), &[]).unwrap()).unwrap();
+    clock.run_until(clock.timer().async_delay(30)).await;
+    assert_eq!(bus.log().iter().map(|(node, _)| *node).collect::<Vec<_>>(), [2, 1, 0]);
+
+    // With a single mailbox the high priority frame is stuck behind the low priority one,
+    // which loses every arbitration against the medium priority stream
+    let order = priority_order(1).await;
+    let position = |id| order.iter().position(|i| *i == Id::Standard(standard(id))).unwrap();
+    assert_eq!(order.len(), 7);
+    assert_eq!(position(0x010), 6);
+    let order = priority_order(3).await;
+    assert_eq!(order[0], Id::Standard(standard(0x010)));
+    assert_eq!(order[6], Id::Standard(standard(0x700)));
+
+    // Missing acknowledges make a lone node error-passive, where it stops counting them
+    let clock = Clock::new();
+    let bus = CanBus::new();
+    let mut lone = bus.connect(1);
+    clock.attach(bus.irq());
+    lone.transmit(&Frame::new(standard(0x001), &[]).unwrap()).unwrap();
+    clock.run_until(clock.timer().async_delay(200)).await;
+    assert_eq!(lone.error_state(), ErrorState::Passive);
+    assert_eq!(lone.error_counters(), (128, 0));
+
+    // Every bit error counts 8, every successful frame takes one off
+    let mut peer = bus.connect(1);
+    lone.recover();
+    lone.inject_tx_errors(17);
+    clock.run_until(clock.timer().async_delay(200)).await;
+    assert_eq!(bus.log().len(), 1);
+    assert_eq!(peer.receive().map(|f| f.id()), Ok(Id::Standard(standard(0x001))));
+    assert_eq!(lone.error_counters(), (17 * 8 - 1, 0));
+    assert_eq!(lone.error_state(), ErrorState::Passive);
+
+    // Bus-off drops the pending frames and fails all operations until the node recovers
+    lone.inject_tx_errors(16);
+    clock.run_until(lone.async_transmit(Frame::new(standard(0x002), &[]).unwrap())).await.unwrap();
+    clock.run_until(clock.timer().async_delay(200)).await;
+    assert_eq!(lone.error_state(), ErrorState::BusOff);
+    assert_eq!(bus.log().len(), 1);
+    let frame = Frame::new(standard(0x003), &[]).unwrap();
+    assert_eq!(clock.run_until(lone.async_transmit(frame)).await, Err(CanError::BusOff));
+    assert_eq!(clock.run_until(lone.async_receive()).await, Err(CanError::BusOff));
+    lone.recover();
+    assert_eq!(lone.error_state(), ErrorState::Active);
+    let (sent, received) = clock.run_until(async { tokio::join!(lone.async_transmit(frame), peer.async_receive()) }).await;
+    assert_eq!(sent, Ok(()));
+    assert_eq!(received, Ok(frame));
+
+    // Every receive error counts 8 and loses the frame, every received frame takes one off
+    peer.inject_rx_errors(16);
+    for _ in 0..16 {
+        clock.run_until(lone.async_transmit(frame)).await.unwrap();
+    }
+    clock.run_until(clock.timer().async_delay(20)).await;
+    assert_eq!(peer.error_counters(), (0, 16 * 8));
+    assert_eq!(peer.error_state(), ErrorState::Passive);
+    assert_eq!(peer.receive(), Err(nb::Error::WouldBlock));
+    let (sent, received) = clock.run_until(async { tokio::join!(lone.async_transmit(frame), peer.async_receive()) }).await;
+    assert_eq!(sent, Ok(()));
+    assert_eq!(received, Ok(frame));
+    assert_eq!(peer.error_counters(), (0, 16 * 8 - 1));
+    assert_eq!(peer.error_state(), ErrorState::Active);
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+#![allow(dead_code)]
+
+use async_trait_poc::capture::*;
+use async_trait_poc::clock::Clock;
+use embedded_async_sandbox::capture::AsyncCapture;
+use embedded_async_sandbox::timer::AsyncDelay;
+use embedded_hal::Capture;
+
+struct AsyncDriver<C> {
+    timer: C
+}
+
+impl<C: AsyncCapture<Capture=u32>> AsyncDriver<C> where C::Channel: Copy {
+    pub fn new(timer: C) -> Self {
+        Self {
+            timer
+        }
+    }
+
+    /// Averages the time between `n + 1` consecutive edges
+    async fn average_period(&mut self, channel: C::Channel, n: u32) -> Result<u32, C::Error> {
+        let first = self.timer.async_capture(channel).await?;
+        let mut last = first;
+        for _ in 0..n {
+            last = self.timer.async_capture(channel).await?;
+        }
+        Ok(last.wrapping_sub(first) / n)
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut timer = CaptureTimer::new();
+    timer.play(Channel::Ch0, vec![10, 25, 40, 55, 70]);
+    timer.play(Channel::Ch1, vec![5, 80, 82]);
+    let mut driver = CaptureDriver::new(timer);
+    clock.attach(driver.irq());
+    driver.enable(Channel::Ch0);
+
+    // Captures hold the counter value at the edge
+    assert_eq!(clock.run_until(driver.async_capture(Channel::Ch0)).await, Ok(10));
+    assert_eq!(clock.now(), 10);
+    assert_eq!(clock.run_until(driver.async_period(Channel::Ch0)).await, Ok(15));
+    assert_eq!(clock.now(), 40);
+
+    // The counter runs slower at a coarser resolution
+    driver.set_resolution(5u32);
+    assert_eq!(driver.get_resolution(), 5);
+    assert_eq!(clock.run_until(driver.async_period(Channel::Ch0)).await, Ok(3));
+    assert_eq!(clock.now(), 70);
+
+    // The edge on the disabled channel is lost, the two late ones overlap
+    driver.enable(Channel::Ch1);
+    clock.run_until(clock.timer().async_delay(20)).await;
+    assert_eq!(driver.capture(Channel::Ch1), Err(nb::Error::Other(CaptureError::Overcapture)));
+    assert_eq!(driver.capture(Channel::Ch1), Ok(82 / 5));
+    assert_eq!(driver.capture(Channel::Ch1), Err(nb::Error::WouldBlock));
+    driver.disable(Channel::Ch1);
+
+    let mut timer = CaptureTimer::new();
+    timer.play(Channel::Ch1, vec![3, 10, 18, 24, 31, 38]);
+    let mut driver = AsyncDriver::new(CaptureDriver::new(timer));
+    clock.attach(driver.timer.irq());
+    driver.timer.enable(Channel::Ch1);
+    assert_eq!(clock.run_until(driver.average_period(Channel::Ch1, 5)).await, Ok(7));
+
+    // Periods stay correct when the counter overflows between the edges
+    let clock = Clock::new();
+    let mut timer = CaptureTimer::new();
+    timer.set_counter(u32::MAX - 4);
+    timer.play(Channel::Ch0, vec![2, 12]);
+    let mut driver = CaptureDriver::new(timer);
+    clock.attach(driver.irq());
+    driver.enable(Channel::Ch0);
+    assert_eq!(clock.run_until(driver.async_period(Channel::Ch0)).await, Ok(10));
+
+    Ok(())
+}
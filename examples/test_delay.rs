@@ -0,0 +1,98 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::serial::*;
+use embedded_async_sandbox::serial::{AsyncWrite, PartialError};
+use embedded_async_sandbox::timer::{delay_ms, delay_us, AsyncDelayMs, AsyncDelayUs};
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+use std::time::{Duration, Instant};
+
+/// Blocking delay sleeping the current thread
+struct SleepDelay;
+
+impl DelayMs<u32> for SleepDelay {
+    fn delay_ms(&mut self, ms: u32) {
+        std::thread::sleep(Duration::from_millis(ms as u64));
+    }
+}
+
+impl DelayUs<u32> for SleepDelay {
+    fn delay_us(&mut self, us: u32) {
+        std::thread::sleep(Duration::from_micros(us as u64));
+    }
+}
+
+impl delay_ms::Default for SleepDelay {}
+
+impl delay_us::Default for SleepDelay {}
+
+struct AsyncDriver<UART, DELAY> {
+    uart: UART,
+    delay: DELAY,
+}
+
+impl<UART: AsyncWrite, DELAY: AsyncDelayMs + AsyncDelayUs> AsyncDriver<UART, DELAY> {
+    pub fn new(uart: UART, delay: DELAY) -> Self {
+        Self {
+            uart,
+            delay,
+        }
+    }
+
+    /// Sends a command to a modem that needs a guard time of silence around "+++"
+    async fn enter_command_mode(&mut self) -> Result<(), UART::Error> {
+        self.delay.async_delay_ms(1).await;
+        self.uart.async_write(b"+++").await.map_err(PartialError::into_inner)?;
+        self.uart.async_flush().await?;
+        self.delay.async_delay_us(250).await;
+        Ok(())
+    }
+}
+
+async fn finish_after<D: AsyncDelayMs>(clock: &Clock, mut delay: D, ms: u32) -> u64 {
+    delay.async_delay_ms(ms).await;
+    clock.now()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let mut timer = clock.timer();
+
+    // A tick is 100µs, partial ticks are rounded up
+    clock.run_until(timer.async_delay_ms(2)).await;
+    assert_eq!(clock.now(), 20);
+    clock.run_until(timer.async_delay_us(250)).await;
+    assert_eq!(clock.now(), 23);
+    assert_eq!(clock.now_us(), 2300);
+
+    // Deadlines 33 and 353 are five turns of the 64 slot timer wheel apart and share a slot,
+    // the later one stays there until its turn comes
+    let finished = clock.run_until(async {
+        tokio::join!(
+            finish_after(&clock, clock.timer(), 1),
+            finish_after(&clock, clock.timer(), 33),
+            finish_after(&clock, clock.timer(), 7),
+            finish_after(&clock, clock.timer(), 1),
+        )
+    }).await;
+    assert_eq!(finished, (23 + 10, 23 + 330, 23 + 70, 23 + 10));
+
+    // The clock drives the UART model: a byte takes 4 ticks on the line
+    let serial = Serial::new(Uart::new());
+    clock.attach(serial.irq());
+    let mut driver = AsyncDriver::new(serial, clock.timer());
+    let start = clock.now_us();
+    clock.run_until(driver.enter_command_mode()).await.unwrap();
+    assert_eq!(clock.now_us() - start, 1000 + 3 * 400 + 300);
+    assert_eq!(driver.uart.transmitted(), b"+++");
+
+    // The blocking fallback takes real time, but completes on the first poll
+    let mut driver = AsyncDriver::new(Serial::new(Uart::new()), SleepDelay);
+    let start = Instant::now();
+    driver.delay.async_delay_ms(5).await;
+    driver.delay.async_delay_us(500).await;
+    assert!(start.elapsed() >= Duration::from_micros(5500));
+
+    Ok(())
+}
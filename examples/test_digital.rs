@@ -0,0 +1,61 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::gpio::*;
+use embedded_async_sandbox::digital::AsyncInputPin;
+
+struct AsyncDriver<PIN> {
+    data_ready: PIN
+}
+
+impl<PIN: AsyncInputPin> AsyncDriver<PIN> {
+    pub fn new(data_ready: PIN) -> Self {
+        Self {
+            data_ready
+        }
+    }
+
+    /// Waits for the sensor to signal a new sample with an active high pulse
+    async fn wait_for_sample(&mut self) -> Result<(), PIN::Error> {
+        self.data_ready.wait_for_rising_edge().await?;
+        self.data_ready.wait_for_low().await
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut pin = ScriptedPin::new(false);
+    pin.script(vec![
+        PinEvent::Hold(5), PinEvent::High,
+        PinEvent::Hold(3), PinEvent::Low,
+        PinEvent::Hold(4), PinEvent::High,
+        PinEvent::Hold(2), PinEvent::Low,
+        PinEvent::Hold(2), PinEvent::High, PinEvent::Low,
+        PinEvent::Hold(2), PinEvent::High,
+        PinEvent::Hold(3), PinEvent::Low,
+    ]);
+    clock.attach(pin.irq());
+
+    // Levels that already hold complete right away
+    clock.run_until(pin.wait_for_low()).await.unwrap();
+    assert_eq!(clock.now(), 0);
+
+    clock.run_until(pin.wait_for_high()).await.unwrap();
+    assert_eq!(clock.now(), 5);
+    clock.run_until(pin.wait_for_high()).await.unwrap();
+    assert_eq!(clock.now(), 5);
+
+    clock.run_until(pin.wait_for_falling_edge()).await.unwrap();
+    assert_eq!(clock.now(), 8);
+    clock.run_until(pin.wait_for_any_edge()).await.unwrap();
+    assert_eq!(clock.now(), 12);
+
+    // The pin goes low at tick 14, the zero-length pulse at tick 16 is too short to be seen
+    let mut driver = AsyncDriver::new(pin);
+    clock.run_until(driver.wait_for_sample()).await.unwrap();
+    assert_eq!(clock.now(), 21);
+
+    Ok(())
+}
@@ -0,0 +1,108 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::dma::DmaEngine;
+use async_trait_poc::serial::*;
+use async_trait_poc::spi::*;
+use embedded_async_sandbox::dma::{DmaReader, DmaSpi, DmaWriter};
+use embedded_async_sandbox::serial::{AsyncRead, AsyncWrite, PartialError};
+use embedded_async_sandbox::spi::AsyncTransfer;
+use embedded_async_sandbox::timer::{with_timeout, AsyncDelay, TimeoutError};
+
+fn dma_buffer(size: usize) -> &'static mut [u8] {
+    Box::leak(vec![0; size].into_boxed_slice())
+}
+
+async fn loopback(spi: &mut impl AsyncTransfer) -> u8 {
+    let mut buf = [0; 32];
+    for (i, b) in buf.iter_mut().enumerate() {
+        *b = (i + 1) as u8;
+    }
+    assert!(spi.async_transfer(&mut buf).await.is_ok());
+    for (i, b) in buf.iter().enumerate() {
+        assert_eq!(*b, !((i + 1) as u8));
+    }
+    buf.len() as u8
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let engine = DmaEngine::new();
+
+    // Writes are split into chunks of the buffer size
+    let serial = Serial::new(Uart::new());
+    clock.attach(serial.irq());
+    clock.attach(engine.irq());
+    let channel = engine.tx_channel(serial.dma_tx());
+    let mut writer = DmaWriter::new(serial, channel, dma_buffer(8));
+    let message = b"Hello from the DMA engine";
+    clock.run_until(writer.async_write(message)).await.unwrap();
+    clock.run_until(writer.async_flush()).await.unwrap();
+    assert_eq!(&writer.get_ref().transmitted()[..], &message[..]);
+
+    // Dropping the future stops the channel, only bytes already in the FIFO go out
+    let mut timer = clock.timer();
+    let result = clock.run_until(with_timeout(writer.async_write(message), timer.async_delay(20))).await;
+    assert_eq!(result, Err(TimeoutError::Timeout));
+    clock.run_until(timer.async_delay(200)).await;
+    let sent = writer.get_ref().transmitted().len() - message.len();
+    assert!(sent > 0 && sent < message.len());
+
+    // A leaked future leaves the channel running on the buffer it owns,
+    // the next write stops it before reusing the buffer
+    let mut write = Box::pin(writer.async_write(message));
+    let result = clock.run_until(with_timeout(write.as_mut(), timer.async_delay(20))).await;
+    assert_eq!(result, Err(TimeoutError::Timeout));
+    std::mem::forget(write);
+    clock.run_until(writer.async_write(b"!")).await.unwrap();
+    clock.run_until(writer.async_flush()).await.unwrap();
+    let transmitted = writer.get_ref().transmitted();
+    assert_eq!(transmitted.last(), Some(&b'!'));
+    let leaked = &transmitted[message.len() + sent..transmitted.len() - 1];
+    assert!(message.starts_with(leaked));
+
+    // Errors report how far the transfer got, the UART rejects 0xff once it has been sent
+    let result = clock.run_until(writer.async_write(&[1, 0xff, 2, 3, 4, 5, 6, 7, 8, 9])).await;
+    assert_eq!(result, Err(PartialError { error: UartError::InvalidData, offset: 5 }));
+
+    let clock = Clock::new();
+    let engine = DmaEngine::new();
+    let mut uart = Uart::new();
+    uart.script_rx(b"DMA!".iter().map(|b| RxEvent::Byte(*b)));
+    uart.script_rx(vec![RxEvent::Byte(b'x'), RxEvent::FramingError]);
+    let serial = Serial::new(uart);
+    clock.attach(serial.irq());
+    clock.attach(engine.irq());
+    let channel = engine.rx_channel(serial.dma_rx());
+    let mut reader = DmaReader::new(serial, channel, dma_buffer(3));
+    let mut buf = [0; 4];
+    clock.run_until(reader.async_read(&mut buf)).await.unwrap();
+    assert_eq!(&buf, b"DMA!");
+    let result = clock.run_until(reader.async_read(&mut buf)).await;
+    assert_eq!(result, Err(PartialError { error: UartError::FramingError, offset: 1 }));
+    assert_eq!(buf[0], b'x');
+
+    // The channels keep the FIFOs full, so the 32 bytes take hardly more than 4 ticks each
+    let clock = Clock::new();
+    let engine = DmaEngine::new();
+    let spi = DummySpi::new();
+    clock.attach(spi.irq());
+    clock.attach(engine.irq());
+    let mut dma = DmaSpi::new(
+        engine.tx_channel(spi.dma_tx()),
+        engine.rx_channel(spi.dma_rx()),
+        dma_buffer(16),
+        dma_buffer(16),
+    );
+    assert_eq!(clock.run_until(loopback(&mut dma)).await, 32);
+    assert_eq!(clock.now(), 130);
+
+    let mut response = [0; 4];
+    dma.set_fill_byte(0xa5);
+    clock.run_until(dma.async_transfer_split(&mut response, &[0x9f])).await.unwrap();
+    assert_eq!(response, [0x60, 0x5a, 0x5a, 0x5a]);
+    assert_eq!(spi.shifted().iter().rev().take(4).map(|s| s.byte).collect::<Vec<_>>(), [0xa5, 0xa5, 0xa5, 0x9f]);
+
+    Ok(())
+}
@@ -0,0 +1,86 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::i2c::*;
+use embedded_async_sandbox::i2c::{AsyncRead, AsyncWrite, AsyncWriteRead};
+
+struct AsyncDriver<I2C> {
+    i2c: I2C
+}
+
+impl<I2C, E> AsyncDriver<I2C>
+where
+    I2C: AsyncWrite<Error=E> + AsyncRead<Error=E> + AsyncWriteRead<Error=E>,
+{
+    pub fn new(i2c: I2C) -> Self {
+        Self {
+            i2c
+        }
+    }
+
+    async fn read_registers(&mut self, address: u8, register: u8) -> Result<[u8; 2], E> {
+        let mut buf = [0; 2];
+        self.i2c.async_write_read(address, &[register], &mut buf).await?;
+        Ok(buf)
+    }
+
+    async fn read_next(&mut self, address: u8) -> Result<u8, E> {
+        let mut buf = [0; 1];
+        self.i2c.async_read(address, &mut buf).await?;
+        Ok(buf[0])
+    }
+
+    async fn write_registers(&mut self, address: u8, register_and_values: &[u8]) -> Result<(), E> {
+        self.i2c.async_write(address, register_and_values).await
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut bus = Bus::new();
+    bus.attach(FakeDevice::new(0x48, &[0x10, 0x20, 0x30, 0x40]));
+    let mut slow = FakeDevice::new(0x49, &[0x11, 0x22, 0x33, 0x44]);
+    slow.set_stretch(5);
+    bus.attach(slow);
+    let mut picky = FakeDevice::new(0x50, &[0; 4]);
+    picky.set_nack_after(2);
+    bus.attach(picky);
+    // The write to the picky device below is interrupted by another master
+    bus.script_arbitration_loss(7);
+
+    let i2c = I2c::new(bus);
+    clock.attach(i2c.irq());
+    let mut driver = AsyncDriver::new(i2c);
+
+    // Start, register, repeated start, two data bytes and stop take 2 ticks each
+    let start = clock.now();
+    assert_eq!(clock.run_until(driver.read_registers(0x48, 1)).await, Ok([0x20, 0x30]));
+    assert_eq!(clock.now() - start, 12);
+    assert_eq!(clock.run_until(driver.read_next(0x48)).await, Ok(0x40));
+
+    // The slow device stretches the clock on each of the three data bytes
+    let start = clock.now();
+    assert_eq!(clock.run_until(driver.read_registers(0x49, 2)).await, Ok([0x33, 0x44]));
+    assert_eq!(clock.now() - start, 12 + 3 * 5);
+
+    clock.run_until(driver.write_registers(0x48, &[2, 0xaa, 0xbb])).await.unwrap();
+    assert_eq!(driver.i2c.registers(0x48), [0x10, 0x20, 0xaa, 0xbb]);
+
+    // Nobody answers at 0x51
+    assert_eq!(clock.run_until(driver.read_next(0x51)).await, Err(I2cError::AddressNack));
+
+    // Lost arbitration is reported, the retry goes through
+    let result = clock.run_until(driver.write_registers(0x50, &[0, 1])).await;
+    assert_eq!(result, Err(I2cError::ArbitrationLoss));
+    clock.run_until(driver.write_registers(0x50, &[0, 1])).await.unwrap();
+    assert_eq!(driver.i2c.registers(0x50), [1, 0, 0, 0]);
+
+    // The picky device only takes the register and one value
+    let result = clock.run_until(driver.write_registers(0x50, &[1, 2, 3])).await;
+    assert_eq!(result, Err(I2cError::DataNack));
+    assert_eq!(driver.i2c.registers(0x50), [1, 2, 0, 0]);
+
+    Ok(())
+}
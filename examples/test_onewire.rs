@@ -0,0 +1,145 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::onewire::*;
+use async_trait_poc::serial::UartError;
+use embedded_async_sandbox::onewire::*;
+
+struct AsyncDriver<BUS> {
+    bus: BUS
+}
+
+impl<BUS: AsyncOneWire> AsyncDriver<BUS> {
+    pub fn new(bus: BUS) -> Self {
+        Self {
+            bus
+        }
+    }
+
+    async fn find_devices(&mut self) -> Result<Vec<Rom>, SearchError<BUS::Error>> {
+        let mut devices = Vec::new();
+        let mut search = RomSearch::new(&mut self.bus);
+        while let Some(rom) = search.async_next().await? {
+            devices.push(rom);
+        }
+        Ok(devices)
+    }
+
+    async fn select(&mut self, rom: Rom) -> Result<(), BUS::Error> {
+        assert!(self.bus.async_reset().await?);
+        self.bus.async_write_byte(MATCH_ROM).await?;
+        for byte in &rom.to_bytes() {
+            self.bus.async_write_byte(*byte).await?;
+        }
+        Ok(())
+    }
+
+    /// Starts a conversion on all sensors at once
+    async fn convert_all(&mut self) -> Result<(), BUS::Error> {
+        assert!(self.bus.async_reset().await?);
+        self.bus.async_write_byte(SKIP_ROM).await?;
+        self.bus.async_write_byte(CONVERT_T).await
+    }
+
+    /// Reads the temperature in 1/16 °C, returns `None` if the scratchpad is corrupted
+    async fn read_temperature(&mut self, rom: Rom) -> Result<Option<i16>, BUS::Error> {
+        self.select(rom).await?;
+        self.bus.async_write_byte(READ_SCRATCHPAD).await?;
+        let mut scratchpad = [0; 9];
+        for byte in scratchpad.iter_mut() {
+            *byte = self.bus.async_read_byte().await?;
+        }
+        if crc8(&scratchpad[..8]) != scratchpad[8] {
+            return Ok(None);
+        }
+        Ok(Some(i16::from_le_bytes([scratchpad[0], scratchpad[1]])))
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    assert_eq!(crc8(&[0x02, 0x1c, 0xb8, 0x01, 0x00, 0x00, 0x00]), 0xa2);
+
+    let clock = Clock::new();
+    let uart = OneWireUart::new();
+    clock.attach(uart.irq());
+
+    // Nobody answers the reset pulse on an empty bus, which takes a byte at 9600 baud
+    let mut driver = AsyncDriver::new(uart);
+    assert_eq!(clock.run_until(driver.bus.async_reset()).await, Ok(false));
+    assert_eq!(clock.now(), 12);
+    assert_eq!(clock.run_until(driver.find_devices()).await, Ok(vec![]));
+
+    let sensors = [
+        driver.bus.add_device(0x28, 0x0000_0000_0001),
+        driver.bus.add_device(0x28, 0x8000_0000_0001),
+        driver.bus.add_device(0x28, 0x0000_0000_0003),
+        driver.bus.add_device(0x10, 0x1234_5678_9abc),
+    ];
+    for rom in &sensors {
+        assert!(rom.is_valid());
+    }
+    assert_eq!(sensors[3].family_code(), 0x10);
+    assert_eq!(sensors[3].serial_number(), 0x1234_5678_9abc);
+
+    // The search finds every device once, in the order of the ROM codes read backwards
+    let found = clock.run_until(driver.find_devices()).await.unwrap();
+    assert_eq!(found.len(), sensors.len());
+    let mut expected = sensors.to_vec();
+    expected.sort_by_key(|rom| rom.as_raw().reverse_bits());
+    assert_eq!(found, expected);
+
+    // A single device can be read without a search
+    let clock = Clock::new();
+    let uart = OneWireUart::new();
+    clock.attach(uart.irq());
+    let only = uart.add_device(0x28, 0x42);
+    let mut driver = AsyncDriver::new(uart);
+    let rom = clock.run_until(async {
+        assert!(driver.bus.async_reset().await?);
+        driver.bus.async_write_byte(READ_ROM).await?;
+        let mut bytes = [0; 8];
+        for byte in bytes.iter_mut() {
+            *byte = driver.bus.async_read_byte().await?;
+        }
+        Ok::<_, UartError>(Rom::from_raw(u64::from_le_bytes(bytes)))
+    }).await.unwrap();
+    assert_eq!(rom, only);
+
+    // Sensors report the power-on value until they convert
+    let first = driver.bus.add_device(0x28, 0x43);
+    assert_eq!(clock.run_until(driver.read_temperature(only)).await, Ok(Some(85 * 16)));
+    driver.bus.set_temperature(only, 21 * 16 + 8);
+    driver.bus.set_temperature(first, -10 * 16);
+    clock.run_until(driver.convert_all()).await.unwrap();
+    assert_eq!(clock.run_until(driver.read_temperature(only)).await, Ok(Some(21 * 16 + 8)));
+    assert_eq!(clock.run_until(driver.read_temperature(first)).await, Ok(Some(-10 * 16)));
+
+    // Read slots without a device talking return ones
+    assert_eq!(clock.run_until(driver.bus.async_read_byte()).await, Ok(0xff));
+    assert_eq!(clock.run_until(driver.bus.async_read_bit()).await, Ok(true));
+    clock.run_until(driver.bus.async_write_bit(false)).await.unwrap();
+
+    // A device with a corrupted ROM code is reported, and the search goes on after it
+    let corrupted = Rom::from_raw(0xff00_0000_0000_0128);
+    driver.bus.add_device_with_rom(corrupted);
+    let mut search = RomSearch::new(&mut driver.bus);
+    let mut results = Vec::new();
+    loop {
+        match clock.run_until(search.async_next()).await {
+            Ok(Some(rom)) => results.push(Ok(rom)),
+            Ok(None) => break,
+            Err(e) => results.push(Err(e)),
+        }
+    }
+    assert_eq!(results, [Ok(only), Err(SearchError::Crc(corrupted)), Ok(first)]);
+
+    // Bus errors end the search
+    driver.bus.short_circuit(true);
+    let result = clock.run_until(driver.find_devices()).await;
+    assert_eq!(result, Err(SearchError::Bus(UartError::FramingError)));
+    driver.bus.short_circuit(false);
+    assert_eq!(clock.run_until(driver.read_temperature(first)).await, Ok(Some(-10 * 16)));
+
+    Ok(())
+}
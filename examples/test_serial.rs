@@ -1,7 +1,8 @@
 #![allow(dead_code)]
 
+use async_trait_poc::clock::Clock;
 use async_trait_poc::serial::*;
-use embedded_async_sandbox::serial::AsyncWrite;
+use embedded_async_sandbox::serial::{AsyncWrite, PartialError};
 
 struct AsyncDriver<UART> {
     uart: UART
@@ -15,21 +16,33 @@ impl<UART: AsyncWrite> AsyncDriver<UART> {
     }
 
     async fn send_hello(&mut self) -> Result<(), UART::Error> {
-        self.uart.async_write(b"Hello!").await?;
+        self.uart.async_write(b"Hello!").await.map_err(PartialError::into_inner)?;
         self.uart.async_flush().await
     }
+
+    async fn send_garbage(&mut self) -> Result<(), PartialError<UART::Error>> {
+        self.uart.async_write(b"ab\xffcdefgh").await
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
     let uart = Uart::new();
     let serial = Serial::new(uart);
+    clock.attach(serial.irq());
 
     //serial.write(b"Hello, world").await.unwrap();
     //serial.write(b"Hello, world\xff").await.unwrap();
 
     let mut driver = AsyncDriver::new(serial);
-    driver.send_hello().await.unwrap();
+    clock.run_until(driver.send_hello()).await.unwrap();
+
+    // The Uart rejects 0xff only once it is shifted out. By then the 4-byte TX FIFO
+    // has made room for "d" and "e", the bytes queued behind it count as accepted.
+    let e = clock.run_until(driver.send_garbage()).await.unwrap_err();
+    assert_eq!(e.error, UartError::InvalidData);
+    assert_eq!(e.offset, 6);
 
     Ok(())
 }
@@ -0,0 +1,120 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::serial::*;
+use embedded_async_sandbox::serial::{AsyncRead, AsyncWrite, BufReader, BufWriter, PartialError, ReadUntilError};
+use embedded_async_sandbox::timer::AsyncDelay;
+
+struct AsyncDriver<UART> {
+    uart: UART
+}
+
+impl<UART: AsyncWrite> AsyncDriver<UART> {
+    pub fn new(uart: UART) -> Self {
+        Self {
+            uart
+        }
+    }
+
+    async fn log(&mut self, message: &[u8]) -> Result<(), UART::Error> {
+        self.uart.async_write(message).await.map_err(PartialError::into_inner)?;
+        self.uart.async_write_byte(b'\n').await
+    }
+
+    async fn sync(&mut self) -> Result<(), UART::Error> {
+        self.uart.async_flush().await
+    }
+}
+
+struct GpsDriver<UART: AsyncRead, const N: usize> {
+    uart: BufReader<UART, N>
+}
+
+impl<UART: AsyncRead, const N: usize> GpsDriver<UART, N> {
+    pub fn new(uart: UART) -> Self {
+        Self {
+            uart: BufReader::new(uart)
+        }
+    }
+
+    async fn next_sentence(&mut self) -> Result<&[u8], ReadUntilError<UART::Error>> {
+        let line = self.uart.async_read_line().await?;
+        if line.ends_with(b"\r\n") {
+            Ok(&line[..line.len() - 2])
+        } else {
+            Ok(line)
+        }
+    }
+
+    /// Skips the rest of a broken sentence
+    async fn resync(&mut self) -> Result<(), UART::Error> {
+        loop {
+            let buf = self.uart.async_fill_buf().await?;
+            match buf.iter().position(|b| *b == b'$') {
+                Some(start) => {
+                    self.uart.consume(start);
+                    return Ok(());
+                },
+                None => {
+                    let len = buf.len();
+                    self.uart.consume(len);
+                }
+            }
+        }
+    }
+}
+
+async fn test_reader() {
+    let clock = Clock::new();
+    let mut uart = Uart::new();
+    uart.script_rx(b"$GPGGA,1\r\n$GPRMC,2\r\n".iter().map(|b| RxEvent::Byte(*b)));
+    uart.script_rx(b"$GPGSV,is too long\r\n$GPGLL,3\r\n".iter().map(|b| RxEvent::Byte(*b)));
+    let serial = Serial::new(uart);
+    clock.attach(serial.irq());
+
+    let mut driver = GpsDriver::<_, 16>::new(serial);
+    assert_eq!(clock.run_until(driver.next_sentence()).await, Ok(&b"$GPGGA,1"[..]));
+    assert_eq!(clock.run_until(driver.next_sentence()).await, Ok(&b"$GPRMC,2"[..]));
+    assert_eq!(clock.run_until(driver.next_sentence()).await, Err(ReadUntilError::Overflow));
+    clock.run_until(driver.resync()).await.unwrap();
+    assert_eq!(clock.run_until(driver.next_sentence()).await, Ok(&b"$GPGLL,3"[..]));
+}
+
+async fn test_writer() {
+    let clock = Clock::new();
+    let serial = Serial::new(Uart::new());
+    clock.attach(serial.irq());
+
+    let mut driver = AsyncDriver::new(BufWriter::<_, 16>::new(serial));
+
+    // Fits into the buffer, no time has to pass
+    clock.run_until(driver.log(b"boot")).await.unwrap();
+    clock.run_until(driver.log(b"temp=21")).await.unwrap();
+    assert_eq!(driver.uart.buffered(), 13);
+    assert_eq!(clock.now(), 0);
+
+    // Overflowing the buffer waits for the hardware to take some of it
+    clock.run_until(driver.log(b"this message does not fit")).await.unwrap();
+    assert!(clock.now() > 0);
+    assert!(driver.uart.buffered() > 0);
+
+    clock.run_until(driver.sync()).await.unwrap();
+    assert_eq!(driver.uart.buffered(), 0);
+
+    // A cancelled flush keeps only the bytes it did not get to, none are sent twice
+    clock.run_until(driver.log(b"cancelled")).await.unwrap();
+    let mut timer = clock.timer();
+    clock.run_until(futures::future::select(Box::pin(driver.sync()), timer.async_delay(8))).await;
+    let left = driver.uart.buffered();
+    assert!(left > 0 && left < 10);
+    clock.run_until(driver.sync()).await.unwrap();
+    assert_eq!(driver.uart.get_ref().transmitted(), b"boot\ntemp=21\nthis message does not fit\ncancelled\n");
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    test_writer().await;
+    test_reader().await;
+
+    Ok(())
+}
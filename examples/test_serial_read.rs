@@ -0,0 +1,70 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::serial::*;
+use embedded_async_sandbox::serial::{AsyncRead, PartialError};
+use embedded_async_sandbox::timer::AsyncDelay;
+
+struct AsyncDriver<UART> {
+    uart: UART
+}
+
+impl<UART: AsyncRead> AsyncDriver<UART> {
+    pub fn new(uart: UART) -> Self {
+        Self {
+            uart
+        }
+    }
+
+    async fn receive_hello(&mut self) -> Result<(), UART::Error> {
+        let mut buf = [0; 7];
+        self.uart.async_read(&mut buf).await.map_err(PartialError::into_inner)?;
+        assert_eq!(&buf, b"Hello!\n");
+        Ok(())
+    }
+
+    async fn receive_byte(&mut self) -> Result<u8, UART::Error> {
+        self.uart.async_read_byte().await
+    }
+
+    async fn receive_line<'a>(&mut self, buf: &'a mut [u8]) -> Result<&'a [u8], UART::Error> {
+        let mut len = 0;
+        while !buf[..len].ends_with(b"\n") {
+            let n = self.uart.async_read_some(&mut buf[len..]).await.map_err(PartialError::into_inner)?;
+            assert!(n > 0);
+            len += n;
+        }
+        Ok(&buf[..len])
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let mut uart = Uart::new();
+    uart.script_rx(b"Hello!\n".iter().map(|b| RxEvent::Byte(*b)));
+    uart.script_rx(vec![RxEvent::Idle(10), RxEvent::FramingError, RxEvent::Byte(b'x')]);
+    uart.script_rx(b"123456".iter().map(|b| RxEvent::Byte(*b)));
+    uart.script_rx(vec![RxEvent::Idle(1000), RxEvent::Byte(b'O'), RxEvent::Byte(b'K'), RxEvent::Idle(10)]);
+    uart.script_rx(vec![RxEvent::Byte(b'\r'), RxEvent::Byte(b'\n')]);
+    let serial = Serial::new(uart);
+    clock.attach(serial.irq());
+
+    let mut driver = AsyncDriver::new(serial);
+    clock.run_until(driver.receive_hello()).await.unwrap();
+    assert_eq!(clock.run_until(driver.receive_byte()).await, Err(UartError::FramingError));
+    assert_eq!(clock.run_until(driver.receive_byte()).await, Ok(b'x'));
+
+    // Stop reading while "123456" arrives (4 ticks per byte), the 4-byte RX FIFO overflows
+    let mut timer = clock.timer();
+    clock.run_until(timer.async_delay(40)).await;
+    assert_eq!(clock.run_until(driver.receive_byte()).await, Err(UartError::Overrun));
+    for b in b"1234" {
+        assert_eq!(clock.run_until(driver.receive_byte()).await, Ok(*b));
+    }
+
+    let mut buf = [0; 16];
+    assert_eq!(clock.run_until(driver.receive_line(&mut buf)).await, Ok(&b"OK\r\n"[..]));
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+#![allow(dead_code)]
+
+use async_trait_poc::irq;
+use async_trait_poc::serial::*;
+use embedded_async_sandbox::serial::split::{split, Storage};
+use embedded_async_sandbox::serial::{AsyncRead, AsyncWrite, PartialError};
+
+static STORAGE: Storage<Serial> = Storage::new();
+
+async fn receive_task<RX: AsyncRead>(rx: &mut RX) -> Result<(), RX::Error> {
+    let mut buf = [0; 5];
+    rx.async_read(&mut buf).await.map_err(PartialError::into_inner)?;
+    assert_eq!(&buf, b"ping\n");
+    Ok(())
+}
+
+async fn transmit_task<TX: AsyncWrite>(tx: &mut TX) -> Result<(), TX::Error> {
+    tx.async_write(b"pong\n").await.map_err(PartialError::into_inner)?;
+    tx.async_flush().await
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut uart = Uart::new();
+    uart.script_rx(vec![RxEvent::Idle(10)]);
+    uart.script_rx(b"ping\n".iter().map(|b| RxEvent::Byte(*b)));
+    let serial = Serial::new(uart);
+    irq::spawn(serial.irq());
+
+    let (mut rx, mut tx) = split(serial, &STORAGE);
+    let (received, transmitted) = tokio::join!(receive_task(&mut rx), transmit_task(&mut tx));
+    received.unwrap();
+    transmitted.unwrap();
+
+    let mut serial = rx.reunite(tx).unwrap();
+    serial.async_write(b"bye\n").await.unwrap();
+    serial.async_flush().await.unwrap();
+
+    // The storage is free again after the halves are reunited or dropped
+    let (rx, tx) = split(serial, &STORAGE);
+    drop(rx);
+    drop(tx);
+    let (rx, tx) = split(Serial::new(Uart::new()), &STORAGE);
+    rx.reunite(tx).unwrap();
+
+    Ok(())
+}
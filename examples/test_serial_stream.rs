@@ -0,0 +1,98 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::serial::*;
+use embedded_async_sandbox::serial::{ByteSink, ByteStream};
+use embedded_async_sandbox::timer::AsyncDelay;
+use futures::{pin_mut, stream, SinkExt, StreamExt};
+
+fn script(uart: &mut Uart, bytes: &[u8]) {
+    uart.script_rx(bytes.iter().map(|b| RxEvent::Byte(*b)));
+}
+
+async fn test_stream() {
+    let clock = Clock::new();
+    let mut uart = Uart::new();
+    script(&mut uart, b"ab");
+    uart.script_rx(vec![RxEvent::FramingError, RxEvent::Byte(b'c')]);
+    let mut serial = Serial::new(uart);
+    clock.attach(serial.irq());
+
+    // Errors are items of the stream, the bytes after them still arrive
+    let bytes = ByteStream::new(&mut serial);
+    pin_mut!(bytes);
+    let received: Vec<_> = clock.run_until(bytes.as_mut().take(4).collect()).await;
+    assert_eq!(received, [Ok(b'a'), Ok(b'b'), Err(UartError::FramingError), Ok(b'c')]);
+
+    // Nothing more arrives until the timeout
+    let mut timer = clock.timer();
+    let start = clock.now();
+    let rest: Vec<_> = clock.run_until(bytes.take_until(timer.async_delay(20)).collect()).await;
+    assert!(rest.is_empty());
+    assert!(clock.now() - start >= 20);
+}
+
+async fn test_sink() {
+    let clock = Clock::new();
+    let mut serial = Serial::new(Uart::new());
+    clock.attach(serial.irq());
+
+    let sink = ByteSink::new(&mut serial);
+    pin_mut!(sink);
+
+    // The first bytes fill the TX FIFO, the next one waits for space
+    for byte in b"hello" {
+        clock.run_until(sink.feed(*byte)).await.unwrap();
+    }
+    assert_eq!(clock.now(), 0);
+    clock.run_until(sink.feed(b' ')).await.unwrap();
+    assert!(clock.now() > 0);
+
+    clock.run_until(sink.send_all(&mut stream::iter(b"world").map(|b| Ok::<_, UartError>(*b)))).await.unwrap();
+    clock.run_until(sink.flush()).await.unwrap();
+
+    // The UART fails after sending 0xff, the error comes out of the flush and clears
+    assert_eq!(clock.run_until(sink.send(0xff)).await, Err(UartError::InvalidData));
+    clock.run_until(sink.send(b'!')).await.unwrap();
+    assert_eq!(serial.transmitted(), b"hello world\xff!");
+}
+
+async fn test_pipeline() {
+    let clock = Clock::new();
+    let mut uart = Uart::new();
+    script(&mut uart, b"echo");
+    uart.script_rx(vec![RxEvent::FramingError]);
+    script(&mut uart, b" me\nignored");
+    let mut rx = Serial::new(uart);
+    let mut other = Uart::new();
+    script(&mut other, b"12");
+    let mut numbers = Serial::new(other);
+    let mut tx = Serial::new(Uart::new());
+    clock.attach(rx.irq());
+    clock.attach(numbers.irq());
+    clock.attach(tx.irq());
+
+    // Upper-cased line without the broken bytes, forwarded to another port
+    let line = ByteStream::new(&mut rx)
+        .filter_map(|result| async move { result.ok() })
+        .take_while(|byte| futures::future::ready(*byte != b'\n'))
+        .map(|byte| Ok(byte.to_ascii_uppercase()));
+    clock.run_until(line.forward(ByteSink::new(&mut tx))).await.unwrap();
+    assert_eq!(tx.transmitted(), b"ECHO ME");
+
+    // Bytes of both ports interleaved as they arrive
+    let merged = stream::select(ByteStream::new(&mut rx), ByteStream::new(&mut numbers));
+    let received: Vec<_> = clock.run_until(merged.take(9).collect()).await;
+    let mut received: Vec<u8> = received.into_iter().map(Result::unwrap).collect();
+    received.sort();
+    assert_eq!(received, b"12deginor");
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    test_stream().await;
+    test_sink().await;
+    test_pipeline().await;
+
+    Ok(())
+}
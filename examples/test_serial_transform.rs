@@ -0,0 +1,64 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::serial::*;
+use embedded_async_sandbox::serial::{AsyncRead, AsyncWrite, CrlfWriter, MapBytes, NewlineReader, PartialError};
+
+struct AsyncDriver<UART> {
+    uart: UART
+}
+
+impl<UART: AsyncWrite + AsyncRead<Error=<UART as AsyncWrite>::Error>> AsyncDriver<UART> {
+    pub fn new(uart: UART) -> Self {
+        Self {
+            uart
+        }
+    }
+
+    async fn send_hello(&mut self) -> Result<(), <UART as AsyncWrite>::Error> {
+        self.uart.async_write(b"Hello!\n").await.map_err(PartialError::into_inner)?;
+        self.uart.async_write_byte(b'\n').await?;
+        self.uart.async_flush().await
+    }
+
+    async fn receive_lines(&mut self) -> Result<[u8; 8], <UART as AsyncWrite>::Error> {
+        let mut buf = [0; 8];
+        self.uart.async_read(&mut buf).await.map_err(PartialError::into_inner)?;
+        Ok(buf)
+    }
+}
+
+fn serial(clock: &Clock, rx: &[u8]) -> Serial {
+    let mut uart = Uart::new();
+    uart.script_rx(rx.iter().map(|b| RxEvent::Byte(*b)));
+    let serial = Serial::new(uart);
+    clock.attach(serial.irq());
+    serial
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut writer = AsyncDriver::new(CrlfWriter::new(serial(&clock, b"")));
+    clock.run_until(writer.send_hello()).await.unwrap();
+    assert_eq!(writer.uart.get_ref().transmitted(), b"Hello!\r\n\r\n");
+
+    let mut reader = AsyncDriver::new(NewlineReader::new(serial(&clock, b"AT\rOK\r\n\r\nX\n")));
+    let lines = clock.run_until(reader.receive_lines()).await.unwrap();
+    assert_eq!(&lines, b"AT\nOK\n\nX");
+
+    let mut shouter = AsyncDriver::new(MapBytes::new(serial(&clock, b"quiet..."), |b: u8| b.to_ascii_uppercase()));
+    let received = clock.run_until(shouter.receive_lines()).await.unwrap();
+    assert_eq!(&received, b"QUIET...");
+    clock.run_until(shouter.send_hello()).await.unwrap();
+    assert_eq!(shouter.uart.get_ref().transmitted(), b"HELLO!\n\n");
+
+    // Adapters stack, the bytes are upper-cased before line endings are translated
+    let shouting = MapBytes::new(CrlfWriter::new(serial(&clock, b"")), |b: u8| b.to_ascii_uppercase());
+    let mut stacked = AsyncDriver::new(shouting);
+    clock.run_until(stacked.send_hello()).await.unwrap();
+    assert_eq!(stacked.uart.get_ref().get_ref().transmitted(), b"HELLO!\r\n\r\n");
+
+    Ok(())
+}
@@ -2,8 +2,9 @@
 #![feature(type_alias_impl_trait)]
 #![allow(dead_code)]
 
+use async_trait_poc::irq;
 use async_trait_poc::serial::*;
-use embedded_async_sandbox::serial::AsyncWrite;
+use embedded_async_sandbox::serial::{AsyncWrite, PartialError};
 use std::future::Future;
 
 struct SerialWrapper<S>(S);
@@ -11,7 +12,7 @@ struct SerialWrapper<S>(S);
 impl<S: AsyncWrite> AsyncWrite for SerialWrapper<S> {
     type Error = S::Error;
     type WriteByteFuture<'t> = impl Future<Output=Result<(), S::Error>>;
-    type WriteFuture<'t> = impl Future<Output=Result<(), S::Error>>;
+    type WriteFuture<'t> = impl Future<Output=Result<(), PartialError<S::Error>>>;
     type FlushFuture<'t> = impl Future<Output=Result<(), S::Error>>;
 
     fn async_write_byte(&mut self, byte: u8) -> Self::WriteByteFuture<'_> {
@@ -26,8 +27,8 @@ impl<S: AsyncWrite> AsyncWrite for SerialWrapper<S> {
 
     fn async_write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
         async move {
-            for b in data {
-                self.async_write_byte(*b).await?;
+            for (offset, b) in data.iter().enumerate() {
+                self.async_write_byte(*b).await.map_err(|error| PartialError { error, offset })?;
             }
             Ok(())
         }
@@ -50,7 +51,7 @@ impl<UART: AsyncWrite> AsyncDriver<UART> {
     }
 
     async fn send_hello(&mut self) -> Result<(), UART::Error> {
-        self.uart.async_write(b"Hello!\n").await?;
+        self.uart.async_write(b"Hello!\n").await.map_err(PartialError::into_inner)?;
         self.uart.async_flush().await
     }
 }
@@ -59,6 +60,7 @@ impl<UART: AsyncWrite> AsyncDriver<UART> {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let uart = Uart::new();
     let serial = Serial::new(uart);
+    irq::spawn(serial.irq());
 
     //serial.write(b"Hello, world").await.unwrap();
     //serial.write(b"Hello, world\xff").await.unwrap();
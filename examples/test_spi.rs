@@ -1,7 +1,12 @@
 #![allow(dead_code)]
 
+use async_trait_poc::clock::Clock;
+use async_trait_poc::irq::{self, Tick};
 use async_trait_poc::spi::*;
-use embedded_async_sandbox::spi::AsyncTransfer;
+use embedded_async_sandbox::spi::{AsyncRead, AsyncTransfer, AsyncWrite};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 
 struct AsyncDriver<SPI> {
     spi: SPI
@@ -28,14 +33,98 @@ impl<SPI: AsyncTransfer> AsyncDriver<SPI> {
 
         Ok(())
     }
+
+    async fn read_id(&mut self) -> Result<[u8; 3], SPI::Error> {
+        let mut response = [0; 4];
+        self.spi.async_transfer_split(&mut response, &[0x9f]).await?;
+        Ok([response[1], response[2], response[3]])
+    }
+
+    async fn send_command(&mut self, command: &[u8]) -> Result<u8, SPI::Error> {
+        let mut status = [0; 1];
+        self.spi.async_transfer_split(&mut status, command).await?;
+        Ok(status[0])
+    }
+}
+
+impl<SPI: AsyncWrite + AsyncRead<Error=<SPI as AsyncWrite>::Error>> AsyncDriver<SPI> {
+    async fn draw_frame(&mut self) -> Result<(), <SPI as AsyncWrite>::Error> {
+        let framebuffer = [0x55; 64];
+        self.spi.async_write(&framebuffer).await
+    }
+
+    async fn read_sample(&mut self) -> Result<[u8; 6], <SPI as AsyncWrite>::Error> {
+        let mut buf = [0; 6];
+        self.spi.async_read(&mut buf).await?;
+        Ok(buf)
+    }
+}
+
+/// Polls `future` only every `latency` ticks, like a task that shares the CPU with others
+struct Sluggish<'a, F> {
+    clock: &'a Clock,
+    future: Pin<Box<F>>,
+    latency: usize,
+}
+
+impl<'a, F: Future> Future for Sluggish<'a, F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(output) => Poll::Ready(output),
+            Poll::Pending => {
+                for _ in 0..self.latency {
+                    self.clock.tick();
+                }
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
+
+async fn loopback_ticks(depth: usize, latency: usize) -> u64 {
+    let clock = Clock::new();
+    let mut spi = DummySpi::new();
+    spi.set_transfer_depth(depth);
+    clock.attach(spi.irq());
+
+    let mut driver = AsyncDriver::new(spi);
+    Sluggish {
+        clock: &clock,
+        future: Box::pin(driver.check_loopback()),
+        latency,
+    }.await.unwrap();
+    clock.now()
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let spi = DummySpi::new();
+    irq::spawn(spi.irq());
 
     let mut driver = AsyncDriver::new(spi);
     driver.check_loopback().await.unwrap();
 
+    // DummySpi returns every byte inverted, including the fill byte
+    assert_eq!(driver.read_id().await.unwrap(), [0xff; 3]);
+    assert_eq!(driver.send_command(&[0x02, 0x10, 0x20, 0x30]).await.unwrap(), !0x02);
+
+    // Writing far more than the RX FIFO holds must not overflow it
+    driver.draw_frame().await.unwrap();
+    // DummySpi returns the inverted fill byte
+    assert_eq!(driver.read_sample().await.unwrap(), [0xff; 6]);
+    driver.check_loopback().await.unwrap();
+
+    // A task polled as soon as a byte arrives keeps the bus busy either way
+    assert_eq!(loopback_ticks(1, 1).await, loopback_ticks(4, 1).await);
+
+    // A task that reacts late leaves the bus idle, unless the FIFO holds the next bytes
+    let strict = loopback_ticks(1, 6).await;
+    let pipelined = loopback_ticks(4, 6).await;
+    println!("loopback: {} ticks strict, {} ticks pipelined", strict, pipelined);
+    assert!(pipelined < strict);
+
     Ok(())
 }
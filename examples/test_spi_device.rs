@@ -0,0 +1,118 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::gpio::Pin;
+use async_trait_poc::spi::*;
+use embedded_async_sandbox::mutex::Mutex;
+use embedded_async_sandbox::spi::{AsyncSpiDevice, AsyncTransfer, DeviceError};
+use embedded_async_sandbox::timer::AsyncDelay;
+use embedded_hal::digital::v2::OutputPin;
+
+struct AsyncDriver<'a, SPI, CS, TIMER> {
+    device: AsyncSpiDevice<'a, SPI, CS, TIMER>,
+    timer: TIMER,
+}
+
+impl<'a, SPI: AsyncTransfer, CS: OutputPin, TIMER: AsyncDelay> AsyncDriver<'a, SPI, CS, TIMER> {
+    pub fn new(device: AsyncSpiDevice<'a, SPI, CS, TIMER>, timer: TIMER) -> Self {
+        Self {
+            device,
+            timer,
+        }
+    }
+
+    /// Sends a command byte followed by transfers of `chunks` bytes, all filled with `command`
+    async fn run_command(&mut self, command: u8, chunks: &[usize]) -> Result<(), DeviceError<SPI::Error, CS::Error>> {
+        let mut bus = self.device.async_select().await.map_err(DeviceError::ChipSelect)?;
+        bus.async_transfer_split(&mut [], &[command]).await.map_err(DeviceError::Bus)?;
+        for len in chunks {
+            let mut buf = [command; 8];
+            bus.async_transfer(&mut buf[..*len]).await.map_err(DeviceError::Bus)?;
+            assert!(buf[..*len].iter().all(|b| *b == !command));
+        }
+        bus.deselect().map_err(DeviceError::ChipSelect)
+    }
+
+    async fn run(&mut self, first_command: u8, chunks: &[usize], count: u8) -> Result<(), DeviceError<SPI::Error, CS::Error>> {
+        for i in 0..count {
+            self.run_command(first_command + i, chunks).await?;
+            // Give the other device a chance to grab the bus
+            self.timer.async_delay(10).await;
+        }
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut spi = DummySpi::new();
+    spi.set_transfer_depth(4);
+    clock.attach(spi.irq());
+
+    let cs_flash = Pin::new();
+    let cs_sensor = Pin::new();
+    let flash_index = spi.attach_cs(&cs_flash);
+    let sensor_index = spi.attach_cs(&cs_sensor);
+
+    let bus = Mutex::new(spi);
+    let mut flash = AsyncDriver::new(AsyncSpiDevice::new(&bus, cs_flash.clone(), clock.timer()), clock.timer());
+    let mut sensor = AsyncDriver::new(AsyncSpiDevice::new(&bus, cs_sensor.clone(), clock.timer()), clock.timer());
+
+    let (flashed, sensed) = clock.run_until(async {
+        tokio::join!(flash.run(0xa0, &[4], 3), sensor.run(0xb0, &[2, 3, 1], 3))
+    }).await;
+    flashed.unwrap();
+    sensed.unwrap();
+
+    // Every transaction selected its chip exactly once
+    assert_eq!(cs_flash.falling_edges(), 3);
+    assert_eq!(cs_sensor.falling_edges(), 3);
+    assert!(cs_flash.is_high() && cs_sensor.is_high());
+
+    // Every transaction shows up as one uninterrupted run of bytes with only its chip selected
+    let shifted = bus.into_inner().shifted();
+    let mut runs: Vec<(Vec<usize>, u8, usize)> = Vec::new();
+    for s in shifted {
+        match runs.last_mut() {
+            Some((selected, byte, len)) if *selected == s.selected && *byte == s.byte => *len += 1,
+            _ => runs.push((s.selected, s.byte, 1)),
+        }
+    }
+    assert_eq!(runs.len(), 6);
+    for (selected, byte, len) in runs {
+        match byte & 0xf0 {
+            0xa0 => assert_eq!((selected, len), (vec![flash_index], 1 + 4)),
+            0xb0 => assert_eq!((selected, len), (vec![sensor_index], 1 + 2 + 3 + 1)),
+            _ => panic!("unexpected byte {:02x}", byte),
+        }
+    }
+
+    // Any number of devices can wait for the bus, including one that gives up waiting
+    let spi = DummySpi::new();
+    clock.attach(spi.irq());
+    let pins: Vec<_> = (0..6).map(|_| Pin::new()).collect();
+    for pin in &pins {
+        spi.attach_cs(pin);
+    }
+    let bus = Mutex::new(spi);
+    let mut drivers: Vec<_> = pins.iter()
+        .map(|pin| AsyncDriver::new(AsyncSpiDevice::new(&bus, pin.clone(), clock.timer()), clock.timer()))
+        .collect();
+    let (impatient, patient) = drivers.split_last_mut().unwrap();
+    let mut timer = clock.timer();
+    let (results, _) = clock.run_until(async {
+        let runs = patient.iter_mut().enumerate().map(|(i, driver)| driver.run(0xc0 + i as u8, &[2], 1));
+        let impatient = futures::future::select(Box::pin(impatient.run(0xd0, &[2], 1)), timer.async_delay(5));
+        tokio::join!(futures::future::join_all(runs), impatient)
+    }).await;
+    assert!(results.into_iter().all(|result| result.is_ok()));
+    for pin in &pins[..5] {
+        assert_eq!(pin.falling_edges(), 1);
+    }
+    assert_eq!(pins[5].falling_edges(), 0);
+    assert!(pins.iter().all(Pin::is_high));
+
+    Ok(())
+}
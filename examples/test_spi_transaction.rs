@@ -0,0 +1,84 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::gpio::Pin;
+use async_trait_poc::spi::*;
+use embedded_async_sandbox::mutex::Mutex;
+use embedded_async_sandbox::spi::{AsyncSpiDevice, AsyncTransaction, Operation};
+
+struct AsyncDriver<DEV> {
+    device: DEV
+}
+
+impl<DEV: AsyncTransaction> AsyncDriver<DEV> {
+    pub fn new(device: DEV) -> Self {
+        Self {
+            device
+        }
+    }
+
+    async fn read_registers(&mut self, address: u8, buf: &mut [u8]) -> Result<(), DEV::Error> {
+        let command = [address | 0x80];
+        let mut operations = [
+            Operation::Write(&command),
+            Operation::Delay(20),
+            Operation::Read(buf),
+        ];
+        self.device.async_transaction(&mut operations).await
+    }
+
+    async fn program_page(&mut self, address: u8, data: &mut [u8]) -> Result<u8, DEV::Error> {
+        let command = [0x02, address];
+        let mut status = [0; 1];
+        let mut operations = [
+            Operation::Write(&command),
+            Operation::TransferInPlace(data),
+            Operation::Transfer(&mut status, &[0x05, 0x00]),
+        ];
+        self.device.async_transaction(&mut operations).await?;
+        Ok(status[0])
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let spi = DummySpi::new();
+    clock.attach(spi.irq());
+
+    let cs_sensor = Pin::new();
+    let cs_flash = Pin::new();
+    let sensor_index = spi.attach_cs(&cs_sensor);
+    let flash_index = spi.attach_cs(&cs_flash);
+
+    let bus = Mutex::new(spi);
+    let mut sensor = AsyncDriver::new(AsyncSpiDevice::new(&bus, cs_sensor.clone(), clock.timer()));
+    let mut flash = AsyncDriver::new(AsyncSpiDevice::new(&bus, cs_flash.clone(), clock.timer()));
+
+    let mut registers = [0; 3];
+    let mut page = [1, 2, 3, 4];
+    let (read, programmed) = clock.run_until(async {
+        tokio::join!(sensor.read_registers(0x10, &mut registers), flash.program_page(0x20, &mut page))
+    }).await;
+    read.unwrap();
+    // DummySpi returns every byte inverted, including the fill byte
+    assert_eq!(registers, [0xff; 3]);
+    assert_eq!(programmed.unwrap(), !0x05);
+    assert_eq!(page, [!1, !2, !3, !4]);
+
+    // The delay kept the sensor selected, the flash waited for the bus
+    assert!(clock.now() >= 20);
+    assert_eq!(cs_sensor.falling_edges(), 1);
+    assert_eq!(cs_flash.falling_edges(), 1);
+
+    let shifted = bus.try_lock().unwrap().shifted();
+    let selected: Vec<_> = shifted.iter().map(|s| s.selected.clone()).collect();
+    let bytes: Vec<_> = shifted.iter().map(|s| s.byte).collect();
+    let mut expected_selected = vec![vec![sensor_index]; 4];
+    expected_selected.extend(vec![vec![flash_index]; 8]);
+    assert_eq!(selected, expected_selected);
+    assert_eq!(bytes, [0x90, 0x00, 0x00, 0x00, 0x02, 0x20, 1, 2, 3, 4, 0x05, 0x00]);
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::flash::SpiFlash;
+use async_trait_poc::gpio::Pin;
+use embedded_async_sandbox::storage::*;
+
+struct AsyncDriver<S> {
+    storage: S,
+}
+
+impl<S: AsyncWriteStorage> AsyncDriver<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage
+        }
+    }
+
+    /// Replaces the bytes at `offset` with `data`, keeping the rest of the sector
+    async fn update(&mut self, offset: u32, data: &[u8]) -> Result<(), S::Error> {
+        let sector_size = self.storage.erase_size() as u32;
+        let start = offset - offset % sector_size;
+        let mut sector = vec![0; sector_size as usize];
+        self.storage.async_read(start, &mut sector).await?;
+
+        let position = (offset - start) as usize;
+        sector[position..position + data.len()].copy_from_slice(data);
+        self.storage.async_erase(start..start + sector_size).await?;
+        self.storage.async_write(start, &sector).await
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let cs = Pin::new();
+    let chip = SpiFlash::new(&cs, 64 * 1024);
+    clock.attach(chip.irq());
+
+    // Nothing drives MISO when the chip is not selected, so the ID reads back as all ones
+    let mut flash = SpiNorFlash::new(chip, Pin::new(), 0);
+    let id = clock.run_until(flash.async_read_jedec_id()).await.unwrap();
+    assert_eq!((id.manufacturer(), id.memory_type(), id.capacity()), (0xff, 0xff, None));
+    let (chip, _) = flash.release();
+
+    // The capacity is only known once the chip has been identified
+    let mut flash = SpiNorFlash::new(chip, cs.clone(), 0);
+    let id = clock.run_until(flash.async_read_jedec_id()).await.unwrap();
+    assert_eq!((id.manufacturer(), id.memory_type()), (0xef, 0x40));
+    assert_eq!(id.capacity(), Some(64 * 1024));
+    // Larger chips are only used up to the 16 MiB reachable with 24-bit addresses
+    flash.set_capacity(32 * 1024 * 1024);
+    assert_eq!(flash.capacity(), 16 * 1024 * 1024);
+    flash.set_capacity(id.capacity().unwrap());
+    assert_eq!(flash.capacity(), 64 * 1024);
+    assert_eq!((flash.write_size(), flash.erase_size()), (1, 4096));
+    assert!(cs.is_high());
+    assert_eq!(cs.falling_edges(), 1);
+
+    // The chip starts out erased
+    let mut buf = [0; 16];
+    clock.run_until(flash.async_read(0xfff8, &mut buf[..8])).await.unwrap();
+    assert_eq!(buf[..8], [0xff; 8]);
+
+    // Writes are split at page boundaries, every page waits for the chip to finish
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let start = clock.now();
+    clock.run_until(flash.async_write(200, &data)).await.unwrap();
+    assert!(clock.now() - start >= 3 * 20);
+    let mut read = vec![0; 304];
+    clock.run_until(flash.async_read(198, &mut read)).await.unwrap();
+    assert_eq!(read[..2], [0xff; 2]);
+    assert_eq!(read[2..302], data[..]);
+    assert_eq!(read[302..], [0xff; 2]);
+
+    // Ranges are checked before anything is sent to the chip
+    let edges = cs.falling_edges();
+    let start = clock.now();
+    assert!(matches!(clock.run_until(flash.async_erase(100..4096)).await, Err(NorFlashError::NotAligned)));
+    assert!(matches!(clock.run_until(flash.async_erase(0..100)).await, Err(NorFlashError::NotAligned)));
+    assert!(matches!(clock.run_until(flash.async_erase(0x11000..0x12000)).await, Err(NorFlashError::OutOfBounds)));
+    assert!(matches!(clock.run_until(flash.async_erase(0..0x11000)).await, Err(NorFlashError::OutOfBounds)));
+    assert!(matches!(clock.run_until(flash.async_write(0xfffe, &[0; 4])).await, Err(NorFlashError::OutOfBounds)));
+    assert!(matches!(clock.run_until(flash.async_read(0x10000, &mut buf[..1])).await, Err(NorFlashError::OutOfBounds)));
+    assert!(clock.run_until(flash.async_read(0x10000, &mut [])).await.is_ok());
+    assert_eq!(clock.now(), start);
+    assert_eq!(cs.falling_edges(), edges);
+
+    // Writing without erasing only clears bits
+    clock.run_until(flash.async_write(0x1000, &[0xf0, 0x3c])).await.unwrap();
+    clock.run_until(flash.async_write(0x1000, &[0x0f, 0xff])).await.unwrap();
+    clock.run_until(flash.async_read(0x1000, &mut buf[..2])).await.unwrap();
+    assert_eq!(buf[..2], [0x00, 0x3c]);
+    let (chip, cs) = flash.release();
+    assert_eq!(chip.overwrites(), 2);
+
+    // Erasing takes a while and only affects whole sectors
+    let mut flash = SpiNorFlash::new(chip, cs, 64 * 1024);
+    let start = clock.now();
+    clock.run_until(flash.async_erase(0x1000..0x2000)).await.unwrap();
+    assert!(clock.now() - start >= 200);
+    clock.run_until(flash.async_read(0x1000, &mut buf[..2])).await.unwrap();
+    assert_eq!(buf[..2], [0xff; 2]);
+    clock.run_until(flash.async_read(200, &mut buf[..4])).await.unwrap();
+    assert_eq!(buf[..4], [0, 1, 2, 3]);
+
+    // Drivers generic over the storage traits erase before rewriting
+    let mut driver = AsyncDriver::new(flash);
+    clock.run_until(driver.update(202, b"hello")).await.unwrap();
+    clock.run_until(driver.storage.async_read(200, &mut buf[..8])).await.unwrap();
+    assert_eq!(&buf[..8], b"\x00\x01hello\x07");
+    let (chip, _) = driver.storage.release();
+    assert_eq!(chip.overwrites(), 2);
+    assert_eq!(chip.memory()[498..501], [42, 43, 0xff]);
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::serial::*;
+use async_trait_poc::spi::*;
+use embedded_async_sandbox::serial::{AsyncRead, PartialError};
+use embedded_async_sandbox::spi::AsyncTransfer;
+use embedded_async_sandbox::timer::{with_timeout, AsyncDelay, TimeoutError};
+
+struct AsyncDriver<UART, SPI, TIMER> {
+    uart: UART,
+    spi: SPI,
+    timer: TIMER,
+}
+
+impl<UART: AsyncRead, SPI: AsyncTransfer, TIMER: AsyncDelay> AsyncDriver<UART, SPI, TIMER> {
+    pub fn new(uart: UART, spi: SPI, timer: TIMER) -> Self {
+        Self {
+            uart,
+            spi,
+            timer,
+        }
+    }
+
+    async fn receive(&mut self, buf: &mut [u8], ticks: u32) -> Result<(), TimeoutError<PartialError<UART::Error>>> {
+        with_timeout(self.uart.async_read(buf), self.timer.async_delay(ticks)).await
+    }
+
+    async fn exchange(&mut self, buf: &mut [u8], ticks: u32) -> Result<(), TimeoutError<SPI::Error>> {
+        with_timeout(self.spi.async_transfer(buf), self.timer.async_delay(ticks)).await
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+
+    let mut uart = Uart::new();
+    uart.script_rx(b"Hi".iter().map(|b| RxEvent::Byte(*b)));
+    let serial = Serial::new(uart);
+    clock.attach(serial.irq());
+
+    let spi = DummySpi::new();
+    clock.attach(spi.irq());
+
+    let mut driver = AsyncDriver::new(serial, spi, clock.timer());
+
+    // Each byte takes 4 ticks on the line
+    let mut buf = [0; 2];
+    clock.run_until(driver.receive(&mut buf, 100)).await.unwrap();
+    assert_eq!(&buf, b"Hi");
+    assert_eq!(clock.now(), 8);
+
+    // Nothing else is coming, the read gives up once the delay elapses
+    let mut buf = [0; 1];
+    let result = clock.run_until(driver.receive(&mut buf, 50)).await;
+    assert_eq!(result, Err(TimeoutError::Timeout));
+    assert_eq!(clock.now(), 58);
+
+    let mut buf = [1, 2, 3, 4];
+    clock.run_until(driver.exchange(&mut buf, 100)).await.unwrap();
+    assert_eq!(buf, [!1, !2, !3, !4]);
+
+    Ok(())
+}
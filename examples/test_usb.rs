@@ -0,0 +1,110 @@
+#![allow(dead_code)]
+
+use async_trait_poc::clock::Clock;
+use async_trait_poc::usb::*;
+use embedded_async_sandbox::serial::{AsyncRead, AsyncWrite, PartialError};
+use embedded_async_sandbox::usb::{AsyncEndpointIn, SerialPort};
+
+struct AsyncDriver<UART> {
+    uart: UART
+}
+
+impl<UART: AsyncWrite + AsyncRead<Error=<UART as AsyncWrite>::Error>> AsyncDriver<UART> {
+    pub fn new(uart: UART) -> Self {
+        Self {
+            uart
+        }
+    }
+
+    async fn send_hello(&mut self) -> Result<(), <UART as AsyncWrite>::Error> {
+        self.uart.async_write(b"Hello!\n").await.map_err(PartialError::into_inner)?;
+        self.uart.async_write_byte(b'\n').await?;
+        self.uart.async_flush().await
+    }
+
+    async fn receive_lines(&mut self) -> Result<[u8; 8], <UART as AsyncWrite>::Error> {
+        let mut buf = [0; 8];
+        self.uart.async_read(&mut buf).await.map_err(PartialError::into_inner)?;
+        Ok(buf)
+    }
+}
+
+fn packets(host: &UsbLoopback) -> Vec<usize> {
+    host.received().iter().map(|packet| packet.len()).collect()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clock = Clock::new();
+    let host = UsbLoopback::new(8);
+    clock.attach(host.irq());
+
+    // Drivers written against the serial traits run unchanged, a full last packet
+    // is followed by a zero-length packet that the reading side skips
+    let mut driver = AsyncDriver::new(SerialPort::<_, _, 8>::new(host.endpoint_in(), host.endpoint_out()));
+    clock.run_until(driver.send_hello()).await.unwrap();
+    assert_eq!(host.received(), [b"Hello!\n\n".to_vec(), Vec::new()]);
+    let lines = clock.run_until(driver.receive_lines()).await.unwrap();
+    assert_eq!(&lines, b"Hello!\n\n");
+
+    // Full packets are queued right away, the rest only when flushing
+    let mut port = driver.uart;
+    let message = b"USB serial console!";
+    clock.run_until(port.async_write(message)).await.unwrap();
+    assert_eq!(port.buffered(), 3);
+    clock.run_until(port.async_flush()).await.unwrap();
+    assert_eq!(packets(&host), [8, 0, 8, 8, 3]);
+
+    // Reads span packets, partial reads return what one packet holds
+    let mut buf = [0; 10];
+    clock.run_until(port.async_read(&mut buf)).await.unwrap();
+    assert_eq!(&buf, &message[..10]);
+    let count = clock.run_until(port.async_read_some(&mut buf)).await.unwrap();
+    assert_eq!(&buf[..count], &message[10..16]);
+    let count = clock.run_until(port.async_read_some(&mut buf)).await.unwrap();
+    assert_eq!(&buf[..count], &message[16..]);
+    assert_eq!(clock.run_until(port.async_read_some(&mut [])).await, Ok(0));
+
+    host.send_packet(b"");
+    host.send_packet(b"x");
+    assert_eq!(clock.run_until(port.async_read_byte()).await, Ok(b'x'));
+
+    // Flushing waits until the host has read the last packet
+    let start = clock.now();
+    clock.run_until(port.async_write(b"ping")).await.unwrap();
+    assert_eq!(clock.now(), start);
+    clock.run_until(port.async_flush()).await.unwrap();
+    assert!(clock.now() > start);
+    assert_eq!(host.received().last().unwrap(), b"ping");
+
+    // Endpoints reject oversized packets
+    let (mut ep_in, ep_out) = port.release();
+    let result = clock.run_until(ep_in.async_write_packet(&[0; 9])).await;
+    assert_eq!(result, Err(UsbError::BufferOverflow));
+
+    // Written bytes are only buffered, so errors show up when flushing
+    let mut port = SerialPort::<_, _, 64>::new(ep_in, ep_out);
+    host.disconnect();
+    clock.run_until(port.async_write(b"lost")).await.unwrap();
+    assert_eq!(clock.run_until(port.async_flush()).await, Err(UsbError::Disconnected));
+    assert_eq!(port.buffered(), 0);
+    let result = clock.run_until(port.async_read(&mut buf)).await;
+    assert_eq!(result, Err(PartialError { error: UsbError::Disconnected, offset: 0 }));
+    // The bytes of the failed packet do not count as written
+    let result = clock.run_until(port.async_write(&[0; 10])).await;
+    assert_eq!(result, Err(PartialError { error: UsbError::Disconnected, offset: 0 }));
+
+    // Bytes of a packet read before an error are not handed out again
+    let clock = Clock::new();
+    let host = UsbLoopback::new(8);
+    clock.attach(host.irq());
+    let mut port = SerialPort::<_, _, 8>::new(host.endpoint_in(), host.endpoint_out());
+    host.send_packet(b"abcd");
+    clock.run_until(port.async_read(&mut buf[..4])).await.unwrap();
+    assert_eq!(&buf[..4], b"abcd");
+    host.disconnect();
+    assert_eq!(clock.run_until(port.async_read_byte()).await, Err(UsbError::Disconnected));
+    assert_eq!(clock.run_until(port.async_read_byte()).await, Err(UsbError::Disconnected));
+
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::adc::Continuous;
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use embedded_hal::adc::{Channel, OneShot};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+/// Largest value of a 12-bit conversion
+const MAX_VALUE: u16 = 0xfff;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AdcError {
+    /// A sample was lost because the previous one was not read in time
+    Overrun,
+}
+
+/// Signal applied to an input of the simulated ADC, as a function of the tick count
+#[derive(Copy, Clone, Debug)]
+pub enum Waveform {
+    Constant(u16),
+    /// Rises by `step` every tick, wrapping around at the top of the range
+    Ramp { start: u16, step: u16 },
+    /// Spends the first half of every period high, the second half low
+    Square { low: u16, high: u16, period: u64 },
+    Sine { offset: u16, amplitude: u16, period: u64 },
+}
+
+impl Waveform {
+    fn value(&self, tick: u64) -> u16 {
+        match *self {
+            Waveform::Constant(value) => value.min(MAX_VALUE),
+            Waveform::Ramp { start, step } => {
+                ((start as u64 + step as u64 * tick) % (MAX_VALUE as u64 + 1)) as u16
+            },
+            Waveform::Square { low, high, period } => {
+                if tick % period < period / 2 { high } else { low }
+            },
+            Waveform::Sine { offset, amplitude, period } => {
+                let phase = 2.0 * std::f64::consts::PI * (tick % period) as f64 / period as f64;
+                let value = offset as f64 + amplitude as f64 * phase.sin();
+                value.round().max(0.0).min(MAX_VALUE as f64) as u16
+            },
+        }
+    }
+}
+
+/// Analog input 0
+pub struct Ain0;
+/// Analog input 1
+pub struct Ain1;
+
+impl Channel<Adc> for Ain0 {
+    type ID = u8;
+
+    fn channel() -> u8 {
+        0
+    }
+}
+
+impl Channel<Adc> for Ain1 {
+    type ID = u8;
+
+    fn channel() -> u8 {
+        1
+    }
+}
+
+/// Simulated 12-bit ADC with two inputs
+///
+/// A one-shot conversion takes 2 ticks and samples its input when it completes.
+/// In continuous mode a sample of the selected input is taken every sample period.
+pub struct Adc {
+    inputs: [Waveform; 2],
+    ticks: u64,
+    conversion: Option<(u8, usize)>,
+    result: Option<(u8, u16)>,
+    continuous_channel: u8,
+    sample_period: usize,
+    running: bool,
+    ticks_to_sample: usize,
+    sample: Option<u16>,
+    overrun: bool,
+}
+
+impl Adc {
+    pub fn new() -> Self {
+        Self {
+            inputs: [Waveform::Constant(0); 2],
+            ticks: 0,
+            conversion: None,
+            result: None,
+            continuous_channel: 0,
+            sample_period: 1,
+            running: false,
+            ticks_to_sample: 0,
+            sample: None,
+            overrun: false,
+        }
+    }
+
+    /// Applies `waveform` to the input `channel`
+    pub fn set_input(&mut self, channel: u8, waveform: Waveform) {
+        self.inputs[channel as usize] = waveform;
+    }
+
+    /// Selects the input sampled every `period` ticks in continuous mode
+    pub fn set_continuous(&mut self, channel: u8, period: usize) {
+        assert!(period > 0);
+        self.continuous_channel = channel;
+        self.sample_period = period;
+    }
+
+    fn has_data(&self) -> bool {
+        self.result.is_some() || self.sample.is_some() || self.overrun
+    }
+
+    fn make_progress(&mut self) {
+        self.ticks += 1;
+
+        if let Some((channel, ticks_left)) = self.conversion {
+            if ticks_left > 1 {
+                self.conversion = Some((channel, ticks_left - 1));
+            } else {
+                self.conversion = None;
+                let value = self.inputs[channel as usize].value(self.ticks);
+                println!("conversion {}: {}", channel, value);
+                self.result = Some((channel, value));
+            }
+        }
+
+        if self.running {
+            if self.ticks_to_sample > 1 {
+                self.ticks_to_sample -= 1;
+            } else {
+                self.ticks_to_sample = self.sample_period;
+                let value = self.inputs[self.continuous_channel as usize].value(self.ticks);
+                println!("sample {}: {}", self.continuous_channel, value);
+                if self.sample.is_some() {
+                    self.overrun = true;
+                }
+                self.sample = Some(value);
+            }
+        }
+    }
+}
+
+struct Shared {
+    adc: Mutex<Adc>,
+    rx_not_empty: AtomicWaker,
+}
+
+impl Shared {
+    fn pending(&self) -> bool {
+        self.adc.lock().unwrap().has_data()
+    }
+}
+
+pub struct AdcDriver {
+    shared: Arc<Shared>,
+}
+
+impl AdcDriver {
+    pub fn new(adc: Adc) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                adc: Mutex::new(adc),
+                rx_not_empty: AtomicWaker::new(),
+            })
+        }
+    }
+
+    /// Returns the clock and interrupt input of the underlying `Adc`
+    pub fn irq(&self) -> AdcIrq {
+        AdcIrq {
+            shared: self.shared.clone()
+        }
+    }
+
+    fn adc(&self) -> MutexGuard<'_, Adc> {
+        self.shared.adc.lock().unwrap()
+    }
+}
+
+impl<PIN: Channel<Adc, ID=u8>> OneShot<Adc, u16, PIN> for AdcDriver {
+    type Error = AdcError;
+
+    fn read(&mut self, _pin: &mut PIN) -> nb::Result<u16, Self::Error> {
+        let channel = PIN::channel();
+        let mut adc = self.adc();
+
+        match adc.result.take() {
+            Some((converted, value)) if converted == channel => {
+                println!("read({}): Ok({})", channel, value);
+                Ok(value)
+            },
+            stale => {
+                if let Some((converted, _)) = stale {
+                    // left behind by a read of another channel that was cancelled
+                    println!("read({}): discarding result of {}", channel, converted);
+                }
+                if adc.conversion.is_none() {
+                    // start converting
+                    adc.conversion = Some((channel, 2));
+                }
+                println!("read({}): WouldBlock", channel);
+                Err(nb::Error::WouldBlock)
+            }
+        }
+    }
+}
+
+impl Continuous<u16> for AdcDriver {
+    type Error = AdcError;
+
+    fn start(&mut self) {
+        let mut adc = self.adc();
+        adc.running = true;
+        adc.ticks_to_sample = adc.sample_period;
+    }
+
+    fn read(&mut self) -> nb::Result<u16, Self::Error> {
+        let mut adc = self.adc();
+
+        if adc.overrun {
+            adc.overrun = false;
+            return Err(nb::Error::Other(AdcError::Overrun));
+        }
+        match adc.sample.take() {
+            Some(value) => Ok(value),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn stop(&mut self) {
+        let mut adc = self.adc();
+        adc.running = false;
+        adc.sample = None;
+        adc.overrun = false;
+    }
+}
+
+impl RegisterWaker for AdcDriver {
+    fn register_waker(&self, _event: Event, waker: &Waker) {
+        self.shared.rx_not_empty.register(waker);
+        if self.shared.pending() {
+            self.shared.rx_not_empty.wake();
+        }
+    }
+}
+
+impl<PIN: Channel<Adc, ID=u8>> embedded_async_sandbox::adc::oneshot::Default<Adc, u16, PIN> for AdcDriver {}
+
+impl embedded_async_sandbox::adc::continuous::Default<u16> for AdcDriver {}
+
+pub struct AdcIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for AdcIrq {
+    fn tick(&self) {
+        self.shared.adc.lock().unwrap().make_progress();
+
+        if self.shared.pending() {
+            self.shared.rx_not_empty.wake();
+        }
+    }
+}
@@ -0,0 +1,420 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::can::{AcceptanceFilter, Can, ErrorState, Filter, Frame, Id};
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+/// Number of frames the receive FIFO of a controller holds
+const RX_FIFO_SIZE: usize = 3;
+/// Number of acceptance filters of a controller
+const FILTER_COUNT: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CanError {
+    /// A frame was received while the receive FIFO was full
+    Overrun,
+    /// The controller has too many transmit errors to take part in bus traffic
+    BusOff,
+}
+
+/// More filters than the controller has were configured
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TooManyFilters;
+
+/// Orders frames by arbitration priority, smaller keys win the bus
+///
+/// The base identifier is sent first. A standard frame beats an extended frame with
+/// the same base identifier, and a data frame beats a remote frame with the same identifier.
+fn priority(frame: &Frame) -> (u16, bool, u32, bool) {
+    match frame.id() {
+        Id::Standard(id) => (id.as_raw(), false, 0, frame.is_remote_frame()),
+        Id::Extended(id) => (id.standard_id().as_raw(), true, id.as_raw(), frame.is_remote_frame()),
+    }
+}
+
+/// Returns the number of ticks `frame` occupies the bus
+fn frame_ticks(frame: &Frame) -> usize {
+    let header = if frame.is_extended() { 6 } else { 4 };
+    header + frame.data().len()
+}
+
+struct Controller {
+    mailboxes: Vec<Frame>,
+    mailbox_count: usize,
+    rx_fifo: VecDeque<Frame>,
+    overrun: bool,
+    filters: Option<Vec<Filter>>,
+    tec: u32,
+    rec: u32,
+    tx_errors: usize,
+    rx_errors: usize,
+}
+
+impl Controller {
+    fn new(mailbox_count: usize) -> Self {
+        Self {
+            mailboxes: Vec::new(),
+            mailbox_count,
+            rx_fifo: VecDeque::new(),
+            overrun: false,
+            filters: None,
+            tec: 0,
+            rec: 0,
+            tx_errors: 0,
+            rx_errors: 0,
+        }
+    }
+
+    fn error_state(&self) -> ErrorState {
+        if self.tec > 255 {
+            ErrorState::BusOff
+        } else if self.tec > 127 || self.rec > 127 {
+            ErrorState::Passive
+        } else {
+            ErrorState::Active
+        }
+    }
+
+    fn is_bus_off(&self) -> bool {
+        self.error_state() == ErrorState::BusOff
+    }
+
+    /// Returns the index of the mailbox holding the frame with the highest priority
+    fn next_mailbox(&self) -> Option<usize> {
+        self.mailboxes.iter()
+            .enumerate()
+            .min_by_key(|(_, frame)| priority(frame))
+            .map(|(i, _)| i)
+    }
+
+    fn accepts(&self, frame: &Frame) -> bool {
+        match &self.filters {
+            Some(filters) => filters.iter().any(|filter| filter.matches(frame.id())),
+            None => true,
+        }
+    }
+
+    fn pending(&self, event: Event) -> bool {
+        match event {
+            Event::RxNotEmpty => !self.rx_fifo.is_empty() || self.overrun || self.is_bus_off(),
+            Event::TxSpace => self.mailboxes.len() < self.mailbox_count || self.is_bus_off(),
+            Event::TxIdle => self.mailboxes.is_empty() || self.is_bus_off(),
+        }
+    }
+}
+
+struct Transmission {
+    node: usize,
+    mailbox: usize,
+    frame: Frame,
+    ticks_left: usize,
+}
+
+struct Bus {
+    controllers: Vec<Controller>,
+    transmission: Option<Transmission>,
+    log: Vec<(usize, Frame)>,
+}
+
+impl Bus {
+    fn make_progress(&mut self) {
+        match self.transmission.take() {
+            Some(mut transmission) if transmission.ticks_left > 1 => {
+                transmission.ticks_left -= 1;
+                self.transmission = Some(transmission);
+            },
+            Some(transmission) => self.complete(transmission),
+            None => self.arbitrate(),
+        }
+    }
+
+    fn arbitrate(&mut self) {
+        let winner = self.controllers.iter()
+            .enumerate()
+            .filter(|(_, controller)| !controller.is_bus_off())
+            .filter_map(|(node, controller)| {
+                controller.next_mailbox().map(|mailbox| (node, mailbox, controller.mailboxes[mailbox]))
+            })
+            .min_by_key(|(_, _, frame)| priority(frame));
+
+        if let Some((node, mailbox, frame)) = winner {
+            println!("node {}: arbitration won by {:?}", node, frame.id());
+            self.transmission = Some(Transmission {
+                node,
+                mailbox,
+                frame,
+                ticks_left: frame_ticks(&frame),
+            });
+        }
+    }
+
+    fn complete(&mut self, transmission: Transmission) {
+        let node = transmission.node;
+        let acknowledged = self.controllers.iter()
+            .enumerate()
+            .any(|(i, controller)| i != node && !controller.is_bus_off());
+
+        let sender = &mut self.controllers[node];
+        if sender.tx_errors > 0 {
+            sender.tx_errors -= 1;
+            sender.tec += 8;
+            println!("node {}: bit error, tec {}", node, sender.tec);
+            if sender.is_bus_off() {
+                println!("node {}: bus-off", node);
+                sender.mailboxes.clear();
+            }
+            return;
+        }
+        if !acknowledged {
+            // An error-passive transmitter does not count missing acknowledges
+            if sender.error_state() == ErrorState::Active {
+                sender.tec += 8;
+            }
+            println!("node {}: no acknowledge, tec {}", node, sender.tec);
+            return;
+        }
+
+        sender.mailboxes.remove(transmission.mailbox);
+        sender.tec = sender.tec.saturating_sub(1);
+        println!("node {}: sent {:?}", node, transmission.frame);
+        self.log.push((node, transmission.frame));
+
+        for (i, controller) in self.controllers.iter_mut().enumerate() {
+            if i == node || controller.is_bus_off() {
+                continue;
+            }
+            if controller.rx_errors > 0 {
+                controller.rx_errors -= 1;
+                controller.rec += 8;
+                println!("node {}: form error, rec {}", i, controller.rec);
+                continue;
+            }
+            controller.rec = controller.rec.saturating_sub(1);
+            if !controller.accepts(&transmission.frame) {
+                continue;
+            }
+            if controller.rx_fifo.len() < RX_FIFO_SIZE {
+                controller.rx_fifo.push_back(transmission.frame);
+            } else {
+                controller.overrun = true;
+            }
+        }
+    }
+}
+
+struct Wakers {
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
+}
+
+impl Wakers {
+    fn slot(&self, event: Event) -> &AtomicWaker {
+        match event {
+            Event::RxNotEmpty => &self.rx_not_empty,
+            Event::TxSpace => &self.tx_space,
+            Event::TxIdle => &self.tx_idle,
+        }
+    }
+}
+
+struct Shared {
+    bus: Mutex<Bus>,
+    wakers: Mutex<Vec<Arc<Wakers>>>,
+}
+
+impl Shared {
+    fn bus(&self) -> MutexGuard<'_, Bus> {
+        self.bus.lock().unwrap()
+    }
+}
+
+/// Simulated CAN bus
+///
+/// Once the bus is idle, every controller offers the frame with the highest priority among
+/// its mailboxes and the one with the highest priority overall wins the arbitration.
+/// Arbitration takes a tick, then the frame occupies the bus for 4 ticks, 6 with an extended
+/// identifier, plus one tick per data byte. A frame that nobody acknowledges or that fails
+/// with an injected transmit error is retried, a frame that fails with an injected receive
+/// error is only lost for the receiving node.
+pub struct CanBus {
+    shared: Arc<Shared>,
+}
+
+impl CanBus {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                bus: Mutex::new(Bus {
+                    controllers: Vec::new(),
+                    transmission: None,
+                    log: Vec::new(),
+                }),
+                wakers: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    /// Connects a controller with `mailboxes` transmit mailboxes to the bus
+    ///
+    /// Controllers receive every frame until acceptance filters are configured.
+    pub fn connect(&self, mailboxes: usize) -> CanNode {
+        assert!(mailboxes > 0);
+        let mut bus = self.shared.bus();
+        bus.controllers.push(Controller::new(mailboxes));
+        let wakers = Arc::new(Wakers {
+            rx_not_empty: AtomicWaker::new(),
+            tx_space: AtomicWaker::new(),
+            tx_idle: AtomicWaker::new(),
+        });
+        self.shared.wakers.lock().unwrap().push(wakers.clone());
+        CanNode {
+            index: bus.controllers.len() - 1,
+            shared: self.shared.clone(),
+            wakers,
+        }
+    }
+
+    /// Returns the clock and interrupt input of the bus and all connected controllers
+    pub fn irq(&self) -> CanBusIrq {
+        CanBusIrq {
+            shared: self.shared.clone()
+        }
+    }
+
+    /// Returns the frames sent so far, with the index of the node that sent them
+    pub fn log(&self) -> Vec<(usize, Frame)> {
+        self.shared.bus().log.clone()
+    }
+}
+
+/// Controller connected to a simulated `CanBus`
+pub struct CanNode {
+    index: usize,
+    shared: Arc<Shared>,
+    wakers: Arc<Wakers>,
+}
+
+impl CanNode {
+    /// Returns the index of the node in the bus log
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Makes the next `count` frames sent by the node fail with a bit error
+    ///
+    /// Every failure raises the transmit error counter by 8, the frame is then retried.
+    pub fn inject_tx_errors(&self, count: usize) {
+        self.shared.bus().controllers[self.index].tx_errors += count;
+    }
+
+    /// Makes the next `count` frames received by the node fail with a form error
+    ///
+    /// Every failure raises the receive error counter by 8, the frame is lost for this node
+    /// and not retried, whether it would have passed the filters or not.
+    pub fn inject_rx_errors(&self, count: usize) {
+        self.shared.bus().controllers[self.index].rx_errors += count;
+    }
+
+    /// Returns the transmit and receive error counters
+    pub fn error_counters(&self) -> (u32, u32) {
+        let bus = self.shared.bus();
+        let controller = &bus.controllers[self.index];
+        (controller.tec, controller.rec)
+    }
+
+    /// Completes the bus-off recovery sequence right away, resetting the error counters
+    pub fn recover(&mut self) {
+        let mut bus = self.shared.bus();
+        let controller = &mut bus.controllers[self.index];
+        controller.tec = 0;
+        controller.rec = 0;
+    }
+}
+
+impl Can for CanNode {
+    type Error = CanError;
+
+    fn transmit(&mut self, frame: &Frame) -> nb::Result<(), Self::Error> {
+        let mut bus = self.shared.bus();
+        let controller = &mut bus.controllers[self.index];
+
+        if controller.is_bus_off() {
+            return Err(nb::Error::Other(CanError::BusOff));
+        }
+        if controller.mailboxes.len() < controller.mailbox_count {
+            println!("node {}: transmit({:?}) - Ok", self.index, frame.id());
+            controller.mailboxes.push(*frame);
+            Ok(())
+        } else {
+            println!("node {}: transmit({:?}) - WouldBlock", self.index, frame.id());
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    fn receive(&mut self) -> nb::Result<Frame, Self::Error> {
+        let mut bus = self.shared.bus();
+        let controller = &mut bus.controllers[self.index];
+
+        if controller.overrun {
+            controller.overrun = false;
+            return Err(nb::Error::Other(CanError::Overrun));
+        }
+        match controller.rx_fifo.pop_front() {
+            Some(frame) => Ok(frame),
+            None if controller.is_bus_off() => Err(nb::Error::Other(CanError::BusOff)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn error_state(&self) -> ErrorState {
+        self.shared.bus().controllers[self.index].error_state()
+    }
+}
+
+impl AcceptanceFilter for CanNode {
+    type Error = TooManyFilters;
+
+    fn set_filters(&mut self, filters: &[Filter]) -> Result<(), Self::Error> {
+        if filters.len() > FILTER_COUNT {
+            return Err(TooManyFilters);
+        }
+        self.shared.bus().controllers[self.index].filters = Some(filters.to_vec());
+        Ok(())
+    }
+}
+
+impl RegisterWaker for CanNode {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = self.wakers.slot(event);
+        slot.register(waker);
+        if self.shared.bus().controllers[self.index].pending(event) {
+            slot.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::can::transmit::Default for CanNode {}
+
+impl embedded_async_sandbox::can::receive::Default for CanNode {}
+
+pub struct CanBusIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for CanBusIrq {
+    fn tick(&self) {
+        let mut bus = self.shared.bus();
+        bus.make_progress();
+
+        let wakers = self.shared.wakers.lock().unwrap();
+        for (controller, wakers) in bus.controllers.iter().zip(wakers.iter()) {
+            for &event in &[Event::RxNotEmpty, Event::TxSpace, Event::TxIdle] {
+                if controller.pending(event) {
+                    wakers.slot(event).wake();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CaptureError {
+    /// An edge was captured before the previous capture was read
+    Overcapture,
+}
+
+/// Input channel of the simulated capture timer
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Channel {
+    Ch0,
+    Ch1,
+}
+
+#[derive(Default)]
+struct CaptureChannel {
+    enabled: bool,
+    edges: VecDeque<u64>,
+    latched: Option<u32>,
+    overcapture: bool,
+}
+
+/// Simulated timer with two input capture channels
+///
+/// The counter advances once every `resolution` ticks. Edges are played back at
+/// the tick they are scheduled for and latch the counter value on an enabled channel.
+/// Edges on a disabled channel are ignored.
+pub struct CaptureTimer {
+    ticks: u64,
+    resolution: u32,
+    start: u32,
+    channels: [CaptureChannel; 2],
+}
+
+impl CaptureTimer {
+    pub fn new() -> Self {
+        Self {
+            ticks: 0,
+            resolution: 1,
+            start: 0,
+            channels: Default::default(),
+        }
+    }
+
+    /// Schedules edges on the input of `channel` at the given tick counts
+    pub fn play<I: IntoIterator<Item=u64>>(&mut self, channel: Channel, edges: I) {
+        self.channels[channel as usize].edges.extend(edges);
+    }
+
+    /// Sets the counter value at tick 0, the counter wraps around after `u32::MAX`
+    pub fn set_counter(&mut self, start: u32) {
+        self.start = start;
+    }
+
+    fn counter(&self) -> u32 {
+        self.start.wrapping_add((self.ticks / self.resolution as u64) as u32)
+    }
+
+    fn has_data(&self) -> bool {
+        self.channels.iter().any(|ch| ch.latched.is_some() || ch.overcapture)
+    }
+
+    fn make_progress(&mut self) {
+        self.ticks += 1;
+
+        let ticks = self.ticks;
+        let counter = self.counter();
+        for (index, ch) in self.channels.iter_mut().enumerate() {
+            while let Some(&edge) = ch.edges.front() {
+                if edge > ticks {
+                    break;
+                }
+                ch.edges.pop_front();
+                if !ch.enabled {
+                    continue;
+                }
+                println!("capture {}: {}", index, counter);
+                if ch.latched.is_some() {
+                    ch.overcapture = true;
+                }
+                ch.latched = Some(counter);
+            }
+        }
+    }
+}
+
+struct Shared {
+    timer: Mutex<CaptureTimer>,
+    rx_not_empty: AtomicWaker,
+}
+
+impl Shared {
+    fn pending(&self) -> bool {
+        self.timer.lock().unwrap().has_data()
+    }
+}
+
+pub struct CaptureDriver {
+    shared: Arc<Shared>,
+}
+
+impl CaptureDriver {
+    pub fn new(timer: CaptureTimer) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                timer: Mutex::new(timer),
+                rx_not_empty: AtomicWaker::new(),
+            })
+        }
+    }
+
+    /// Returns the clock and interrupt input of the underlying `CaptureTimer`
+    pub fn irq(&self) -> CaptureIrq {
+        CaptureIrq {
+            shared: self.shared.clone()
+        }
+    }
+
+    fn timer(&self) -> MutexGuard<'_, CaptureTimer> {
+        self.shared.timer.lock().unwrap()
+    }
+}
+
+impl embedded_hal::Capture for CaptureDriver {
+    type Error = CaptureError;
+    type Channel = Channel;
+    /// Number of ticks per counter increment
+    type Time = u32;
+    type Capture = u32;
+
+    fn capture(&mut self, channel: Channel) -> nb::Result<u32, Self::Error> {
+        let mut timer = self.timer();
+        let ch = &mut timer.channels[channel as usize];
+
+        if ch.overcapture {
+            ch.overcapture = false;
+            return Err(nb::Error::Other(CaptureError::Overcapture));
+        }
+        match ch.latched.take() {
+            Some(capture) => Ok(capture),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn disable(&mut self, channel: Channel) {
+        let mut timer = self.timer();
+        let ch = &mut timer.channels[channel as usize];
+        ch.enabled = false;
+        ch.latched = None;
+        ch.overcapture = false;
+    }
+
+    fn enable(&mut self, channel: Channel) {
+        self.timer().channels[channel as usize].enabled = true;
+    }
+
+    fn get_resolution(&self) -> u32 {
+        self.timer().resolution
+    }
+
+    fn set_resolution<R: Into<u32>>(&mut self, resolution: R) {
+        let resolution = resolution.into();
+        assert!(resolution > 0);
+        self.timer().resolution = resolution;
+    }
+}
+
+impl RegisterWaker for CaptureDriver {
+    fn register_waker(&self, _event: Event, waker: &Waker) {
+        self.shared.rx_not_empty.register(waker);
+        if self.shared.pending() {
+            self.shared.rx_not_empty.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::capture::input::Default for CaptureDriver {}
+
+pub struct CaptureIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for CaptureIrq {
+    fn tick(&self) {
+        self.shared.timer.lock().unwrap().make_progress();
+
+        if self.shared.pending() {
+            self.shared.rx_not_empty.wake();
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::timer::{AsyncDelay, AsyncDelayMs, AsyncDelayUs};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Number of slots of the timer wheel
+const WHEEL_SLOTS: usize = 64;
+
+struct Inner {
+    now: u64,
+    tick_period_us: u32,
+    sources: Vec<Box<dyn Tick + Send>>,
+    // Timers are kept in the slot of their deadline modulo the number of slots,
+    // so that a tick only has to look at a single slot
+    wheel: Vec<Vec<(u64, Waker)>>,
+}
+
+/// Simulated monotonic clock shared by peripherals and timers
+///
+/// Every tick advances the attached peripherals by one step of their tick model
+/// and expires the timers whose deadline was reached.
+#[derive(Clone)]
+pub struct Clock {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Clock {
+    /// Creates a clock ticking every 100µs, the period of `irq::spawn`
+    pub fn new() -> Self {
+        Self::with_tick_period_us(100)
+    }
+
+    /// Creates a clock ticking every `tick_period_us` microseconds
+    pub fn with_tick_period_us(tick_period_us: u32) -> Self {
+        assert!(tick_period_us > 0);
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                now: 0,
+                tick_period_us,
+                sources: Vec::new(),
+                wheel: (0..WHEEL_SLOTS).map(|_| Vec::new()).collect(),
+            }))
+        }
+    }
+
+    /// Feeds `source` from this clock
+    pub fn attach<T: Tick + Send + 'static>(&self, source: T) {
+        self.inner.lock().unwrap().sources.push(Box::new(source));
+    }
+
+    /// Returns the number of ticks elapsed since the clock was created
+    pub fn now(&self) -> u64 {
+        self.inner.lock().unwrap().now
+    }
+
+    /// Returns the number of microseconds elapsed since the clock was created
+    pub fn now_us(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        inner.now * inner.tick_period_us as u64
+    }
+
+    /// Returns a timer running off this clock
+    pub fn timer(&self) -> Timer {
+        Timer {
+            clock: self.clone()
+        }
+    }
+
+    /// Polls `future` to completion, advancing the clock by one tick each time it is pending
+    ///
+    /// Time only passes while the future waits, so the number of elapsed ticks is deterministic.
+    pub fn run_until<F: Future>(&self, future: F) -> RunUntil<F> {
+        RunUntil {
+            clock: self.clone(),
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl Tick for Clock {
+    fn tick(&self) {
+        let mut expired = Vec::new();
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.now += 1;
+            for source in &inner.sources {
+                source.tick();
+            }
+
+            let now = inner.now;
+            let slot = &mut inner.wheel[now as usize % WHEEL_SLOTS];
+            let mut i = 0;
+            while i < slot.len() {
+                // Timers more than a full turn of the wheel away stay for later rounds
+                if slot[i].0 <= now {
+                    expired.push(slot.swap_remove(i).1);
+                } else {
+                    i += 1;
+                }
+            }
+        }
+
+        for waker in expired {
+            waker.wake();
+        }
+    }
+}
+
+pub struct RunUntil<F> {
+    clock: Clock,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for RunUntil<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.future.as_mut().poll(cx) {
+            Poll::Ready(output) => Poll::Ready(output),
+            Poll::Pending => {
+                self.clock.tick();
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Timer handle of a simulated `Clock`
+pub struct Timer {
+    clock: Clock,
+}
+
+impl AsyncDelay for Timer {
+    type DelayFuture<'t> = Delay<'t>;
+
+    fn async_delay(&mut self, ticks: u32) -> Self::DelayFuture<'_> {
+        Delay {
+            clock: &self.clock,
+            ticks: ticks as u64,
+            deadline: None,
+        }
+    }
+}
+
+impl Timer {
+    /// Returns a delay of at least `us` microseconds, rounded up to whole ticks
+    fn delay_us(&self, us: u64) -> Delay<'_> {
+        let period = self.clock.inner.lock().unwrap().tick_period_us as u64;
+        let mut ticks = us / period;
+        if ticks * period < us {
+            ticks += 1;
+        }
+        Delay {
+            clock: &self.clock,
+            ticks,
+            deadline: None,
+        }
+    }
+}
+
+impl AsyncDelayMs for Timer {
+    type DelayMsFuture<'t> = Delay<'t>;
+
+    fn async_delay_ms(&mut self, ms: u32) -> Self::DelayMsFuture<'_> {
+        self.delay_us(ms as u64 * 1000)
+    }
+}
+
+impl AsyncDelayUs for Timer {
+    type DelayUsFuture<'t> = Delay<'t>;
+
+    fn async_delay_us(&mut self, us: u32) -> Self::DelayUsFuture<'_> {
+        self.delay_us(us as u64)
+    }
+}
+
+pub struct Delay<'a> {
+    clock: &'a Clock,
+    ticks: u64,
+    deadline: Option<u64>,
+}
+
+impl Future for Delay<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut inner = this.clock.inner.lock().unwrap();
+        let deadline = *this.deadline.get_or_insert(inner.now + this.ticks);
+        if inner.now >= deadline {
+            Poll::Ready(())
+        } else {
+            let slot = &mut inner.wheel[deadline as usize % WHEEL_SLOTS];
+            let registered = slot.iter()
+                .any(|(d, waker)| *d == deadline && waker.will_wake(cx.waker()));
+            if !registered {
+                slot.push((deadline, cx.waker().clone()));
+            }
+            Poll::Pending
+        }
+    }
+}
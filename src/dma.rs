@@ -0,0 +1,237 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::dma::Channel;
+use embedded_async_sandbox::waker::AtomicWaker;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+/// Request line of a peripheral that accepts bytes from memory
+pub trait TxRequest: Send + Sync + 'static {
+    type Error: Send + 'static;
+
+    /// Moves `byte` into the peripheral, returns false if it has no room for it
+    fn push(&self, byte: u8) -> Result<bool, Self::Error>;
+}
+
+/// Request line of a peripheral that provides bytes to memory
+pub trait RxRequest: Send + Sync + 'static {
+    type Error: Send + 'static;
+
+    /// Takes the next byte out of the peripheral, if there is one
+    fn pop(&self) -> Option<Result<u8, Self::Error>>;
+}
+
+enum Port<E> {
+    Tx(Box<dyn TxRequest<Error=E>>),
+    Rx(Box<dyn RxRequest<Error=E>>),
+}
+
+enum Status<E> {
+    Idle,
+    Busy,
+    Complete,
+    Failed(E),
+}
+
+struct State<E> {
+    status: Status<E>,
+    /// Memory given to the running transfer, as an address so that it can cross threads
+    address: usize,
+    len: usize,
+    moved: usize,
+}
+
+impl<E> State<E> {
+    fn is_busy(&self) -> bool {
+        matches!(self.status, Status::Busy)
+    }
+}
+
+struct Shared<E> {
+    index: usize,
+    port: Port<E>,
+    state: Mutex<State<E>>,
+    complete: AtomicWaker,
+}
+
+impl<E> Shared<E> {
+    fn state(&self) -> MutexGuard<'_, State<E>> {
+        self.state.lock().unwrap()
+    }
+}
+
+trait Service: Send + Sync {
+    fn service(&self);
+}
+
+impl<E: Send + 'static> Service for Shared<E> {
+    /// Moves as many bytes as the peripheral can take or provide right now
+    fn service(&self) {
+        let mut state = self.state();
+        if !state.is_busy() {
+            return;
+        }
+
+        while state.moved < state.len {
+            let address = (state.address + state.moved) as *mut u8;
+            let result = match &self.port {
+                Port::Tx(request) => {
+                    // The memory stays valid until the channel is stopped, which takes the lock
+                    let byte = unsafe { address.read_volatile() };
+                    request.push(byte)
+                },
+                Port::Rx(request) => match request.pop() {
+                    Some(Ok(byte)) => {
+                        unsafe { address.write_volatile(byte) };
+                        Ok(true)
+                    },
+                    Some(Err(e)) => Err(e),
+                    None => Ok(false),
+                },
+            };
+            match result {
+                Ok(true) => state.moved += 1,
+                Ok(false) => break,
+                Err(e) => {
+                    println!("dma {}: error after {} bytes", self.index, state.moved);
+                    state.status = Status::Failed(e);
+                    break;
+                }
+            }
+        }
+
+        if state.is_busy() && state.moved == state.len {
+            println!("dma {}: complete", self.index);
+            state.status = Status::Complete;
+        }
+        if !state.is_busy() {
+            drop(state);
+            self.complete.wake();
+        }
+    }
+}
+
+/// Simulated DMA controller
+///
+/// Every tick, each busy channel moves as many bytes as its peripheral can accept or provide,
+/// in the order the channels were created. Attach the controller to the clock after
+/// the peripherals it serves, so that it sees their FIFOs once they have made progress.
+pub struct DmaEngine {
+    channels: Arc<Mutex<Vec<Arc<dyn Service>>>>,
+}
+
+impl DmaEngine {
+    pub fn new() -> Self {
+        Self {
+            channels: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Creates a channel moving bytes from memory to the peripheral behind `request`
+    pub fn tx_channel<R: TxRequest>(&self, request: R) -> DmaChannel<R::Error> {
+        self.channel(Port::Tx(Box::new(request)))
+    }
+
+    /// Creates a channel moving bytes from the peripheral behind `request` to memory
+    pub fn rx_channel<R: RxRequest>(&self, request: R) -> DmaChannel<R::Error> {
+        self.channel(Port::Rx(Box::new(request)))
+    }
+
+    /// Returns the clock input of the controller
+    pub fn irq(&self) -> DmaEngineIrq {
+        DmaEngineIrq {
+            channels: self.channels.clone()
+        }
+    }
+
+    fn channel<E: Send + 'static>(&self, port: Port<E>) -> DmaChannel<E> {
+        let mut channels = self.channels.lock().unwrap();
+        let shared = Arc::new(Shared {
+            index: channels.len(),
+            port,
+            state: Mutex::new(State {
+                status: Status::Idle,
+                address: 0,
+                len: 0,
+                moved: 0,
+            }),
+            complete: AtomicWaker::new(),
+        });
+        channels.push(shared.clone());
+        DmaChannel {
+            shared
+        }
+    }
+}
+
+/// Channel of the simulated DMA controller
+///
+/// Starting a channel that is still running panics, as the previous transfer
+/// would keep accessing memory its owner may have reused.
+pub struct DmaChannel<E> {
+    shared: Arc<Shared<E>>,
+}
+
+impl<E> DmaChannel<E> {
+    /// Returns true while the channel is moving bytes
+    pub fn is_busy(&self) -> bool {
+        self.shared.state().is_busy()
+    }
+}
+
+impl<E> Channel for DmaChannel<E> {
+    type Error = E;
+
+    unsafe fn start(&mut self, address: *mut u8, len: usize) {
+        let mut state = self.shared.state();
+        assert!(!state.is_busy(), "DMA channel {} started while busy", self.shared.index);
+        println!("dma {}: start {} bytes", self.shared.index, len);
+        state.status = Status::Busy;
+        state.address = address as usize;
+        state.len = len;
+        state.moved = 0;
+    }
+
+    fn complete(&mut self) -> nb::Result<(), Self::Error> {
+        let mut state = self.shared.state();
+        match std::mem::replace(&mut state.status, Status::Idle) {
+            Status::Busy => {
+                state.status = Status::Busy;
+                Err(nb::Error::WouldBlock)
+            },
+            Status::Failed(e) => Err(nb::Error::Other(e)),
+            Status::Idle | Status::Complete => {
+                state.status = Status::Complete;
+                Ok(())
+            },
+        }
+    }
+
+    fn stop(&mut self) -> usize {
+        let mut state = self.shared.state();
+        if state.is_busy() {
+            println!("dma {}: stopped after {} bytes", self.shared.index, state.moved);
+        }
+        state.status = Status::Idle;
+        state.moved
+    }
+
+    fn register_waker(&self, waker: &Waker) {
+        self.shared.complete.register(waker);
+        if !self.is_busy() {
+            self.shared.complete.wake();
+        }
+    }
+}
+
+pub struct DmaEngineIrq {
+    channels: Arc<Mutex<Vec<Arc<dyn Service>>>>,
+}
+
+impl Tick for DmaEngineIrq {
+    fn tick(&self) {
+        let channels = self.channels.lock().unwrap().clone();
+        for channel in channels {
+            channel.service();
+        }
+    }
+}
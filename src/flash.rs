@@ -0,0 +1,298 @@
+use crate::gpio::Pin;
+use crate::irq::Tick;
+use crate::spi::SpiError;
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+const WRITE_ENABLE: u8 = 0x06;
+const WRITE_DISABLE: u8 = 0x04;
+const READ_STATUS: u8 = 0x05;
+const READ_DATA: u8 = 0x03;
+const PAGE_PROGRAM: u8 = 0x02;
+const SECTOR_ERASE: u8 = 0x20;
+const READ_JEDEC_ID: u8 = 0x9f;
+
+const MANUFACTURER: u8 = 0xef;
+const MEMORY_TYPE: u8 = 0x40;
+
+const PAGE_SIZE: usize = 256;
+const SECTOR_SIZE: usize = 4096;
+
+/// Ticks a page program keeps the chip busy
+const PAGE_PROGRAM_TICKS: usize = 20;
+/// Ticks a sector erase keeps the chip busy
+const SECTOR_ERASE_TICKS: usize = 200;
+
+struct Chip {
+    memory: Vec<u8>,
+    /// Write enable latch, set by WRITE_ENABLE and cleared by every program or erase
+    write_enabled: bool,
+    /// Ticks until the running program or erase completes
+    busy_ticks: usize,
+    /// Bytes received since the chip was selected, starting with the command
+    command: Vec<u8>,
+    /// Falling edges of the chip select seen when the current command started
+    selected_at: Option<usize>,
+    overwrites: usize,
+}
+
+impl Chip {
+    fn status(&self) -> u8 {
+        (self.busy_ticks > 0) as u8 | (self.write_enabled as u8) << 1
+    }
+
+    fn address(&self) -> Option<usize> {
+        if self.command.len() < 4 {
+            return None;
+        }
+        Some((self.command[1] as usize) << 16 | (self.command[2] as usize) << 8 | self.command[3] as usize)
+    }
+
+    /// Receives `byte` while selected, returns the byte sent back
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.command.push(byte);
+        let index = self.command.len() - 1;
+        match self.command[0] {
+            READ_STATUS if index > 0 => self.status(),
+            // Everything except the status register is ignored while busy
+            _ if self.busy_ticks > 0 => 0xff,
+            READ_JEDEC_ID => match index {
+                1 => MANUFACTURER,
+                2 => MEMORY_TYPE,
+                3 => self.memory.len().trailing_zeros() as u8,
+                _ => 0xff,
+            },
+            READ_DATA => match self.address() {
+                Some(address) if index > 3 => self.memory[(address + index - 4) % self.memory.len()],
+                _ => 0xff,
+            },
+            _ => 0xff,
+        }
+    }
+
+    /// Executes the current command once the chip is deselected
+    fn deselect(&mut self) {
+        self.selected_at = None;
+        let command = std::mem::take(&mut self.command);
+        if command.is_empty() || self.busy_ticks > 0 {
+            return;
+        }
+        match command[0] {
+            WRITE_ENABLE => self.write_enabled = true,
+            WRITE_DISABLE => self.write_enabled = false,
+            PAGE_PROGRAM | SECTOR_ERASE if !self.write_enabled => {
+                println!("flash: {:02x} ignored, writes are not enabled", command[0]);
+            },
+            PAGE_PROGRAM if command.len() > 4 => {
+                self.command = command;
+                let address = self.address().unwrap() % self.memory.len();
+                let page = address - address % PAGE_SIZE;
+                println!("flash: program {} bytes at {:06x}", self.command.len() - 4, address);
+                // Data past the end of the page wraps around to its start
+                for (i, byte) in self.command[4..].iter().enumerate() {
+                    let cell = &mut self.memory[page + (address + i) % PAGE_SIZE];
+                    if *cell != 0xff {
+                        self.overwrites += 1;
+                    }
+                    *cell &= *byte;
+                }
+                self.command.clear();
+                self.write_enabled = false;
+                self.busy_ticks = PAGE_PROGRAM_TICKS;
+            },
+            SECTOR_ERASE if command.len() == 4 => {
+                self.command = command;
+                let address = self.address().unwrap() % self.memory.len();
+                let sector = address - address % SECTOR_SIZE;
+                println!("flash: erase sector at {:06x}", sector);
+                for cell in &mut self.memory[sector..sector + SECTOR_SIZE] {
+                    *cell = 0xff;
+                }
+                self.command.clear();
+                self.write_enabled = false;
+                self.busy_ticks = SECTOR_ERASE_TICKS;
+            },
+            command => println!("flash: {:02x} ignored", command),
+        }
+    }
+}
+
+struct Bus {
+    cs: Pin,
+    tx: VecDeque<u8>,
+    rx: VecDeque<u8>,
+    overflow: bool,
+    chip: Chip,
+}
+
+impl Bus {
+    fn make_progress(&mut self) {
+        if self.chip.busy_ticks > 0 {
+            self.chip.busy_ticks -= 1;
+        }
+
+        let byte = match self.tx.pop_front() {
+            Some(byte) => byte,
+            None => {
+                if self.cs.is_high() && self.chip.selected_at.is_some() {
+                    self.chip.deselect();
+                }
+                return;
+            },
+        };
+
+        // A falling edge since the current command started means the chip was deselected in between
+        let edges = self.cs.falling_edges();
+        if self.chip.selected_at.is_some() && (self.cs.is_high() || self.chip.selected_at != Some(edges)) {
+            self.chip.deselect();
+        }
+        let response = if self.cs.is_low() {
+            self.chip.selected_at = Some(edges);
+            self.chip.exchange(byte)
+        } else {
+            0xff
+        };
+
+        if self.rx.len() < 4 {
+            self.rx.push_back(response);
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    fn pending(&self, event: Event) -> bool {
+        match event {
+            Event::RxNotEmpty => !self.rx.is_empty() || self.overflow,
+            Event::TxSpace => self.tx.len() < 4,
+            Event::TxIdle => self.tx.is_empty(),
+        }
+    }
+}
+
+struct Shared {
+    bus: Mutex<Bus>,
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
+}
+
+impl Shared {
+    fn bus(&self) -> MutexGuard<'_, Bus> {
+        self.bus.lock().unwrap()
+    }
+
+    fn slot(&self, event: Event) -> &AtomicWaker {
+        match event {
+            Event::RxNotEmpty => &self.rx_not_empty,
+            Event::TxSpace => &self.tx_space,
+            Event::TxIdle => &self.tx_idle,
+        }
+    }
+}
+
+/// Simulated SPI peripheral wired to a NOR flash chip with 4 KiB sectors and 256 byte pages
+///
+/// A byte takes a tick to shift. The chip executes program and erase commands when its chip
+/// select goes high, and stays busy for 20 ticks after a page program and 200 ticks after
+/// a sector erase. Programming only clears bits, bytes have to be erased to be written again.
+pub struct SpiFlash {
+    shared: Arc<Shared>,
+}
+
+impl SpiFlash {
+    /// Creates a chip of `capacity` bytes, erased, selected by `cs` going low
+    pub fn new(cs: &Pin, capacity: usize) -> Self {
+        assert!(capacity.is_power_of_two() && (SECTOR_SIZE..=1 << 24).contains(&capacity));
+        Self {
+            shared: Arc::new(Shared {
+                bus: Mutex::new(Bus {
+                    cs: cs.clone(),
+                    tx: VecDeque::new(),
+                    rx: VecDeque::new(),
+                    overflow: false,
+                    chip: Chip {
+                        memory: vec![0xff; capacity],
+                        write_enabled: false,
+                        busy_ticks: 0,
+                        command: Vec::new(),
+                        selected_at: None,
+                        overwrites: 0,
+                    },
+                }),
+                rx_not_empty: AtomicWaker::new(),
+                tx_space: AtomicWaker::new(),
+                tx_idle: AtomicWaker::new(),
+            })
+        }
+    }
+
+    /// Returns a copy of the memory array
+    pub fn memory(&self) -> Vec<u8> {
+        self.shared.bus().chip.memory.clone()
+    }
+
+    /// Returns the number of bytes programmed without being erased first
+    pub fn overwrites(&self) -> usize {
+        self.shared.bus().chip.overwrites
+    }
+
+    pub fn irq(&self) -> SpiFlashIrq {
+        SpiFlashIrq {
+            shared: self.shared.clone()
+        }
+    }
+}
+
+impl embedded_hal::spi::FullDuplex<u8> for SpiFlash {
+    type Error = SpiError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let mut bus = self.shared.bus();
+        if bus.overflow {
+            bus.overflow = false;
+            return Err(nb::Error::Other(SpiError::RxFifoOverflow));
+        }
+        bus.rx.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+
+    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        let mut bus = self.shared.bus();
+        if bus.tx.len() == 4 {
+            return Err(nb::Error::WouldBlock);
+        }
+        bus.tx.push_back(byte);
+        Ok(())
+    }
+}
+
+impl RegisterWaker for SpiFlash {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = self.shared.slot(event);
+        slot.register(waker);
+        if self.shared.bus().pending(event) {
+            slot.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::spi::FillByte for SpiFlash {}
+
+impl embedded_async_sandbox::spi::transfer::Default for SpiFlash {}
+
+pub struct SpiFlashIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for SpiFlashIrq {
+    fn tick(&self) {
+        let mut bus = self.shared.bus();
+        bus.make_progress();
+        for &event in &[Event::RxNotEmpty, Event::TxSpace, Event::TxIdle] {
+            if bus.pending(event) {
+                self.shared.slot(event).wake();
+            }
+        }
+    }
+}
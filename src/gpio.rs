@@ -0,0 +1,156 @@
+use crate::irq::Tick;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+struct Level {
+    high: AtomicBool,
+    falling_edges: AtomicUsize,
+}
+
+/// Simulated push-pull output pin
+///
+/// Clones refer to the same pin, so that peripherals can sample the level driven by a driver.
+#[derive(Clone)]
+pub struct Pin {
+    level: Arc<Level>,
+}
+
+impl Pin {
+    /// Creates a pin driven high
+    pub fn new() -> Self {
+        Self {
+            level: Arc::new(Level {
+                high: AtomicBool::new(true),
+                falling_edges: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    pub fn is_high(&self) -> bool {
+        self.level.high.load(Ordering::SeqCst)
+    }
+
+    pub fn is_low(&self) -> bool {
+        !self.is_high()
+    }
+
+    /// Returns the number of times the pin went from high to low
+    pub fn falling_edges(&self) -> usize {
+        self.level.falling_edges.load(Ordering::SeqCst)
+    }
+}
+
+impl embedded_hal::digital::v2::OutputPin for Pin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        if self.level.high.swap(false, Ordering::SeqCst) {
+            self.level.falling_edges.fetch_add(1, Ordering::SeqCst);
+            println!("set_low()");
+        }
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        if !self.level.high.swap(true, Ordering::SeqCst) {
+            println!("set_high()");
+        }
+        Ok(())
+    }
+}
+
+/// Level change played back on a `ScriptedPin`
+#[derive(Copy, Clone, Debug)]
+pub enum PinEvent {
+    /// The pin goes high
+    High,
+    /// The pin goes low
+    Low,
+    /// The pin keeps its level for the given number of ticks
+    Hold(usize),
+}
+
+struct Script {
+    high: bool,
+    events: VecDeque<PinEvent>,
+}
+
+impl Script {
+    fn make_progress(&mut self) {
+        if let Some(PinEvent::Hold(ticks)) = self.events.front_mut() {
+            *ticks = ticks.saturating_sub(1);
+            if *ticks > 0 {
+                return;
+            }
+            self.events.pop_front();
+        }
+
+        // Level changes following the hold take effect right away
+        while let Some(event) = self.events.front().copied() {
+            match event {
+                PinEvent::High => self.high = true,
+                PinEvent::Low => self.high = false,
+                PinEvent::Hold(_) => return,
+            }
+            self.events.pop_front();
+            println!("pin: {:?}", event);
+        }
+    }
+}
+
+/// Simulated input pin driven by a script
+///
+/// Level changes take no time, so consecutive `High` and `Low` events produce a pulse
+/// too short to be sampled.
+pub struct ScriptedPin {
+    script: Arc<Mutex<Script>>,
+}
+
+impl ScriptedPin {
+    pub fn new(high: bool) -> Self {
+        Self {
+            script: Arc::new(Mutex::new(Script {
+                high,
+                events: VecDeque::new(),
+            }))
+        }
+    }
+
+    /// Appends `events` to the level changes played back on the pin
+    pub fn script<I: IntoIterator<Item=PinEvent>>(&mut self, events: I) {
+        self.script.lock().unwrap().events.extend(events);
+    }
+
+    /// Returns the clock input playing back the script
+    pub fn irq(&self) -> ScriptedPinIrq {
+        ScriptedPinIrq {
+            script: self.script.clone()
+        }
+    }
+}
+
+impl embedded_hal::digital::v2::InputPin for ScriptedPin {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.script.lock().unwrap().high)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!self.script.lock().unwrap().high)
+    }
+}
+
+impl embedded_async_sandbox::digital::input::Default for ScriptedPin {}
+
+pub struct ScriptedPinIrq {
+    script: Arc<Mutex<Script>>,
+}
+
+impl Tick for ScriptedPinIrq {
+    fn tick(&self) {
+        self.script.lock().unwrap().make_progress();
+    }
+}
@@ -0,0 +1,297 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::i2c::{Direction, Master};
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum I2cError {
+    /// No slave acknowledged the address
+    AddressNack,
+    /// The slave did not acknowledge a data byte
+    DataNack,
+    /// Another master won the bus
+    ArbitrationLoss,
+}
+
+/// Simulated I2C slave with a bank of registers
+///
+/// The first byte of a write selects the register, following bytes are written to
+/// consecutive registers. Reads start at the selected register and auto-increment as well.
+pub struct FakeDevice {
+    address: u8,
+    registers: Vec<u8>,
+    pointer: usize,
+    pointer_set: bool,
+    stretch: usize,
+    nack_after: Option<usize>,
+    written: usize,
+}
+
+impl FakeDevice {
+    pub fn new(address: u8, registers: &[u8]) -> Self {
+        Self {
+            address,
+            registers: registers.to_vec(),
+            pointer: 0,
+            pointer_set: false,
+            stretch: 0,
+            nack_after: None,
+            written: 0,
+        }
+    }
+
+    /// Holds the clock low for `ticks` extra ticks on every data byte
+    pub fn set_stretch(&mut self, ticks: usize) {
+        self.stretch = ticks;
+    }
+
+    /// Stops acknowledging data bytes once `bytes` bytes have been written in a transfer
+    pub fn set_nack_after(&mut self, bytes: usize) {
+        self.nack_after = Some(bytes);
+    }
+
+    fn start(&mut self, direction: Direction) {
+        if direction == Direction::Write {
+            self.pointer_set = false;
+            self.written = 0;
+        }
+    }
+
+    fn write(&mut self, byte: u8) -> bool {
+        if matches!(self.nack_after, Some(limit) if self.written >= limit) {
+            return false;
+        }
+        self.written += 1;
+
+        if self.pointer_set {
+            let len = self.registers.len();
+            self.registers[self.pointer % len] = byte;
+            self.pointer += 1;
+        } else {
+            self.pointer = byte as usize;
+            self.pointer_set = true;
+        }
+        true
+    }
+
+    fn read(&mut self) -> u8 {
+        let byte = self.registers[self.pointer % self.registers.len()];
+        self.pointer += 1;
+        byte
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Operation {
+    Start(u8, Direction),
+    Send(u8),
+    Read,
+    Stop,
+}
+
+/// Simulated I2C bus with a master peripheral and attached slaves
+///
+/// Every bus operation takes 2 ticks, plus the clock stretching of the addressed slave
+/// for data bytes.
+pub struct Bus {
+    devices: Vec<FakeDevice>,
+    selected: Option<usize>,
+    operation: Option<Operation>,
+    ticks_left: usize,
+    outcome: Option<Result<u8, I2cError>>,
+    arbitration_losses: Vec<usize>,
+    starts: usize,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self {
+            devices: Vec::new(),
+            selected: None,
+            operation: None,
+            ticks_left: 0,
+            outcome: None,
+            arbitration_losses: Vec::new(),
+            starts: 0,
+        }
+    }
+
+    pub fn attach(&mut self, device: FakeDevice) {
+        self.devices.push(device);
+    }
+
+    /// Lets another master win the arbitration during the `start`-th start condition, counting from 0
+    pub fn script_arbitration_loss(&mut self, start: usize) {
+        self.arbitration_losses.push(start);
+    }
+
+    fn is_idle(&self) -> bool {
+        self.operation.is_none()
+    }
+
+    /// Starts `operation` unless one is in progress, then takes its outcome once complete
+    fn run(&mut self, operation: Operation) -> nb::Result<u8, I2cError> {
+        if let Some(outcome) = self.outcome.take() {
+            return outcome.map_err(nb::Error::Other);
+        }
+        if self.operation.is_none() {
+            let stretch = match (operation, self.selected) {
+                (Operation::Send(_), Some(i)) | (Operation::Read, Some(i)) => self.devices[i].stretch,
+                _ => 0,
+            };
+            self.operation = Some(operation);
+            self.ticks_left = 2 + stretch;
+        }
+        Err(nb::Error::WouldBlock)
+    }
+
+    fn make_progress(&mut self) {
+        let operation = match self.operation {
+            Some(operation) => operation,
+            None => return,
+        };
+        if self.ticks_left > 1 {
+            self.ticks_left -= 1;
+            return;
+        }
+        self.operation = None;
+
+        let outcome = match operation {
+            Operation::Start(address, direction) => {
+                let start = self.starts;
+                self.starts += 1;
+                if self.arbitration_losses.contains(&start) {
+                    self.selected = None;
+                    Err(I2cError::ArbitrationLoss)
+                } else {
+                    self.selected = self.devices.iter().position(|device| device.address == address);
+                    match self.selected {
+                        Some(i) => {
+                            self.devices[i].start(direction);
+                            Ok(0)
+                        },
+                        None => Err(I2cError::AddressNack),
+                    }
+                }
+            },
+            Operation::Send(byte) => match self.selected {
+                Some(i) if self.devices[i].write(byte) => Ok(0),
+                _ => Err(I2cError::DataNack),
+            },
+            Operation::Read => match self.selected {
+                Some(i) => Ok(self.devices[i].read()),
+                None => Ok(0xff),
+            },
+            Operation::Stop => {
+                self.selected = None;
+                Ok(0)
+            },
+        };
+        println!("{:?}: {:?}", operation, outcome);
+        self.outcome = Some(outcome);
+    }
+}
+
+struct Shared {
+    bus: Mutex<Bus>,
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
+}
+
+impl Shared {
+    fn pending(&self) -> bool {
+        let bus = self.bus.lock().unwrap();
+        bus.outcome.is_some() || bus.is_idle()
+    }
+}
+
+/// Master peripheral of a simulated I2C `Bus`
+pub struct I2c {
+    shared: Arc<Shared>,
+}
+
+impl I2c {
+    pub fn new(bus: Bus) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                bus: Mutex::new(bus),
+                rx_not_empty: AtomicWaker::new(),
+                tx_space: AtomicWaker::new(),
+                tx_idle: AtomicWaker::new(),
+            })
+        }
+    }
+
+    /// Returns the clock and interrupt input of the underlying `Bus`
+    pub fn irq(&self) -> I2cIrq {
+        I2cIrq {
+            shared: self.shared.clone()
+        }
+    }
+
+    /// Returns the registers of the slave at `address`
+    pub fn registers(&self, address: u8) -> Vec<u8> {
+        self.bus().devices.iter()
+            .find(|device| device.address == address)
+            .map(|device| device.registers.clone())
+            .unwrap_or_default()
+    }
+
+    fn bus(&self) -> MutexGuard<'_, Bus> {
+        self.shared.bus.lock().unwrap()
+    }
+}
+
+impl Master for I2c {
+    type Error = I2cError;
+
+    fn start(&mut self, address: u8, direction: Direction) -> nb::Result<(), Self::Error> {
+        self.bus().run(Operation::Start(address, direction)).map(|_| ())
+    }
+
+    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.bus().run(Operation::Send(byte)).map(|_| ())
+    }
+
+    fn read(&mut self, _last: bool) -> nb::Result<u8, Self::Error> {
+        self.bus().run(Operation::Read)
+    }
+
+    fn stop(&mut self) -> nb::Result<(), Self::Error> {
+        self.bus().run(Operation::Stop).map(|_| ())
+    }
+}
+
+impl RegisterWaker for I2c {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = match event {
+            Event::RxNotEmpty => &self.shared.rx_not_empty,
+            Event::TxSpace => &self.shared.tx_space,
+            Event::TxIdle => &self.shared.tx_idle,
+        };
+        slot.register(waker);
+        if self.shared.pending() {
+            slot.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::i2c::master::Default for I2c {}
+
+pub struct I2cIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for I2cIrq {
+    fn tick(&self) {
+        self.shared.bus.lock().unwrap().make_progress();
+
+        if self.shared.pending() {
+            self.shared.rx_not_empty.wake();
+            self.shared.tx_space.wake();
+            self.shared.tx_idle.wake();
+        }
+    }
+}
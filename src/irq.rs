@@ -0,0 +1,19 @@
+use std::thread;
+use std::time::Duration;
+
+/// Clock input of a simulated peripheral
+///
+/// Each tick advances the hardware model by one step and fires the interrupts
+/// that became pending, waking the futures registered for them.
+pub trait Tick {
+    fn tick(&self);
+}
+
+/// Runs `source` from a background thread, emulating hardware that makes progress
+/// on its own while the executor is parked
+pub fn spawn<T: Tick + Send + 'static>(source: T) {
+    thread::spawn(move || loop {
+        source.tick();
+        thread::sleep(Duration::from_micros(100));
+    });
+}
@@ -1,4 +1,15 @@
 #![allow(dead_code)]
 
+pub mod adc;
+pub mod can;
+pub mod capture;
+pub mod clock;
+pub mod dma;
+pub mod gpio;
+pub mod i2c;
+pub mod irq;
 pub mod spi;
 pub mod serial;
+pub mod usb;
+pub mod onewire;
+pub mod flash;
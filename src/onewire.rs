@@ -0,0 +1,403 @@
+use crate::irq::Tick;
+use crate::serial::UartError;
+use embedded_async_sandbox::onewire::{crc8, Rom, MATCH_ROM, READ_ROM, SEARCH_ROM, SKIP_ROM};
+use embedded_async_sandbox::serial::SetBaudRate;
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+/// Function command starting a temperature conversion
+pub const CONVERT_T: u8 = 0x44;
+/// Function command reading the 9 bytes of the scratchpad
+pub const READ_SCRATCHPAD: u8 = 0xbe;
+
+/// Temperature a sensor reports before its first conversion, 85 °C in 1/16 °C
+const POWER_ON_TEMPERATURE: i16 = 85 * 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+    /// Waiting for a reset pulse, after sending what is left in `tx`
+    Idle,
+    RomCommand,
+    /// Taking part in a search, at the given bit of the ROM code
+    Search(usize),
+    MatchRom,
+    FunctionCommand,
+}
+
+/// DS18B20-style temperature sensor
+struct Device {
+    rom: Rom,
+    state: State,
+    /// Bits the device is going to send in the next read time slots
+    tx: VecDeque<bool>,
+    /// Bits received since the last command
+    rx: Vec<bool>,
+    temperature: i16,
+    scratchpad: [u8; 9],
+}
+
+impl Device {
+    fn new(rom: Rom) -> Self {
+        let mut device = Self {
+            rom,
+            state: State::Idle,
+            tx: VecDeque::new(),
+            rx: Vec::new(),
+            temperature: POWER_ON_TEMPERATURE,
+            scratchpad: [0; 9],
+        };
+        device.convert();
+        device
+    }
+
+    fn convert(&mut self) {
+        let [lsb, msb] = self.temperature.to_le_bytes();
+        self.scratchpad[..8].copy_from_slice(&[lsb, msb, 0x4b, 0x46, 0x7f, 0xff, 0x0c, 0x10]);
+        self.scratchpad[8] = crc8(&self.scratchpad[..8]);
+    }
+
+    fn rom_bit(&self, bit: usize) -> bool {
+        self.rom.as_raw() & (1 << bit) != 0
+    }
+
+    fn send(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.tx.extend((0..8).map(|bit| byte & (1 << bit) != 0));
+        }
+    }
+
+    fn send_search_bits(&mut self, bit: usize) {
+        let value = self.rom_bit(bit);
+        self.tx.push_back(value);
+        self.tx.push_back(!value);
+    }
+
+    fn received_byte(&self) -> Option<u8> {
+        if self.rx.len() < 8 {
+            return None;
+        }
+        Some(self.rx.iter().enumerate().fold(0, |byte, (i, bit)| byte | (*bit as u8) << i))
+    }
+
+    fn reset(&mut self) {
+        self.state = State::RomCommand;
+        self.tx.clear();
+        self.rx.clear();
+    }
+
+    /// Returns the level the device drives the bus to in the next time slot
+    fn drive(&self) -> bool {
+        self.tx.front().copied().unwrap_or(true)
+    }
+
+    /// Completes a time slot in which the bus was at `level`
+    fn slot(&mut self, level: bool) {
+        if self.tx.pop_front().is_some() || self.state == State::Idle {
+            return;
+        }
+        self.rx.push(level);
+
+        match self.state {
+            State::Idle => {},
+            State::RomCommand => {
+                let command = match self.received_byte() {
+                    Some(command) => command,
+                    None => return,
+                };
+                self.rx.clear();
+                self.state = match command {
+                    SEARCH_ROM => {
+                        self.send_search_bits(0);
+                        State::Search(0)
+                    },
+                    READ_ROM => {
+                        self.send(&self.rom.to_bytes());
+                        State::FunctionCommand
+                    },
+                    MATCH_ROM => State::MatchRom,
+                    SKIP_ROM => State::FunctionCommand,
+                    _ => State::Idle,
+                };
+            },
+            State::Search(bit) => {
+                self.rx.clear();
+                if level != self.rom_bit(bit) || bit == 63 {
+                    // Either another device was chosen or the search is complete
+                    self.state = State::Idle;
+                } else {
+                    self.send_search_bits(bit + 1);
+                    self.state = State::Search(bit + 1);
+                }
+            },
+            State::MatchRom => {
+                if self.rx.len() < 64 {
+                    return;
+                }
+                let matches = self.rx.iter().enumerate().all(|(i, bit)| *bit == self.rom_bit(i));
+                self.rx.clear();
+                self.state = if matches { State::FunctionCommand } else { State::Idle };
+            },
+            State::FunctionCommand => {
+                let command = match self.received_byte() {
+                    Some(command) => command,
+                    None => return,
+                };
+                self.rx.clear();
+                match command {
+                    CONVERT_T => {
+                        println!("{:016x}: convert", self.rom.as_raw());
+                        self.convert();
+                    },
+                    READ_SCRATCHPAD => {
+                        let scratchpad = self.scratchpad;
+                        self.send(&scratchpad);
+                    },
+                    _ => {},
+                }
+                // Whatever is left to send goes out before the device waits for the next reset
+                self.state = State::Idle;
+            },
+        }
+    }
+}
+
+struct Transmission {
+    byte: u8,
+    ticks_left: usize,
+}
+
+struct Bus {
+    baud_rate: u32,
+    tx: Option<Transmission>,
+    rx: VecDeque<Result<u8, UartError>>,
+    devices: Vec<Device>,
+    shorted: bool,
+}
+
+impl Bus {
+    fn ticks_per_byte(&self) -> usize {
+        match self.baud_rate {
+            9600 => 12,
+            115_200 => 1,
+            _ => panic!("unsupported baud rate {}", self.baud_rate),
+        }
+    }
+
+    fn make_progress(&mut self) {
+        let mut transmission = match self.tx.take() {
+            Some(transmission) => transmission,
+            None => return,
+        };
+        transmission.ticks_left -= 1;
+        if transmission.ticks_left > 0 {
+            self.tx = Some(transmission);
+            return;
+        }
+
+        let echo = if self.shorted {
+            // The line never goes back high for the stop bit
+            Err(UartError::FramingError)
+        } else if self.baud_rate == 9600 {
+            Ok(self.reset_pulse(transmission.byte))
+        } else {
+            Ok(self.time_slot(transmission.byte))
+        };
+        println!("onewire: {:02x} -> {:?}", transmission.byte, echo);
+        self.rx.push_back(echo);
+    }
+
+    /// Returns the byte read back from the bus after sending `byte` at 9600 baud
+    fn reset_pulse(&mut self, byte: u8) -> u8 {
+        for device in &mut self.devices {
+            device.reset();
+        }
+        if self.devices.is_empty() {
+            byte
+        } else {
+            // The presence pulse pulls the bus low while the upper bits are sent
+            byte & 0xe0
+        }
+    }
+
+    /// Returns the byte read back from the bus after sending `byte` at 115200 baud
+    ///
+    /// The master releases the bus early in the time slot if the least significant bit is set,
+    /// otherwise it keeps the bus low and writes a 0.
+    fn time_slot(&mut self, byte: u8) -> u8 {
+        let level = byte & 1 != 0 && self.devices.iter().all(|device| device.drive());
+        for device in &mut self.devices {
+            device.slot(level);
+        }
+        if level {
+            byte
+        } else {
+            byte & 0xf0
+        }
+    }
+
+    fn pending(&self, event: Event) -> bool {
+        match event {
+            Event::RxNotEmpty => !self.rx.is_empty(),
+            Event::TxSpace | Event::TxIdle => self.tx.is_none(),
+        }
+    }
+}
+
+struct Shared {
+    bus: Mutex<Bus>,
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
+}
+
+impl Shared {
+    fn bus(&self) -> MutexGuard<'_, Bus> {
+        self.bus.lock().unwrap()
+    }
+
+    fn slot(&self, event: Event) -> &AtomicWaker {
+        match event {
+            Event::RxNotEmpty => &self.rx_not_empty,
+            Event::TxSpace => &self.tx_space,
+            Event::TxIdle => &self.tx_idle,
+        }
+    }
+}
+
+/// Simulated UART with TX and RX tied to a 1-Wire bus of DS18B20-style temperature sensors
+///
+/// A byte takes 12 ticks at 9600 baud and a tick at 115200 baud, other baud rates
+/// are not supported. Every byte sent is received back as the bus level made it.
+pub struct OneWireUart {
+    shared: Arc<Shared>,
+}
+
+impl OneWireUart {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                bus: Mutex::new(Bus {
+                    baud_rate: 115_200,
+                    tx: None,
+                    rx: VecDeque::new(),
+                    devices: Vec::new(),
+                    shorted: false,
+                }),
+                rx_not_empty: AtomicWaker::new(),
+                tx_space: AtomicWaker::new(),
+                tx_idle: AtomicWaker::new(),
+            })
+        }
+    }
+
+    /// Connects a sensor with a valid ROM code made of `family` and `serial_number`
+    pub fn add_device(&self, family: u8, serial_number: u64) -> Rom {
+        let raw = (serial_number & 0xffff_ffff_ffff) << 8 | family as u64;
+        let crc = crc8(&raw.to_le_bytes()[..7]);
+        let rom = Rom::from_raw(raw | (crc as u64) << 56);
+        self.add_device_with_rom(rom);
+        rom
+    }
+
+    /// Connects a sensor with an arbitrary ROM code
+    pub fn add_device_with_rom(&self, rom: Rom) {
+        self.shared.bus().devices.push(Device::new(rom));
+    }
+
+    /// Sets the temperature, in 1/16 °C, that the sensor measures on the next conversion
+    pub fn set_temperature(&self, rom: Rom, temperature: i16) {
+        let mut bus = self.shared.bus();
+        let device = bus.devices.iter_mut().find(|device| device.rom == rom).expect("no such device");
+        device.temperature = temperature;
+    }
+
+    /// Shorts the bus to ground, so that no byte is received intact
+    pub fn short_circuit(&self, shorted: bool) {
+        self.shared.bus().shorted = shorted;
+    }
+
+    pub fn irq(&self) -> OneWireUartIrq {
+        OneWireUartIrq {
+            shared: self.shared.clone()
+        }
+    }
+}
+
+impl embedded_hal::serial::Write<u8> for OneWireUart {
+    type Error = UartError;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        let mut bus = self.shared.bus();
+        if bus.tx.is_some() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let ticks_left = bus.ticks_per_byte();
+        bus.tx = Some(Transmission {
+            byte,
+            ticks_left,
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.shared.bus().tx.is_some() {
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl embedded_hal::serial::Read<u8> for OneWireUart {
+    type Error = UartError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.shared.bus().rx.pop_front() {
+            Some(Ok(byte)) => Ok(byte),
+            Some(Err(e)) => Err(nb::Error::Other(e)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl SetBaudRate for OneWireUart {
+    fn set_baud_rate(&mut self, baud_rate: u32) {
+        let mut bus = self.shared.bus();
+        assert!(bus.tx.is_none(), "baud rate changed while transmitting");
+        bus.baud_rate = baud_rate;
+    }
+}
+
+impl RegisterWaker for OneWireUart {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = self.shared.slot(event);
+        slot.register(waker);
+        if self.shared.bus().pending(event) {
+            slot.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::serial::read::Default for OneWireUart {}
+
+impl embedded_async_sandbox::serial::write::Default for OneWireUart {}
+
+impl embedded_async_sandbox::onewire::uart::Default for OneWireUart {}
+
+pub struct OneWireUartIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for OneWireUartIrq {
+    fn tick(&self) {
+        let mut bus = self.shared.bus();
+        bus.make_progress();
+        for &event in &[Event::RxNotEmpty, Event::TxSpace, Event::TxIdle] {
+            if bus.pending(event) {
+                self.shared.slot(event).wake();
+            }
+        }
+    }
+}
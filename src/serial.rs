@@ -1,6 +1,26 @@
-#[derive(Copy, Clone, Debug)]
+use crate::dma::{RxRequest, TxRequest};
+use crate::irq::Tick;
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum UartError {
-    InvalidData
+    InvalidData,
+    FramingError,
+    Overrun,
+}
+
+/// Activity on the simulated RX line
+#[derive(Copy, Clone, Debug)]
+pub enum RxEvent {
+    /// A byte arrives, taking the same number of ticks as sending one
+    Byte(u8),
+    /// A malformed frame arrives and is reported as `UartError::FramingError`
+    FramingError,
+    /// The line stays idle for the given number of ticks
+    Idle(usize),
 }
 
 pub struct Uart {
@@ -8,6 +28,12 @@ pub struct Uart {
     fifo_size: usize,
     error: bool,
     ticks_to_send: usize,
+    tx_log: Vec<u8>,
+    rx_fifo: [Result<u8, UartError>; 4],
+    rx_fifo_size: usize,
+    rx_overrun: bool,
+    rx_script: VecDeque<RxEvent>,
+    ticks_to_receive: usize,
 }
 
 impl Uart {
@@ -17,6 +43,26 @@ impl Uart {
             fifo_size: 0,
             error: false,
             ticks_to_send: 0,
+            tx_log: Vec::new(),
+            rx_fifo: [Ok(0); 4],
+            rx_fifo_size: 0,
+            rx_overrun: false,
+            rx_script: VecDeque::new(),
+            ticks_to_receive: 0,
+        }
+    }
+
+    /// Appends `events` to the input played back on the RX line
+    ///
+    /// Bytes that arrive while the RX FIFO is full are lost and reported as `UartError::Overrun`.
+    pub fn script_rx<I: IntoIterator<Item=RxEvent>>(&mut self, events: I) {
+        let was_empty = self.rx_script.is_empty();
+        self.rx_script.extend(events);
+        if was_empty {
+            if let Some(first) = self.rx_script.front() {
+                // start receiving
+                self.ticks_to_receive = Self::rx_event_ticks(first);
+            }
         }
     }
 
@@ -28,6 +74,10 @@ impl Uart {
         self.fifo_size < self.fifo.len()
     }
 
+    fn has_rx_data(&self) -> bool {
+        self.rx_fifo_size > 0 || self.rx_overrun
+    }
+
     fn write_byte(&mut self, byte: u8) {
         if self.fifo_size < self.fifo.len() {
             self.fifo[self.fifo_size] = byte;
@@ -39,7 +89,43 @@ impl Uart {
         }
     }
 
+    /// Queues `byte` for sending, returns false if the TX FIFO is full
+    fn try_write_byte(&mut self, byte: u8) -> Result<bool, UartError> {
+        if self.error {
+            self.error = false;
+            return Err(UartError::InvalidData);
+        }
+
+        if self.has_space() {
+            self.write_byte(byte);
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<Result<u8, UartError>> {
+        if self.rx_overrun {
+            self.rx_overrun = false;
+            return Some(Err(UartError::Overrun));
+        }
+
+        if self.rx_fifo_size > 0 {
+            let byte = self.rx_fifo[0];
+            self.rx_fifo.rotate_left(1);
+            self.rx_fifo_size -= 1;
+            Some(byte)
+        } else {
+            None
+        }
+    }
+
     fn make_progress(&mut self) {
+        self.make_tx_progress();
+        self.make_rx_progress();
+    }
+
+    fn make_tx_progress(&mut self) {
         if self.fifo_size > 0 {
             if self.ticks_to_send == 0 {
                 let byte = self.fifo[0];
@@ -47,6 +133,7 @@ impl Uart {
                 self.fifo_size -= 1;
 
                 println!("byte! {:02x}", byte);
+                self.tx_log.push(byte);
                 if byte == 0xff {
                     self.error = true;
                 }
@@ -60,19 +147,98 @@ impl Uart {
             }
         }
     }
+
+    fn make_rx_progress(&mut self) {
+        if let Some(event) = self.rx_script.front().copied() {
+            if self.ticks_to_receive == 0 {
+                self.rx_script.pop_front();
+
+                let received = match event {
+                    RxEvent::Byte(byte) => Some(Ok(byte)),
+                    RxEvent::FramingError => Some(Err(UartError::FramingError)),
+                    RxEvent::Idle(_) => None,
+                };
+                if let Some(received) = received {
+                    println!("rx byte! {:?}", received);
+                    if self.rx_fifo_size < self.rx_fifo.len() {
+                        self.rx_fifo[self.rx_fifo_size] = received;
+                        self.rx_fifo_size += 1;
+                    } else {
+                        self.rx_overrun = true;
+                    }
+                }
+
+                if let Some(next) = self.rx_script.front() {
+                    // start receiving next event
+                    self.ticks_to_receive = Self::rx_event_ticks(next);
+                }
+            } else {
+                self.ticks_to_receive -= 1;
+            }
+        }
+    }
+
+    fn rx_event_ticks(event: &RxEvent) -> usize {
+        match event {
+            RxEvent::Byte(_) | RxEvent::FramingError => 3,
+            RxEvent::Idle(ticks) => *ticks,
+        }
+    }
+}
+
+struct Shared {
+    uart: Mutex<Uart>,
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
 }
 
 pub struct Serial {
-    uart: Uart,
+    shared: Arc<Shared>,
 }
 
 impl Serial {
     pub fn new(uart: Uart) -> Serial {
         Self {
-            uart
+            shared: Arc::new(Shared {
+                uart: Mutex::new(uart),
+                rx_not_empty: AtomicWaker::new(),
+                tx_space: AtomicWaker::new(),
+                tx_idle: AtomicWaker::new(),
+            })
         }
     }
 
+    /// Returns the clock and interrupt input of the underlying `Uart`
+    pub fn irq(&self) -> SerialIrq {
+        SerialIrq {
+            shared: self.shared.clone()
+        }
+    }
+
+    /// Returns the DMA request line of the transmitter
+    pub fn dma_tx(&self) -> UartTxRequest {
+        UartTxRequest {
+            shared: self.shared.clone()
+        }
+    }
+
+    /// Returns the DMA request line of the receiver
+    pub fn dma_rx(&self) -> UartRxRequest {
+        UartRxRequest {
+            shared: self.shared.clone()
+        }
+    }
+
+    /// Returns all bytes shifted out on the TX line so far
+    pub fn transmitted(&self) -> Vec<u8> {
+        self.uart().tx_log.clone()
+    }
+
+    fn uart(&self) -> MutexGuard<'_, Uart> {
+        self.shared.uart.lock().unwrap()
+    }
+
     // pub fn write_byte_nowait(&mut self, cx: &mut Context<'_>, byte: u8) -> Poll<()> {
     //     self.uart.make_progress();
     //
@@ -114,16 +280,16 @@ impl embedded_hal::serial::Write<u8> for Serial {
     type Error = UartError;
 
     fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        self.uart.make_progress();
+        let mut uart = self.uart();
 
-        if self.uart.error {
-            self.uart.error = false;
+        if uart.error {
+            uart.error = false;
             return Err(nb::Error::Other(UartError::InvalidData));
         }
 
-        if self.uart.has_space() {
+        if uart.has_space() {
             println!("write_byte({:02x}) - Ok", byte);
-            self.uart.write_byte(byte);
+            uart.write_byte(byte);
             Ok(())
         } else {
             println!("write_byte({:02x}) - WouldBlock", byte);
@@ -132,14 +298,14 @@ impl embedded_hal::serial::Write<u8> for Serial {
     }
 
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
-        self.uart.make_progress();
+        let mut uart = self.uart();
 
-        if self.uart.error {
-            self.uart.error = false;
+        if uart.error {
+            uart.error = false;
             return Err(nb::Error::Other(UartError::InvalidData));
         }
 
-        if self.uart.is_idle() {
+        if uart.is_idle() {
             println!("flush() - Ok");
             Ok(())
         } else {
@@ -149,8 +315,100 @@ impl embedded_hal::serial::Write<u8> for Serial {
     }
 }
 
+impl embedded_hal::serial::Read<u8> for Serial {
+    type Error = UartError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.uart().read_byte() {
+            Some(Ok(byte)) => {
+                println!("read() - Ok({:02x})", byte);
+                Ok(byte)
+            },
+            Some(Err(e)) => {
+                println!("read() - {:?}", e);
+                Err(nb::Error::Other(e))
+            },
+            None => {
+                println!("read() - WouldBlock");
+                Err(nb::Error::WouldBlock)
+            }
+        }
+    }
+}
+
+impl RegisterWaker for Serial {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = match event {
+            Event::RxNotEmpty => &self.shared.rx_not_empty,
+            Event::TxSpace => &self.shared.tx_space,
+            Event::TxIdle => &self.shared.tx_idle,
+        };
+        slot.register(waker);
+        if self.shared.pending(event) {
+            slot.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::serial::read::Default for Serial {}
+
 impl embedded_async_sandbox::serial::write::Default for Serial {}
 
+impl Shared {
+    fn pending(&self, event: Event) -> bool {
+        let uart = self.uart.lock().unwrap();
+        match event {
+            Event::RxNotEmpty => uart.has_rx_data(),
+            Event::TxSpace => uart.has_space() || uart.error,
+            Event::TxIdle => uart.is_idle() || uart.error,
+        }
+    }
+}
+
+pub struct SerialIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for SerialIrq {
+    fn tick(&self) {
+        self.shared.uart.lock().unwrap().make_progress();
+
+        if self.shared.pending(Event::RxNotEmpty) {
+            self.shared.rx_not_empty.wake();
+        }
+        if self.shared.pending(Event::TxSpace) {
+            self.shared.tx_space.wake();
+        }
+        if self.shared.pending(Event::TxIdle) {
+            self.shared.tx_idle.wake();
+        }
+    }
+}
+
+pub struct UartTxRequest {
+    shared: Arc<Shared>,
+}
+
+impl TxRequest for UartTxRequest {
+    type Error = UartError;
+
+    fn push(&self, byte: u8) -> Result<bool, Self::Error> {
+        self.shared.uart.lock().unwrap().try_write_byte(byte)
+    }
+}
+
+pub struct UartRxRequest {
+    shared: Arc<Shared>,
+}
+
+impl RxRequest for UartRxRequest {
+    type Error = UartError;
+
+    fn pop(&self) -> Option<Result<u8, Self::Error>> {
+        self.shared.uart.lock().unwrap().read_byte()
+    }
+}
+
 // impl AsyncWrite for Serial {
 //     type Error = UartError;
 //     type WriteByteFuture<'t> = SerialWriteByteFuture;
@@ -1,27 +1,82 @@
+use crate::dma::{RxRequest, TxRequest};
+use crate::gpio::Pin;
+use crate::irq::Tick;
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
 #[derive(Copy, Clone, Debug)]
 pub enum SpiError {
     InvalidData,
     RxFifoOverflow,
 }
 
-pub struct DummySpi {
+/// Byte shifted out on the bus
+#[derive(Clone, Debug)]
+pub struct Shifted {
+    /// Indices of the chip select pins driven low while the byte was shifted
+    pub selected: Vec<usize>,
+    pub byte: u8,
+}
+
+struct Spi {
     tx_fifo: [u8; 4],
     tx_fifo_size: usize,
     rx_fifo: [u8; 4],
     rx_fifo_size: usize,
     error_fifo: bool,
     ticks_to_send: usize,
+    chip_selects: Vec<Pin>,
+    shifted: Vec<Shifted>,
 }
 
-impl DummySpi {
-    pub fn new() -> Self {
+impl Spi {
+    fn new() -> Self {
         Self {
             tx_fifo: [0; 4],
             tx_fifo_size: 0,
             rx_fifo: [0; 4],
             rx_fifo_size: 0,
             error_fifo: false,
-            ticks_to_send: 0
+            ticks_to_send: 0,
+            chip_selects: Vec::new(),
+            shifted: Vec::new(),
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<Result<u8, SpiError>> {
+        if self.error_fifo {
+            self.error_fifo = false;
+            return Some(Err(SpiError::RxFifoOverflow));
+        }
+
+        if self.rx_fifo_size > 0 {
+            let byte = self.rx_fifo[0];
+            self.rx_fifo[0] = 0;
+            self.rx_fifo.rotate_left(1);
+            self.rx_fifo_size -= 1;
+
+            if byte == 0x42 {
+                return Some(Err(SpiError::InvalidData));
+            }
+            Some(Ok(byte))
+        } else {
+            None
+        }
+    }
+
+    /// Queues `byte` for sending, returns false if the TX FIFO is full
+    fn send_byte(&mut self, byte: u8) -> bool {
+        if self.tx_fifo_size < self.tx_fifo.len() {
+            self.tx_fifo[self.tx_fifo_size] = byte;
+            if self.tx_fifo_size == 0 {
+                // start sending
+                self.ticks_to_send = 3;
+            }
+            self.tx_fifo_size += 1;
+            true
+        } else {
+            false
         }
     }
 
@@ -33,6 +88,13 @@ impl DummySpi {
                 self.tx_fifo.rotate_left(1);
                 self.tx_fifo_size -= 1;
 
+                let selected = self.chip_selects.iter()
+                    .enumerate()
+                    .filter(|(_, pin)| pin.is_low())
+                    .map(|(i, _)| i)
+                    .collect();
+                self.shifted.push(Shifted { selected, byte });
+
                 let byte = !byte;
 
                 if self.rx_fifo_size < self.rx_fifo.len() {
@@ -53,48 +115,111 @@ impl DummySpi {
     }
 }
 
-impl embedded_hal::spi::FullDuplex<u8> for DummySpi {
-    type Error = SpiError;
+struct Shared {
+    spi: Mutex<Spi>,
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
+}
 
-    fn read(&mut self) -> nb::Result<u8, Self::Error> {
-        self.make_progress();
+impl Shared {
+    fn pending(&self, event: Event) -> bool {
+        let spi = self.spi.lock().unwrap();
+        match event {
+            Event::RxNotEmpty => spi.rx_fifo_size > 0 || spi.error_fifo,
+            Event::TxSpace => spi.tx_fifo_size < spi.tx_fifo.len(),
+            Event::TxIdle => spi.tx_fifo_size == 0,
+        }
+    }
+}
 
-        if self.error_fifo {
-            self.error_fifo = false;
-            println!("read(): RxFifoOverflow");
-            return Err(nb::Error::Other(SpiError::RxFifoOverflow));
+pub struct DummySpi {
+    shared: Arc<Shared>,
+    transfer_depth: usize,
+}
+
+impl DummySpi {
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                spi: Mutex::new(Spi::new()),
+                rx_not_empty: AtomicWaker::new(),
+                tx_space: AtomicWaker::new(),
+                tx_idle: AtomicWaker::new(),
+            }),
+            transfer_depth: 1,
         }
+    }
 
-        if self.rx_fifo_size > 0 {
-            let byte = self.rx_fifo[0];
-            self.rx_fifo[0] = 0;
-            self.rx_fifo.rotate_left(1);
-            self.rx_fifo_size -= 1;
+    /// Lets transfers send up to `depth` bytes ahead of the received ones
+    ///
+    /// The depth is limited by the size of the RX FIFO, which holds 4 bytes.
+    pub fn set_transfer_depth(&mut self, depth: usize) {
+        assert!(depth > 0 && depth <= 4);
+        self.transfer_depth = depth;
+    }
 
-            if byte == 0x42 {
-                println!("read(): InvalidData");
-                return Err(nb::Error::Other(SpiError::InvalidData));
-            }
+    /// Returns the clock and interrupt input of the simulated peripheral
+    pub fn irq(&self) -> DummySpiIrq {
+        DummySpiIrq {
+            shared: self.shared.clone()
+        }
+    }
 
-            println!("read(): Ok({:02x})", byte);
-            Ok(byte)
-        } else {
-            println!("read(): WouldBlock");
-            Err(nb::Error::WouldBlock)
+    /// Lets the bus monitor `pin` as a chip select, returning its index
+    pub fn attach_cs(&self, pin: &Pin) -> usize {
+        let mut spi = self.spi();
+        spi.chip_selects.push(pin.clone());
+        spi.chip_selects.len() - 1
+    }
+
+    /// Returns the bytes shifted out so far, with the chips selected at that time
+    pub fn shifted(&self) -> Vec<Shifted> {
+        self.spi().shifted.clone()
+    }
+
+    /// Returns the DMA request line of the transmitter
+    pub fn dma_tx(&self) -> SpiTxRequest {
+        SpiTxRequest {
+            shared: self.shared.clone()
         }
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        if self.tx_fifo_size < self.tx_fifo.len() {
-            self.tx_fifo[self.tx_fifo_size] = byte;
-            if self.tx_fifo_size == 0 {
-                // start sending
-                self.ticks_to_send = 3;
+    /// Returns the DMA request line of the receiver
+    pub fn dma_rx(&self) -> SpiRxRequest {
+        SpiRxRequest {
+            shared: self.shared.clone()
+        }
+    }
+
+    fn spi(&self) -> MutexGuard<'_, Spi> {
+        self.shared.spi.lock().unwrap()
+    }
+}
+
+impl embedded_hal::spi::FullDuplex<u8> for DummySpi {
+    type Error = SpiError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.spi().read_byte() {
+            Some(Ok(byte)) => {
+                println!("read(): Ok({:02x})", byte);
+                Ok(byte)
+            },
+            Some(Err(e)) => {
+                println!("read(): {:?}", e);
+                Err(nb::Error::Other(e))
+            },
+            None => {
+                println!("read(): WouldBlock");
+                Err(nb::Error::WouldBlock)
             }
-            self.tx_fifo_size += 1;
+        }
+    }
 
+    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        if self.spi().send_byte(byte) {
             println!("send({:02x}): Ok", byte);
-
             Ok(())
         } else {
             println!("send({:02x}): WouldBlock", byte);
@@ -103,4 +228,72 @@ impl embedded_hal::spi::FullDuplex<u8> for DummySpi {
     }
 }
 
-impl embedded_async_sandbox::spi::transfer::Default for DummySpi {}
+impl RegisterWaker for DummySpi {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = match event {
+            Event::RxNotEmpty => &self.shared.rx_not_empty,
+            Event::TxSpace => &self.shared.tx_space,
+            Event::TxIdle => &self.shared.tx_idle,
+        };
+        slot.register(waker);
+        if self.shared.pending(event) {
+            slot.wake();
+        }
+    }
+}
+
+impl embedded_async_sandbox::spi::FillByte for DummySpi {}
+
+impl embedded_async_sandbox::spi::transfer::Default for DummySpi {
+    fn transfer_depth(&self) -> usize {
+        self.transfer_depth
+    }
+}
+
+impl embedded_async_sandbox::spi::write::Default for DummySpi {}
+
+impl embedded_async_sandbox::spi::read::Default for DummySpi {}
+
+pub struct DummySpiIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for DummySpiIrq {
+    fn tick(&self) {
+        self.shared.spi.lock().unwrap().make_progress();
+
+        if self.shared.pending(Event::RxNotEmpty) {
+            self.shared.rx_not_empty.wake();
+        }
+        if self.shared.pending(Event::TxSpace) {
+            self.shared.tx_space.wake();
+        }
+        if self.shared.pending(Event::TxIdle) {
+            self.shared.tx_idle.wake();
+        }
+    }
+}
+
+pub struct SpiTxRequest {
+    shared: Arc<Shared>,
+}
+
+impl TxRequest for SpiTxRequest {
+    type Error = SpiError;
+
+    fn push(&self, byte: u8) -> Result<bool, Self::Error> {
+        Ok(self.shared.spi.lock().unwrap().send_byte(byte))
+    }
+}
+
+pub struct SpiRxRequest {
+    shared: Arc<Shared>,
+}
+
+impl RxRequest for SpiRxRequest {
+    type Error = SpiError;
+
+    fn pop(&self) -> Option<Result<u8, Self::Error>> {
+        self.shared.spi.lock().unwrap().read_byte()
+    }
+}
@@ -0,0 +1,264 @@
+use crate::irq::Tick;
+use embedded_async_sandbox::usb::{Endpoint, EndpointIn, EndpointOut};
+use embedded_async_sandbox::waker::{AtomicWaker, Event, RegisterWaker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::Waker;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UsbError {
+    /// The packet is longer than the maximum packet size or does not fit into the buffer
+    BufferOverflow,
+    /// The device is not connected to the host
+    Disconnected,
+}
+
+struct Host {
+    max_packet_size: usize,
+    connected: bool,
+    /// Packet waiting in the IN endpoint for the host to read it
+    in_buffer: Option<Vec<u8>>,
+    /// Packet waiting in the OUT endpoint for the device to read it
+    out_buffer: Option<Vec<u8>>,
+    /// Packets the host is going to send
+    queue: VecDeque<Vec<u8>>,
+    received: Vec<Vec<u8>>,
+}
+
+impl Host {
+    fn make_progress(&mut self) {
+        if !self.connected {
+            return;
+        }
+        if self.out_buffer.is_none() {
+            self.out_buffer = self.queue.pop_front();
+        }
+        if let Some(packet) = self.in_buffer.take() {
+            println!("host: received {:?}", packet);
+            self.queue.push_back(packet.clone());
+            self.received.push(packet);
+        }
+    }
+
+    fn pending(&self, event: Event) -> bool {
+        if !self.connected {
+            return true;
+        }
+        match event {
+            Event::RxNotEmpty => self.out_buffer.is_some(),
+            Event::TxSpace | Event::TxIdle => self.in_buffer.is_none(),
+        }
+    }
+}
+
+struct Wakers {
+    rx_not_empty: AtomicWaker,
+    tx_space: AtomicWaker,
+    tx_idle: AtomicWaker,
+}
+
+impl Wakers {
+    fn slot(&self, event: Event) -> &AtomicWaker {
+        match event {
+            Event::RxNotEmpty => &self.rx_not_empty,
+            Event::TxSpace => &self.tx_space,
+            Event::TxIdle => &self.tx_idle,
+        }
+    }
+}
+
+struct Shared {
+    host: Mutex<Host>,
+    wakers: Wakers,
+}
+
+impl Shared {
+    fn host(&self) -> MutexGuard<'_, Host> {
+        self.host.lock().unwrap()
+    }
+
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        let slot = self.wakers.slot(event);
+        slot.register(waker);
+        if self.host().pending(event) {
+            slot.wake();
+        }
+    }
+}
+
+/// Simulated USB host echoing everything a device sends on a pair of bulk endpoints
+///
+/// Every tick the host reads the packet waiting in the IN endpoint, if any, and queues it
+/// to be sent back. Queued packets go to the OUT endpoint one per tick, as soon as
+/// the device has taken the previous one out of the endpoint buffer.
+/// Zero-length packets are echoed like any other packet.
+pub struct UsbLoopback {
+    shared: Arc<Shared>,
+}
+
+impl UsbLoopback {
+    pub fn new(max_packet_size: usize) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                host: Mutex::new(Host {
+                    max_packet_size,
+                    connected: true,
+                    in_buffer: None,
+                    out_buffer: None,
+                    queue: VecDeque::new(),
+                    received: Vec::new(),
+                }),
+                wakers: Wakers {
+                    rx_not_empty: AtomicWaker::new(),
+                    tx_space: AtomicWaker::new(),
+                    tx_idle: AtomicWaker::new(),
+                },
+            })
+        }
+    }
+
+    pub fn endpoint_in(&self) -> UsbEndpointIn {
+        UsbEndpointIn {
+            shared: self.shared.clone()
+        }
+    }
+
+    pub fn endpoint_out(&self) -> UsbEndpointOut {
+        UsbEndpointOut {
+            shared: self.shared.clone()
+        }
+    }
+
+    /// Queues a packet to be sent to the OUT endpoint, in addition to the echoed ones
+    pub fn send_packet(&self, data: &[u8]) {
+        let mut host = self.shared.host();
+        assert!(data.len() <= host.max_packet_size, "packet is longer than the maximum packet size");
+        host.queue.push_back(data.to_vec());
+    }
+
+    /// Returns the packets read from the IN endpoint so far
+    pub fn received(&self) -> Vec<Vec<u8>> {
+        self.shared.host().received.clone()
+    }
+
+    /// Unplugs the device, dropping all packets in flight
+    pub fn disconnect(&self) {
+        let mut host = self.shared.host();
+        host.connected = false;
+        host.in_buffer = None;
+        host.out_buffer = None;
+        host.queue.clear();
+    }
+
+    pub fn irq(&self) -> UsbLoopbackIrq {
+        UsbLoopbackIrq {
+            shared: self.shared.clone()
+        }
+    }
+}
+
+/// IN endpoint of a `UsbLoopback`
+pub struct UsbEndpointIn {
+    shared: Arc<Shared>,
+}
+
+impl Endpoint for UsbEndpointIn {
+    fn max_packet_size(&self) -> usize {
+        self.shared.host().max_packet_size
+    }
+}
+
+impl EndpointIn for UsbEndpointIn {
+    type Error = UsbError;
+
+    fn write_packet(&mut self, data: &[u8]) -> nb::Result<(), Self::Error> {
+        let mut host = self.shared.host();
+        if !host.connected {
+            return Err(nb::Error::Other(UsbError::Disconnected));
+        }
+        if data.len() > host.max_packet_size {
+            return Err(nb::Error::Other(UsbError::BufferOverflow));
+        }
+        if host.in_buffer.is_none() {
+            println!("write_packet({:?}) - Ok", data);
+            host.in_buffer = Some(data.to_vec());
+            Ok(())
+        } else {
+            println!("write_packet({:?}) - WouldBlock", data);
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        let host = self.shared.host();
+        if !host.connected {
+            Err(nb::Error::Other(UsbError::Disconnected))
+        } else if host.in_buffer.is_none() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl RegisterWaker for UsbEndpointIn {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        self.shared.register_waker(event, waker);
+    }
+}
+
+impl embedded_async_sandbox::usb::endpoint_in::Default for UsbEndpointIn {}
+
+/// OUT endpoint of a `UsbLoopback`
+pub struct UsbEndpointOut {
+    shared: Arc<Shared>,
+}
+
+impl Endpoint for UsbEndpointOut {
+    fn max_packet_size(&self) -> usize {
+        self.shared.host().max_packet_size
+    }
+}
+
+impl EndpointOut for UsbEndpointOut {
+    type Error = UsbError;
+
+    fn read_packet(&mut self, buf: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        let mut host = self.shared.host();
+        if !host.connected {
+            return Err(nb::Error::Other(UsbError::Disconnected));
+        }
+        match host.out_buffer.take() {
+            Some(packet) if packet.len() > buf.len() => Err(nb::Error::Other(UsbError::BufferOverflow)),
+            Some(packet) => {
+                buf[..packet.len()].copy_from_slice(&packet);
+                Ok(packet.len())
+            },
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl RegisterWaker for UsbEndpointOut {
+    fn register_waker(&self, event: Event, waker: &Waker) {
+        self.shared.register_waker(event, waker);
+    }
+}
+
+impl embedded_async_sandbox::usb::endpoint_out::Default for UsbEndpointOut {}
+
+pub struct UsbLoopbackIrq {
+    shared: Arc<Shared>,
+}
+
+impl Tick for UsbLoopbackIrq {
+    fn tick(&self) {
+        let mut host = self.shared.host();
+        host.make_progress();
+        for &event in &[Event::RxNotEmpty, Event::TxSpace, Event::TxIdle] {
+            if host.pending(event) {
+                self.shared.wakers.slot(event).wake();
+            }
+        }
+    }
+}